@@ -28,6 +28,20 @@ impl fmt::Display for LexicalError {
     }
 }
 
+impl LexicalError {
+    /// Stable identifier of error, used in machine readable diagnostics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexicalError::IsVeryBad => "E0001",
+            LexicalError::OpenComment(_) => "E0002",
+            LexicalError::UnexpectedCharacter(_, _) => "E0003",
+            LexicalError::OpenStringLiteral(_) => "E0004",
+            LexicalError::LiteralIntOverflow(_) => "E0005",
+            LexicalError::LiteralFloatOverflow(_) => "E0006",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SemanticError {
     UndefinedIdentifier(Sp, String),
@@ -44,6 +58,16 @@ pub enum SemanticError {
     ArgumentsMismatch(String, Sp, Vec<Vec<String>>),
     // type name, variable span, field name
     NoField(String, Sp, String),
+    FunctionRedefinition(String, Sp, Sp),
+    RecursiveFunction(String, Sp),
+    // gpu functions are inlined, so return has to be their last statement
+    MisplacedReturn(Sp),
+    UnexpectedTuple(Sp),
+    UnknownEdgeMode(String, Sp),
+    // factor of scale depends on pipeline inputs
+    NonConstantScale(Sp),
+    // literal factor of scale is not in (0, 1]
+    ScaleOutOfRange(Sp),
 }
 
 #[derive(Debug)]
@@ -51,3 +75,72 @@ pub enum SemanticWarning {
     NotUsedArgument(Sp, String),
     UnusedVariable(Sp, String),
 }
+
+impl SemanticError {
+    /// Stable identifier of error, used in machine readable diagnostics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SemanticError::UndefinedIdentifier(_, _) => "E0201",
+            SemanticError::UnusedArgument => "E0202",
+            SemanticError::TypeMismatch(_, _, _) => "E0203",
+            SemanticError::OperationTypeMismatch(_, _, _, _) => "E0204",
+            SemanticError::ArgumentsNumberMismatch => "E0205",
+            SemanticError::NotAssignedReturnVariable(_, _) => "E0206",
+            SemanticError::VariableRedefinition(_, _, _) => "E0207",
+            SemanticError::LogicTypeMismatch(_, _, _) => "E0208",
+            SemanticError::UnknownFunction(_, _) => "E0209",
+            SemanticError::ArgumentsMismatch(_, _, _) => "E0210",
+            SemanticError::NoField(_, _, _) => "E0211",
+            SemanticError::FunctionRedefinition(_, _, _) => "E0212",
+            SemanticError::RecursiveFunction(_, _) => "E0213",
+            SemanticError::MisplacedReturn(_) => "E0214",
+            SemanticError::UnexpectedTuple(_) => "E0215",
+            SemanticError::UnknownEdgeMode(_, _) => "E0216",
+            SemanticError::NonConstantScale(_) => "E0217",
+            SemanticError::ScaleOutOfRange(_) => "E0218",
+        }
+    }
+}
+
+impl SemanticWarning {
+    /// Stable identifier of warning, used in machine readable diagnostics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            SemanticWarning::NotUsedArgument(_, _) => "W0201",
+            SemanticWarning::UnusedVariable(_, _) => "W0202",
+        }
+    }
+}
+
+/// Errors found in IR, when values are known only after constant propagation.
+#[derive(Debug)]
+pub enum IrError {
+    // factor of scale didn't fold into constant
+    NonConstantScale,
+    // loop containing sync has no constant trip count
+    SyncInLoop,
+    // product of nested scale factors is not in (0, 1]
+    ScaleOutOfRange,
+}
+
+impl fmt::Display for IrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let val = match *self {
+            IrError::NonConstantScale => "Scale factor couldn't be computed at compile time",
+            IrError::SyncInLoop => "Loop containing sync couldn't be statically unrolled",
+            IrError::ScaleOutOfRange => "Scale factor has to be greater than zero and at most one",
+        };
+        val.fmt(f)
+    }
+}
+
+impl IrError {
+    /// Stable identifier of error, used in machine readable diagnostics.
+    pub fn code(&self) -> &'static str {
+        match self {
+            IrError::NonConstantScale => "E0301",
+            IrError::SyncInLoop => "E0302",
+            IrError::ScaleOutOfRange => "E0303",
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! Language server for .octo files, talks LSP over stdio.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use serde::de::DeserializeOwned;
+
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{GotoDefinition, HoverRequest, Request as _, SignatureHelpRequest};
+use lsp_types::{
+    DiagnosticSeverity, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    InitializeParams, MarkedString, ParameterInformation, ParameterLabel, Position, PublishDiagnosticsParams,
+    Range, ServerCapabilities, SignatureHelp, SignatureHelpOptions, SignatureHelpParams, SignatureInformation,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use octo::ide::{self, Analysis};
+use octo::{CompileOptions, Location};
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    stderrlog::new().verbosity(1).init().unwrap();
+
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::Full)),
+        hover_provider: Some(true),
+        definition_provider: Some(true),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec!["(".to_owned(), ",".to_owned()]),
+            retrigger_characters: None,
+            work_done_progress_options: Default::default(),
+        }),
+        ..ServerCapabilities::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let _: InitializeParams = serde_json::from_value(params)?;
+
+    let mut server = Server {
+        connection: &connection,
+        documents: HashMap::new(),
+    };
+    server.run()?;
+
+    io_threads.join()?;
+    Result::Ok(())
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    // full text of open documents
+    documents: HashMap<Url, String>,
+}
+
+impl<'a> Server<'a> {
+    fn run(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        for message in &self.connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Result::Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+        Result::Ok(())
+    }
+
+    fn handle_request(&mut self, request: Request) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let id = request.id.clone();
+        match request.method.as_str() {
+            HoverRequest::METHOD => {
+                let params: HoverParams = match self.request_params(&id, request.params)? {
+                    Some(x) => x,
+                    None => return Result::Ok(()),
+                };
+                let position = params.text_document_position_params;
+                let result = self.hover(&position.text_document.uri, position.position);
+                self.respond(id, serde_json::to_value(result)?)
+            }
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = match self.request_params(&id, request.params)? {
+                    Some(x) => x,
+                    None => return Result::Ok(()),
+                };
+                let position = params.text_document_position_params;
+                let result = self.definition(&position.text_document.uri, position.position);
+                self.respond(id, serde_json::to_value(result)?)
+            }
+            SignatureHelpRequest::METHOD => {
+                let params: SignatureHelpParams = match self.request_params(&id, request.params)? {
+                    Some(x) => x,
+                    None => return Result::Ok(()),
+                };
+                let position = params.text_document_position_params;
+                let result = self.signature_help(&position.text_document.uri, position.position);
+                self.respond(id, serde_json::to_value(result)?)
+            }
+            _ => {
+                let response = Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request: {}", request.method),
+                );
+                self.connection.sender.send(Message::Response(response))?;
+                Result::Ok(())
+            }
+        }
+    }
+
+    // malformed request is answered with error, so that server keeps running
+    fn request_params<P: DeserializeOwned>(
+        &self,
+        id: &RequestId,
+        params: serde_json::Value,
+    ) -> Result<Option<P>, Box<dyn Error + Sync + Send>> {
+        match serde_json::from_value(params) {
+            Result::Ok(x) => Result::Ok(Some(x)),
+            Result::Err(err) => {
+                let response = Response::new_err(
+                    id.clone(),
+                    lsp_server::ErrorCode::InvalidParams as i32,
+                    format!("invalid params: {}", err),
+                );
+                self.connection.sender.send(Message::Response(response))?;
+                Result::Ok(None)
+            }
+        }
+    }
+
+    fn respond(&self, id: RequestId, result: serde_json::Value) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let response = Response::new_ok(id, result);
+        self.connection.sender.send(Message::Response(response))?;
+        Result::Ok(())
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), Box<dyn Error + Sync + Send>> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams = match notification_params(&notification) {
+                    Some(x) => x,
+                    None => return Result::Ok(()),
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), params.text_document.text);
+                self.publish_diagnostics(uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams = match notification_params(&notification) {
+                    Some(x) => x,
+                    None => return Result::Ok(()),
+                };
+                let uri = params.text_document.uri;
+                // full synchronization, last change holds whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), change.text);
+                }
+                self.publish_diagnostics(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams = match notification_params(&notification) {
+                    Some(x) => x,
+                    None => return Result::Ok(()),
+                };
+                self.documents.remove(&params.text_document.uri);
+                Result::Ok(())
+            }
+            _ => Result::Ok(()),
+        }
+    }
+
+    fn analyze(&self, uri: &Url) -> Option<(Analysis, String)> {
+        let text = self.documents.get(uri)?;
+        let name = document_name(uri);
+        let options = CompileOptions {
+            name: name.clone(),
+            path: uri.to_file_path().ok().and_then(|x| x.canonicalize().ok()),
+            ..CompileOptions::default()
+        };
+        Some((ide::analyze(text, &options), name))
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let (analysis, name) = match self.analyze(&uri) {
+            Some(x) => x,
+            None => return Result::Ok(()),
+        };
+        let text = &self.documents[&uri];
+
+        let diagnostics = analysis
+            .diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let json = octo::to_json(&analysis.map, diagnostic);
+                // problems found in imported files are shown at the start of document
+                let range = match &json.location {
+                    Some(location) if location.file == name => location_range(text, location),
+                    _ => Range::default(),
+                };
+                let severity = match json.severity {
+                    "warning" => DiagnosticSeverity::Warning,
+                    "note" => DiagnosticSeverity::Information,
+                    "help" => DiagnosticSeverity::Hint,
+                    _ => DiagnosticSeverity::Error,
+                };
+                let mut message = json.message.clone();
+                for label in &json.labels {
+                    if let Some(label_message) = &label.message {
+                        message.push_str(&format!("\n{}", label_message));
+                    }
+                }
+                lsp_types::Diagnostic {
+                    range,
+                    severity: Some(severity),
+                    code: json.code.clone().map(lsp_types::NumberOrString::String),
+                    source: Some("octo".to_owned()),
+                    message,
+                    ..lsp_types::Diagnostic::default()
+                }
+            })
+            .collect();
+
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        self.connection.sender.send(Message::Notification(notification))?;
+        Result::Ok(())
+    }
+
+    fn hover(&self, uri: &Url, position: Position) -> Option<Hover> {
+        let (analysis, name) = self.analyze(uri)?;
+        let text = &self.documents[uri];
+        let reference = analysis.reference_at(&name, position_to_offset(text, position))?;
+        let location = analysis.locate(reference)?;
+        let variable = &reference.variable;
+        Some(Hover {
+            contents: HoverContents::Scalar(MarkedString::String(format!("{}: {}", variable.name, variable.typ))),
+            range: Some(location_range(text, &location)),
+        })
+    }
+
+    fn definition(&self, uri: &Url, position: Position) -> Option<GotoDefinitionResponse> {
+        let (analysis, name) = self.analyze(uri)?;
+        let text = &self.documents[uri];
+        let reference = analysis.reference_at(&name, position_to_offset(text, position))?;
+        let definition = analysis.locate_definition(reference)?;
+
+        // definition may come from imported file, which has to be read to compute its position
+        let (target, range) = if definition.file == name {
+            (uri.clone(), location_range(text, &definition))
+        } else {
+            let target = Url::from_file_path(&definition.file).ok()?;
+            let imported = std::fs::read_to_string(&definition.file).ok()?;
+            (target, location_range(&imported, &definition))
+        };
+        Some(GotoDefinitionResponse::Scalar(lsp_types::Location { uri: target, range }))
+    }
+
+    fn signature_help(&self, uri: &Url, position: Position) -> Option<SignatureHelp> {
+        let text = self.documents.get(uri)?;
+        let (function, active_parameter) = ide::enclosing_call(&text[..position_to_offset(text, position)])?;
+        let signatures: Vec<_> = ide::builtin_signatures(&function)
+            .into_iter()
+            .map(|signature| SignatureInformation {
+                label: signature.label,
+                documentation: None,
+                parameters: Some(
+                    signature
+                        .parameters
+                        .into_iter()
+                        .map(|x| ParameterInformation {
+                            label: ParameterLabel::Simple(x),
+                            documentation: None,
+                        })
+                        .collect(),
+                ),
+            })
+            .collect();
+        if signatures.is_empty() {
+            return None;
+        }
+        Some(SignatureHelp {
+            signatures,
+            active_signature: None,
+            active_parameter: Some(active_parameter as i64),
+        })
+    }
+}
+
+// notifications have no response, malformed ones are only logged
+fn notification_params<P: DeserializeOwned>(notification: &Notification) -> Option<P> {
+    match serde_json::from_value(notification.params.clone()) {
+        Result::Ok(x) => Some(x),
+        Result::Err(err) => {
+            log::warn!("skipping malformed {} notification: {}", notification.method, err);
+            None
+        }
+    }
+}
+
+// same name as in analysis, so spans of this document can be told apart from imported ones
+fn document_name(uri: &Url) -> String {
+    match uri.to_file_path() {
+        Result::Ok(path) => match path.canonicalize() {
+            Result::Ok(path) => path.to_string_lossy().into_owned(),
+            Result::Err(_) => path.to_string_lossy().into_owned(),
+        },
+        Result::Err(()) => uri.to_string(),
+    }
+}
+
+fn location_range(text: &str, location: &Location) -> Range {
+    Range {
+        start: offset_to_position(text, location.byte_start),
+        end: offset_to_position(text, location.byte_end),
+    }
+}
+
+// lsp columns count UTF-16 code units
+fn offset_to_position(text: &str, offset: usize) -> Position {
+    let offset = offset.min(text.len());
+    let line_start = text[..offset].rfind('\n').map(|x| x + 1).unwrap_or(0);
+    let line = text[..line_start].matches('\n').count();
+    let character: usize = text[line_start..offset].chars().map(|x| x.len_utf16()).sum();
+    Position::new(line as u64, character as u64)
+}
+
+fn position_to_offset(text: &str, position: Position) -> usize {
+    let mut offset = 0;
+    for _ in 0..position.line {
+        match text[offset..].find('\n') {
+            Some(x) => offset += x + 1,
+            None => return text.len(),
+        }
+    }
+    let mut character = 0;
+    for (index, c) in text[offset..].char_indices() {
+        if character >= position.character as usize || c == '\n' {
+            return offset + index;
+        }
+        character += c.len_utf16();
+    }
+    text.len()
+}
@@ -0,0 +1,603 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use codespan_reporting::{Diagnostic, Label};
+use errors::IrError;
+use octo_runtime::OctoModule;
+use parser::ast;
+use parser::codespan::CodeMap;
+use parser::codespan_reporting;
+
+use crate::static_analysis::{self, Diagnostics};
+use crate::{semantics, tac_ir, Backend};
+
+/// Options of `compile`.
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    /// Name of compiled file, used in diagnostics and stored in module.
+    pub name: String,
+    /// Backend of additional shader sources, module always contains SPIR-V.
+    pub backend: Backend,
+    /// Path of compiled file, imports are resolved relative to it.
+    /// Without it imports are reported as errors.
+    pub path: Option<PathBuf>,
+    /// Keep textual dumps of AST, IR after every stage and split passes.
+    pub dump_ir: bool,
+    /// Unrolls loops without syncs if their trip count is constant,
+    /// as long as unrolled loop has at most given number of operations.
+    pub unroll_loops: Option<usize>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            name: "pipeline".to_owned(),
+            backend: Backend::Spirv,
+            path: None,
+            dump_ir: false,
+            unroll_loops: None,
+        }
+    }
+}
+
+/// Result of successful compilation.
+pub struct CompiledPipeline {
+    pub module: OctoModule,
+    /// GLSL or HLSL source of every shader, empty for other backends.
+    /// WGSL shaders are stored in module together with their bindings.
+    pub sources: Vec<String>,
+    /// Dumps below are filled only with `dump_ir` option.
+    pub ast: Option<String>,
+    /// Stage name and IR after it, first one is IR before any optimization.
+    pub ir: Vec<(String, String)>,
+    pub passes: Option<String>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Compiles pipeline source without touching disk (apart from imports) or stdout.
+/// On failure all diagnostics are returned, warnings included.
+pub fn compile(source: &str, options: CompileOptions) -> Result<CompiledPipeline, Vec<Diagnostic>> {
+    let mut map = CodeMap::new();
+    compile_with_map(&mut map, source, options)
+}
+
+/// Same as `compile`, but files are added to given map, so diagnostics can be rendered with it.
+pub fn compile_with_map(
+    map: &mut CodeMap,
+    source: &str,
+    options: CompileOptions,
+) -> Result<CompiledPipeline, Vec<Diagnostic>> {
+    let mut dumps = Dumps::default();
+    let (pipeline, warnings) = compile_pipeline(map, source, &options, &mut dumps)?;
+
+    let sources = match options.backend {
+        Backend::Glsl => tac_ir::emit_glsl(&pipeline),
+        Backend::Hlsl => tac_ir::emit_hlsl(&pipeline),
+        _ => vec![],
+    };
+    let wgsl = match options.backend {
+        Backend::Wgsl => tac_ir::emit_wgsl(&pipeline),
+        _ => vec![],
+    };
+
+    let mut module = tac_ir::emit_spirv(&options.name, pipeline);
+    module.wgsl_shaders = wgsl.into_iter().enumerate().collect();
+
+    Result::Ok(CompiledPipeline {
+        module,
+        sources,
+        ast: dumps.ast,
+        ir: dumps.ir,
+        passes: dumps.passes,
+        warnings,
+    })
+}
+
+#[derive(Default)]
+pub(crate) struct Dumps {
+    pub ast: Option<String>,
+    pub ir: Vec<(String, String)>,
+    pub passes: Option<String>,
+}
+
+/// Runs front end and IR passes up to splitting into shaders.
+/// Dumps are filled if options ask for them.
+pub(crate) fn compile_pipeline(
+    map: &mut CodeMap,
+    source: &str,
+    options: &CompileOptions,
+    dumps: &mut Dumps,
+) -> Result<(tac_ir::PipelineDef, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let (valid_ast, warnings) = analyze(map, source, options)?;
+    if options.dump_ir {
+        dumps.ast = Some(format!("{:#?}", valid_ast));
+    }
+
+    let mut dump = |stage: &str, tac: &dyn std::fmt::Debug| {
+        if options.dump_ir {
+            dumps.ir.push((stage.to_owned(), format!("{:?}", tac)));
+        }
+    };
+
+    let tac = tac_ir::emit_ir(valid_ast);
+    dump("before constant propagation", &tac);
+    let tac = tac_ir::propagate_constants(tac);
+    dump("after constant propagation", &tac);
+    let tac = match options.unroll_loops {
+        Some(max_operations) => {
+            let tac = tac_ir::unroll_constant_loops(tac, max_operations);
+            dump("after loop unrolling", &tac);
+            tac
+        }
+        None => tac,
+    };
+    let tac = tac_ir::eliminate_common_subexpressions(tac);
+    dump("after common subexpression elimination", &tac);
+    let tac = tac_ir::remove_unused_operations(tac);
+    dump("after unused operation removal", &tac);
+    let tac = tac_ir::move_sync_operations(tac);
+    dump("after sync movement", &tac);
+    let tac = match tac_ir::unroll_synced_loop(tac) {
+        Result::Ok(x) => x,
+        Result::Err(err) => return Result::Err(with_ir_error(warnings, err)),
+    };
+    dump("after loop reexport", &tac);
+    let tac = tac_ir::fuse_syncs(tac);
+    dump("after sync fusion", &tac);
+    let tac = tac_ir::remove_unused_operations(tac);
+    dump("after unused operation removal", &tac);
+    let tac = tac_ir::simplify_cfg(tac);
+    dump("after control flow simplification", &tac);
+
+    let pipeline = match tac_ir::split_passes(tac) {
+        Result::Ok(x) => x,
+        Result::Err(err) => return Result::Err(with_ir_error(warnings, err)),
+    };
+    if options.dump_ir {
+        dumps.passes = Some(format!("{:#?}", pipeline));
+    }
+    Result::Ok((pipeline, warnings))
+}
+
+// IR doesn't keep spans, so error is reported without location
+fn with_ir_error(warnings: Vec<Diagnostic>, err: IrError) -> Vec<Diagnostic> {
+    let mut diagnostics = warnings;
+    diagnostics.push(Diagnostic::new_error(err.to_string()).with_code(err.code()));
+    diagnostics
+}
+
+/// Loads source with its imports and runs static analysis.
+fn analyze(
+    map: &mut CodeMap,
+    source: &str,
+    options: &CompileOptions,
+) -> Result<(ast::Program, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+    let ast = match load(map, source, options, false, &mut diagnostics) {
+        Result::Ok(x) => x,
+        Result::Err(()) => return Result::Err(diagnostics),
+    };
+
+    if ast.pipeline.is_none() {
+        diagnostics.push(Diagnostic::new_error(format!(
+            "File {} doesn't define a pipeline",
+            options.name
+        )));
+        return Result::Err(diagnostics);
+    }
+
+    let static_analysis_res = static_analysis::analyze(ast);
+    let Diagnostics { errors, warnings } = static_analysis_res.1;
+    let error_happened = errors.len() > 0;
+    diagnostics.extend(warnings.into_iter().map(|x| semantics::WarningWrap::new(x).into()));
+    diagnostics.extend(errors.into_iter().map(|x| semantics::ErrorWrap::new(x).into()));
+    if error_happened {
+        return Result::Err(diagnostics);
+    }
+    match static_analysis_res.0 {
+        None => unreachable!(),
+        Some(x) => Result::Ok((x, diagnostics)),
+    }
+}
+
+/// Parses source and all files imported by it.
+/// With `recover` parts of program that parsed correctly are returned despite errors.
+pub(crate) fn load(
+    map: &mut CodeMap,
+    source: &str,
+    options: &CompileOptions,
+    recover: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<ast::Program, ()> {
+    let mut loaded = HashSet::new();
+    if let Some(path) = &options.path {
+        loaded.insert(path.clone());
+    }
+    load_program(
+        options.name.clone(),
+        source.to_owned(),
+        options.path.as_ref().map(|x| x.as_path()),
+        map,
+        &mut vec![],
+        &mut loaded,
+        recover,
+        diagnostics,
+    )
+}
+
+/// Parses file and all files imported by it.
+/// Constants and functions of imported files go first, so they are defined before use.
+#[allow(clippy::too_many_arguments)]
+fn load_program(
+    name: String,
+    data: String,
+    path: Option<&Path>,
+    map: &mut CodeMap,
+    import_stack: &mut Vec<PathBuf>,
+    loaded: &mut HashSet<PathBuf>,
+    recover: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<ast::Program, ()> {
+    let filemap = map.add_filemap(name.into(), data);
+    // lexer starts counting from 1
+    let offset = filemap.span().start().0 as usize - 1;
+    let mut program = parse_data(filemap.src(), offset, recover, diagnostics)?;
+
+    if let Some(path) = path {
+        import_stack.push(path.to_owned());
+    }
+    let mut constants = vec![];
+    let mut functions = vec![];
+    let mut failed = false;
+    for import in program.imports.drain(..) {
+        let path = match path {
+            Some(x) => x,
+            None => {
+                diagnostics.push(
+                    Diagnostic::new_error("Imports need path of compiled file")
+                        .with_label(Label::new_primary(import.span).with_message("Imported here")),
+                );
+                failed = true;
+                continue;
+            }
+        };
+        let import_path = match path.parent().unwrap().join(&import.val).canonicalize() {
+            Result::Ok(x) => x,
+            Result::Err(_) => {
+                diagnostics.push(
+                    Diagnostic::new_error(format!("Cannot open imported file \"{}\"", import.val))
+                        .with_label(Label::new_primary(import.span).with_message("Imported here")),
+                );
+                failed = true;
+                continue;
+            }
+        };
+        if import_stack.contains(&import_path) {
+            diagnostics.push(
+                Diagnostic::new_error(format!("Import cycle: \"{}\" imports itself", import.val))
+                    .with_label(Label::new_primary(import.span).with_message("Cycle closed here")),
+            );
+            failed = true;
+            continue;
+        }
+        // file was already merged through another import
+        if !loaded.insert(import_path.clone()) {
+            continue;
+        }
+        let data = match std::fs::read_to_string(&import_path) {
+            Result::Ok(x) => x,
+            Result::Err(e) => {
+                diagnostics.push(
+                    Diagnostic::new_error(format!("Cannot read imported file \"{}\": {}", import.val, e))
+                        .with_label(Label::new_primary(import.span).with_message("Imported here")),
+                );
+                failed = true;
+                continue;
+            }
+        };
+        let name = import_path.to_string_lossy().into_owned();
+        match load_program(name, data, Some(&import_path), map, import_stack, loaded, recover, diagnostics) {
+            Result::Ok(imported) => {
+                if let Some(pipeline) = imported.pipeline {
+                    diagnostics.push(
+                        Diagnostic::new_error(format!("Imported file \"{}\" defines a pipeline", import.val))
+                            .with_label(Label::new_primary(pipeline.name.span).with_message("Pipeline defined here"))
+                            .with_label(Label::new_primary(import.span).with_message("Imported here")),
+                    );
+                    failed = true;
+                }
+                constants.extend(imported.constants);
+                functions.extend(imported.functions);
+            }
+            Result::Err(()) => failed = true,
+        }
+    }
+    if path.is_some() {
+        import_stack.pop();
+    }
+
+    if failed && !recover {
+        return Result::Err(());
+    }
+    constants.extend(program.constants);
+    functions.extend(program.functions);
+    program.constants = constants;
+    program.functions = functions;
+    Result::Ok(program)
+}
+
+fn parse_data(
+    data: &str,
+    offset: usize,
+    recover: bool,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<ast::Program, ()> {
+    match parser::parse_with_offset(data, offset, false) {
+        Err(failure_info) => {
+            log::debug!("{:#?}", failure_info.errors);
+            diagnostics.extend(failure_info.errors.iter().map(|err| Diagnostic::from(parser::ErrWrap { err })));
+            match failure_info.program {
+                Some(program) if recover => Result::Ok(program),
+                _ => Result::Err(()),
+            }
+        }
+        Ok(ast) => Result::Ok(ast),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::TempFiles;
+    use octo_runtime::OutputType;
+
+    fn example(name: &str) -> (String, CompileOptions) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../examples")
+            .join(name)
+            .canonicalize()
+            .unwrap();
+        let source = std::fs::read_to_string(&path).unwrap();
+        let options = CompileOptions {
+            name: name.to_owned(),
+            path: Some(path),
+            ..CompileOptions::default()
+        };
+        (source, options)
+    }
+
+    fn compile_example(name: &str) -> CompiledPipeline {
+        let (source, options) = example(name);
+        match compile(&source, options) {
+            Result::Ok(x) => x,
+            Result::Err(diagnostics) => {
+                let messages: Vec<_> = diagnostics.iter().map(|x| x.message.clone()).collect();
+                panic!("{} failed to compile: {:?}", name, messages)
+            }
+        }
+    }
+
+    #[test]
+    fn examples_compile() {
+        for name in &["bloom.octo", "sobel.octo"] {
+            let compiled = compile_example(name);
+            assert!(compiled.module.passes.last().unwrap().output == OutputType::Result);
+        }
+    }
+
+    #[test]
+    fn results_are_written_to_separate_attachments() {
+        let compiled = compile_example("deferred.octo");
+        assert_eq!(compiled.module.passes.len(), 1);
+        assert!(compiled.module.passes[0].output == OutputType::Results(vec![0, 1]));
+    }
+
+    fn compile_file(files: &TempFiles, name: &str) -> Result<CompiledPipeline, Vec<Diagnostic>> {
+        let path = files.path(name);
+        let source = std::fs::read_to_string(&path).unwrap();
+        let options = CompileOptions {
+            name: name.to_owned(),
+            path: Some(path),
+            ..CompileOptions::default()
+        };
+        compile(&source, options)
+    }
+
+    fn errors(result: Result<CompiledPipeline, Vec<Diagnostic>>) -> Vec<String> {
+        match result {
+            Result::Ok(_) => panic!("compilation should fail"),
+            Result::Err(diagnostics) => diagnostics.into_iter().map(|x| x.message).collect(),
+        }
+    }
+
+    const PIPELINE: &str = "pipeline main(color: vec4) -> (vec4) { return color * k; }";
+
+    #[test]
+    fn imports_are_relative_to_importing_file() {
+        let main = format!("import \"lib/scale.octo\";\n{}", PIPELINE);
+        let files = TempFiles::new(
+            "relative",
+            &[
+                ("main.octo", main.as_str()),
+                ("lib/scale.octo", "import \"../common.octo\";\nlet k = half * 2.0;"),
+                ("common.octo", "let half = 0.5;"),
+            ],
+        );
+        if let Result::Err(diagnostics) = compile_file(&files, "main.octo") {
+            panic!("{:?}", diagnostics.iter().map(|x| &x.message).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn diamond_imports_are_merged_once() {
+        let main = format!("import \"left.octo\";\nimport \"right.octo\";\n{}", PIPELINE);
+        let files = TempFiles::new(
+            "diamond",
+            &[
+                ("main.octo", main.as_str()),
+                ("left.octo", "import \"shared.octo\";\nlet left = base;"),
+                ("right.octo", "import \"shared.octo\";\nlet right = base;"),
+                ("shared.octo", "let base = 1.0;\nlet k = 2.0;"),
+            ],
+        );
+        // second copy of shared.octo would redefine its constants
+        if let Result::Err(diagnostics) = compile_file(&files, "main.octo") {
+            panic!("{:?}", diagnostics.iter().map(|x| &x.message).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn import_cycles_are_reported() {
+        let main = format!("import \"a.octo\";\n{}", PIPELINE);
+        let files = TempFiles::new(
+            "cycle",
+            &[
+                ("main.octo", main.as_str()),
+                ("a.octo", "import \"b.octo\";\nlet k = 1.0;"),
+                ("b.octo", "import \"a.octo\";\nlet other = 1.0;"),
+            ],
+        );
+        let errors = errors(compile_file(&files, "main.octo"));
+        assert_eq!(errors, vec!["Import cycle: \"a.octo\" imports itself".to_owned()]);
+    }
+
+    #[test]
+    fn imported_pipelines_are_rejected() {
+        let main = format!("import \"other.octo\";\nlet k = 1.0;\n{}", PIPELINE);
+        let files = TempFiles::new(
+            "pipeline",
+            &[
+                ("main.octo", main.as_str()),
+                ("other.octo", "pipeline other(color: vec4) -> (vec4) { return color; }"),
+            ],
+        );
+        let errors = errors(compile_file(&files, "main.octo"));
+        assert_eq!(errors, vec!["Imported file \"other.octo\" defines a pipeline".to_owned()]);
+    }
+
+    #[test]
+    fn imports_need_path() {
+        let source = format!("import \"common.octo\";\n{}", PIPELINE);
+        let errors = errors(compile(&source, CompileOptions::default()));
+        assert_eq!(errors, vec!["Imports need path of compiled file".to_owned()]);
+    }
+
+    #[test]
+    fn missing_imports_are_reported() {
+        let main = format!("import \"missing.octo\";\n{}", PIPELINE);
+        let files = TempFiles::new("missing", &[("main.octo", main.as_str())]);
+        let errors = errors(compile_file(&files, "main.octo"));
+        assert_eq!(errors, vec!["Cannot open imported file \"missing.octo\"".to_owned()]);
+    }
+
+    const TWO_PASSES: &str = "
+        pipeline main(color: vec4) -> (vec4) {
+            let small = scale(color * color, 0.5);
+            return small + color;
+        }
+    ";
+
+    fn compile_source(source: &str, options: CompileOptions) -> CompiledPipeline {
+        match compile(source, options) {
+            Result::Ok(x) => x,
+            Result::Err(diagnostics) => {
+                panic!("{:?}", diagnostics.iter().map(|x| &x.message).collect::<Vec<_>>())
+            }
+        }
+    }
+
+    fn with_backend(backend: Backend) -> CompileOptions {
+        CompileOptions {
+            backend,
+            ..CompileOptions::default()
+        }
+    }
+
+    #[test]
+    fn module_describes_pipeline() {
+        let options = CompileOptions {
+            name: "two_passes".to_owned(),
+            ..CompileOptions::default()
+        };
+        let compiled = compile_source(TWO_PASSES, options);
+        assert_eq!(compiled.module.name, "two_passes");
+        assert_eq!(compiled.module.passes.len(), 2);
+        assert_eq!(compiled.module.fragment_shaders.len(), 2);
+        assert_eq!(compiled.module.required_input.len(), 1);
+        assert_eq!(compiled.module.required_input[0].0, "color");
+        assert!(compiled.warnings.is_empty());
+    }
+
+    #[test]
+    fn sources_are_kept_for_selected_backend() {
+        let spirv = compile_source(TWO_PASSES, CompileOptions::default());
+        assert!(spirv.sources.is_empty());
+        assert!(spirv.module.wgsl_shaders.is_empty());
+        for backend in &[Backend::Glsl, Backend::Hlsl] {
+            let compiled = compile_source(TWO_PASSES, with_backend(*backend));
+            assert_eq!(compiled.sources.len(), 2);
+            assert!(compiled.module.wgsl_shaders.is_empty());
+        }
+        let wgsl = compile_source(TWO_PASSES, with_backend(Backend::Wgsl));
+        assert!(wgsl.sources.is_empty());
+        assert_eq!(wgsl.module.wgsl_shaders.len(), 2);
+    }
+
+    #[test]
+    fn dumps_are_kept_only_on_request() {
+        let compiled = compile_source(TWO_PASSES, CompileOptions::default());
+        assert!(compiled.ast.is_none());
+        assert!(compiled.ir.is_empty());
+        assert!(compiled.passes.is_none());
+
+        let options = CompileOptions {
+            dump_ir: true,
+            unroll_loops: Some(100),
+            ..CompileOptions::default()
+        };
+        let compiled = compile_source(TWO_PASSES, options);
+        assert!(compiled.ast.is_some());
+        assert!(compiled.passes.is_some());
+        let stages: Vec<_> = compiled.ir.iter().map(|x| x.0.as_str()).collect();
+        assert_eq!(stages[0], "before constant propagation");
+        assert!(stages.contains(&"after loop unrolling"));
+        assert_eq!(stages[stages.len() - 1], "after control flow simplification");
+    }
+
+    #[test]
+    fn warnings_are_returned_on_success() {
+        let source = "pipeline main(color: vec4) -> (vec4) { let unused = 1.0; return color; }";
+        let compiled = compile_source(source, CompileOptions::default());
+        assert_eq!(compiled.warnings.len(), 1);
+        assert!(match compiled.warnings[0].severity {
+            codespan_reporting::Severity::Warning => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn ir_errors_have_code_and_no_location() {
+        // factor is folded to a constant only after static analysis
+        let source = "
+            pipeline main(color: vec4) -> (vec4) {
+                let unused = 1.0;
+                let small = scale(color * color, 0.5 - 1.0);
+                return small + color;
+            }
+        ";
+        let diagnostics = match compile(source, CompileOptions::default()) {
+            Result::Ok(_) => panic!("compilation should fail"),
+            Result::Err(x) => x,
+        };
+        assert_eq!(diagnostics.len(), 2);
+        // warnings found before error are kept
+        assert!(match diagnostics[0].severity {
+            codespan_reporting::Severity::Warning => true,
+            _ => false,
+        });
+        let error = &diagnostics[1];
+        assert_eq!(error.message, IrError::ScaleOutOfRange.to_string());
+        assert_eq!(error.code, Some(IrError::ScaleOutOfRange.code().to_owned()));
+        assert!(error.labels.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use parser::codespan::{ByteIndex, ByteSpan, CodeMap};
+use parser::codespan_reporting::{self, Diagnostic, LabelStyle, Severity};
+use serde::Serialize;
+
+/// How diagnostics are reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    /// Colored snippets of source, written to stderr.
+    Human,
+    /// One JSON object per line, written to stdout.
+    Json,
+}
+
+impl std::str::FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Result::Ok(MessageFormat::Human),
+            "json" => Result::Ok(MessageFormat::Json),
+            _ => Result::Err(format!("unknown message format \"{}\", expected human or json", s)),
+        }
+    }
+}
+
+/// Line and column, both counted from 1.
+#[derive(Debug, Serialize)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// Location of span inside single file, byte range is relative to file start.
+#[derive(Debug, Serialize)]
+pub struct Location {
+    pub file: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonLabel {
+    pub primary: bool,
+    pub message: Option<String>,
+    pub location: Option<Location>,
+}
+
+/// Diagnostic with spans resolved to files, location is taken from first primary label.
+#[derive(Debug, Serialize)]
+pub struct JsonDiagnostic {
+    pub severity: &'static str,
+    pub code: Option<String>,
+    pub message: String,
+    pub location: Option<Location>,
+    pub labels: Vec<JsonLabel>,
+}
+
+pub fn to_json(map: &CodeMap, diagnostic: &Diagnostic) -> JsonDiagnostic {
+    let severity = match diagnostic.severity {
+        Severity::Bug => "bug",
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note => "note",
+        Severity::Help => "help",
+    };
+    let labels: Vec<_> = diagnostic
+        .labels
+        .iter()
+        .map(|x| JsonLabel {
+            primary: is_primary(x.style),
+            message: x.message.clone(),
+            location: locate(map, x.span),
+        })
+        .collect();
+    let location = diagnostic
+        .labels
+        .iter()
+        .find(|x| is_primary(x.style))
+        .and_then(|x| locate(map, x.span));
+    JsonDiagnostic {
+        severity,
+        code: diagnostic.code.clone(),
+        message: diagnostic.message.clone(),
+        location,
+        labels,
+    }
+}
+
+fn is_primary(style: LabelStyle) -> bool {
+    match style {
+        LabelStyle::Primary => true,
+        LabelStyle::Secondary => false,
+    }
+}
+
+/// Resolves span to file and position inside it.
+pub fn locate(map: &CodeMap, span: ByteSpan) -> Option<Location> {
+    let file = map.find_file(span.start())?;
+    let position = |index: ByteIndex| {
+        file.location(index).ok().map(|(line, column)| Position {
+            line: line.0 + 1,
+            column: column.0 + 1,
+        })
+    };
+    let file_start = file.span().start().0;
+    Some(Location {
+        file: file.name().to_string(),
+        byte_start: (span.start().0 - file_start) as usize,
+        byte_end: (span.end().0 - file_start) as usize,
+        start: position(span.start())?,
+        end: position(span.end())?,
+    })
+}
+
+/// Reports diagnostics in given format.
+pub fn report(map: &CodeMap, messages: &[Diagnostic], format: MessageFormat) {
+    match format {
+        MessageFormat::Human => {
+            use codespan_reporting::termcolor::StandardStream;
+            let writer = StandardStream::stderr(codespan_reporting::termcolor::ColorChoice::Auto);
+            for message in messages {
+                let wr = &mut writer.lock();
+                codespan_reporting::emit(wr, map, message).unwrap();
+            }
+        }
+        MessageFormat::Json => {
+            for message in messages {
+                println!("{}", serde_json::to_string(&to_json(map, message)).unwrap());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::{compile_with_map, CompileOptions};
+
+    #[test]
+    fn unknown_variable_location() {
+        let source = "pipeline main(color: vec4) -> (vec4)\n{\n    return colour;\n}\n";
+        let mut map = CodeMap::new();
+        let diagnostics = match compile_with_map(&mut map, source, CompileOptions::default()) {
+            Result::Ok(_) => panic!("compilation should fail"),
+            Result::Err(x) => x,
+        };
+        // warning about unused argument goes first
+        let json = to_json(&map, &diagnostics[diagnostics.len() - 1]);
+        assert_eq!(json.severity, "error");
+        assert_eq!(json.code, Some("E0201".to_owned()));
+
+        let location = json.location.unwrap();
+        assert_eq!(location.file, "pipeline");
+        assert_eq!(&source[location.byte_start..location.byte_end], "colour");
+        assert_eq!((location.start.line, location.start.column), (3, 12));
+    }
+}
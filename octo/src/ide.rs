@@ -0,0 +1,197 @@
+use parser::codespan::CodeMap;
+use parser::codespan_reporting::Diagnostic;
+
+use crate::compile::{load, CompileOptions};
+use crate::diagnostics::{locate, Location};
+use crate::prototypes::{get_prototypes, match_prototype};
+use crate::semantics::{self, env::Reference};
+use crate::static_analysis::{analyze_with_references, Diagnostics};
+
+/// Result of front end, kept by editor tooling.
+pub struct Analysis {
+    pub map: CodeMap,
+    pub diagnostics: Vec<Diagnostic>,
+    pub references: Vec<Reference>,
+}
+
+impl Analysis {
+    /// Reference covering byte offset of given file, cursor just after identifier still hits it.
+    pub fn reference_at(&self, file: &str, offset: usize) -> Option<&Reference> {
+        self.references.iter().find(|x| match self.locate(x) {
+            Some(location) => location.file == file && location.byte_start <= offset && offset <= location.byte_end,
+            None => false,
+        })
+    }
+
+    pub fn locate(&self, reference: &Reference) -> Option<Location> {
+        locate(&self.map, reference.span)
+    }
+
+    pub fn locate_definition(&self, reference: &Reference) -> Option<Location> {
+        locate(&self.map, reference.variable.span)
+    }
+}
+
+/// Loads source with imports and runs static analysis.
+/// Pipeline is not required, so files with shared functions can be checked too.
+/// Statements skipped after syntax errors are left out of analysis.
+pub fn analyze(source: &str, options: &CompileOptions) -> Analysis {
+    let mut map = CodeMap::new();
+    let mut diagnostics = vec![];
+    let mut references = vec![];
+    if let Result::Ok(ast) = load(&mut map, source, options, true, &mut diagnostics) {
+        let (_, Diagnostics { errors, warnings }, found) = analyze_with_references(ast);
+        diagnostics.extend(warnings.into_iter().map(|x| semantics::WarningWrap::new(x).into()));
+        diagnostics.extend(errors.into_iter().map(|x| semantics::ErrorWrap::new(x).into()));
+        references = found;
+    }
+    Analysis {
+        map,
+        diagnostics,
+        references,
+    }
+}
+
+pub struct Signature {
+    /// Whole signature, as "name(arguments) -> result".
+    pub label: String,
+    pub parameters: Vec<String>,
+}
+
+/// Every prototype of builtin function.
+pub fn builtin_signatures(name: &str) -> Vec<Signature> {
+    get_prototypes(name)
+        .into_iter()
+        .map(|args| {
+            let result = match match_prototype(name, &args) {
+                Result::Ok(typ) => typ.to_string(),
+                Result::Err(_) => "?".to_owned(),
+            };
+            let parameters: Vec<_> = args.iter().map(|x| x.to_string()).collect();
+            Signature {
+                label: format!("{}({}) -> {}", name, parameters.join(", "), result),
+                parameters,
+            }
+        })
+        .collect()
+}
+
+/// Name of function whose argument list is open at the end of text, and index of current argument.
+pub fn enclosing_call(text: &str) -> Option<(String, usize)> {
+    let mut depth = 0;
+    let mut commas = 0;
+    for (index, c) in text.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' if depth > 0 => depth -= 1,
+            '(' => {
+                let before = text[..index].trim_end();
+                let start = before
+                    .rfind(|x: char| !(x.is_alphanumeric() || x == '_'))
+                    .map(|x| x + 1)
+                    .unwrap_or(0);
+                let name = &before[start..];
+                if name.is_empty() {
+                    return None;
+                }
+                return Some((name.to_owned(), commas));
+            }
+            ',' if depth == 0 => commas += 1,
+            ';' | '{' | '}' => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::ast::Type;
+    use std::path::Path;
+
+    const SOURCE: &str = r#"import "common/color.octo";
+
+pipeline main(color: vec4) -> (vec4)
+with {
+    exposure: float,
+}
+{
+    let weighted = color.xyz * luminance_weights;
+    let bright = weighted * exposure;
+    return vec4(bright.x, bright.y, bright.z, 1.0);
+}
+"#;
+
+    // imports are resolved next to examples, file itself doesn't have to exist
+    fn analyze_source() -> Analysis {
+        let options = CompileOptions {
+            name: "test.octo".to_owned(),
+            path: Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("../examples/test.octo")),
+            ..CompileOptions::default()
+        };
+        let analysis = analyze(SOURCE, &options);
+        assert!(analysis.diagnostics.is_empty());
+        analysis
+    }
+
+    fn definition_of(analysis: &Analysis, usage: &str) -> Location {
+        let offset = SOURCE.find(usage).unwrap();
+        let reference = analysis.reference_at("test.octo", offset).unwrap();
+        analysis.locate_definition(reference).unwrap()
+    }
+
+    #[test]
+    fn hover_shows_type() {
+        let analysis = analyze_source();
+        let reference = analysis
+            .reference_at("test.octo", SOURCE.find("weighted *").unwrap())
+            .unwrap();
+        assert_eq!(reference.variable.name, "weighted");
+        assert_eq!(reference.variable.typ, Type::Vec3);
+    }
+
+    #[test]
+    fn definition_of_local() {
+        let definition = definition_of(&analyze_source(), "weighted *");
+        assert_eq!(definition.file, "test.octo");
+        assert_eq!(definition.byte_start, SOURCE.find("weighted").unwrap());
+    }
+
+    #[test]
+    fn definition_of_argument() {
+        let definition = definition_of(&analyze_source(), "color.xyz");
+        assert_eq!(definition.file, "test.octo");
+        assert_eq!(definition.byte_start, SOURCE.find("color: vec4").unwrap());
+    }
+
+    #[test]
+    fn definition_of_uniform() {
+        let definition = definition_of(&analyze_source(), "exposure;");
+        assert_eq!(definition.file, "test.octo");
+        assert_eq!(definition.byte_start, SOURCE.find("exposure: float").unwrap());
+    }
+
+    #[test]
+    fn definition_of_imported_constant() {
+        let definition = definition_of(&analyze_source(), "luminance_weights;");
+        assert!(definition.file.ends_with("color.octo"));
+        let imported = std::fs::read_to_string(&definition.file).unwrap();
+        assert_eq!(definition.byte_start, imported.find("luminance_weights").unwrap());
+    }
+
+    #[test]
+    fn signature_help() {
+        let text = &SOURCE[..SOURCE.find("bright.y").unwrap()];
+        assert_eq!(enclosing_call(text), Some(("vec4".to_owned(), 1)));
+        assert_eq!(enclosing_call("let a = clamp(min(x, y), "), Some(("clamp".to_owned(), 1)));
+        assert_eq!(enclosing_call("let a = (x + y"), None);
+
+        let signatures = builtin_signatures("clamp");
+        assert!(!signatures.is_empty());
+        for signature in signatures {
+            assert!(signature.label.starts_with("clamp("));
+            assert_eq!(signature.parameters.len(), 3);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Reading and writing image files for pipelines run on CPU.
+use std::path::Path;
+
+use parser::ast::Type;
+
+use crate::tac_ir::{ConstantValue, Image};
+
+type Pixels = (usize, usize, Vec<[f32; 4]>);
+
+/// Loads image, value of every pixel is built from as many channels (starting from red) as given type needs.
+pub fn load(path: &Path, typ: Type) -> Result<Image, String> {
+    match typ {
+        Type::Float | Type::Vec2 | Type::Vec3 | Type::Vec4 => {}
+        _ => return Result::Err(format!("{} can't be read from image", typ)),
+    }
+    let (width, height, pixels) = if is_exr(path) { load_exr(path)? } else { load_ldr(path)? };
+    let pixels = pixels
+        .into_iter()
+        .map(|p| {
+            let p = [p[0] as f64, p[1] as f64, p[2] as f64, p[3] as f64];
+            match typ {
+                Type::Float => ConstantValue::Float(p[0]),
+                Type::Vec2 => ConstantValue::Vec2([p[0], p[1]]),
+                Type::Vec3 => ConstantValue::Vec3([p[0], p[1], p[2]]),
+                _ => ConstantValue::Vec4(p),
+            }
+        })
+        .collect();
+    Result::Ok(Image { width, height, pixels })
+}
+
+/// Saves image as rgba, float is written as gray and missing channels are 0 (alpha 1).
+pub fn save(path: &Path, image: &Image) -> Result<(), String> {
+    let pixels = image
+        .pixels
+        .iter()
+        .map(|x| {
+            let p = match *x {
+                ConstantValue::Float(v) => [v, v, v, 1.0],
+                ConstantValue::Vec2(v) => [v[0], v[1], 0.0, 1.0],
+                ConstantValue::Vec3(v) => [v[0], v[1], v[2], 1.0],
+                ConstantValue::Vec4(v) => v,
+                ConstantValue::Int(v) => [v as f64, v as f64, v as f64, 1.0],
+                ConstantValue::Bool(v) => {
+                    let v = if v { 1.0 } else { 0.0 };
+                    [v, v, v, 1.0]
+                }
+            };
+            [p[0] as f32, p[1] as f32, p[2] as f32, p[3] as f32]
+        })
+        .collect();
+    let pixels = (image.width, image.height, pixels);
+    if is_exr(path) {
+        save_exr(path, &pixels)
+    } else {
+        save_ldr(path, &pixels)
+    }
+}
+
+fn is_exr(path: &Path) -> bool {
+    match path.extension() {
+        Some(x) => x.to_string_lossy().to_lowercase() == "exr",
+        None => false,
+    }
+}
+
+// values are taken as they are, without srgb conversion
+fn load_ldr(path: &Path) -> Result<Pixels, String> {
+    let image = image::open(path).map_err(|e| e.to_string())?.to_rgba();
+    let (width, height) = image.dimensions();
+    let pixels = image
+        .pixels()
+        .map(|p| {
+            let c = |x: u8| x as f32 / 255.0;
+            [c(p[0]), c(p[1]), c(p[2]), c(p[3])]
+        })
+        .collect();
+    Result::Ok((width as usize, height as usize, pixels))
+}
+
+fn save_ldr(path: &Path, pixels: &Pixels) -> Result<(), String> {
+    let (width, height, pixels) = pixels;
+    let data: Vec<u8> = pixels
+        .iter()
+        .flat_map(|p| p.iter())
+        .map(|x| (x.max(0.0).min(1.0) * 255.0).round() as u8)
+        .collect();
+    image::save_buffer(path, &data, *width as u32, *height as u32, image::ColorType::RGBA(8))
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "exr")]
+fn load_exr(path: &Path) -> Result<Pixels, String> {
+    use openexr::{FrameBufferMut, InputFile};
+
+    let mut file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let mut input = InputFile::new(&mut file).map_err(|e| e.to_string())?;
+    let (width, height) = input.header().data_dimensions();
+    let mut data = vec![(0.0f32, 0.0f32, 0.0f32, 1.0f32); (width * height) as usize];
+    {
+        let mut buffer = FrameBufferMut::new(width, height);
+        buffer.insert_channels(&[("R", 0.0), ("G", 0.0), ("B", 0.0), ("A", 1.0)], &mut data);
+        input.read_pixels(&mut buffer).map_err(|e| e.to_string())?;
+    }
+    let pixels = data.into_iter().map(|(r, g, b, a)| [r, g, b, a]).collect();
+    Result::Ok((width as usize, height as usize, pixels))
+}
+
+#[cfg(feature = "exr")]
+fn save_exr(path: &Path, pixels: &Pixels) -> Result<(), String> {
+    use openexr::{FrameBuffer, Header, PixelType, ScanlineOutputFile};
+
+    let (width, height, pixels) = pixels;
+    let (width, height) = (*width as u32, *height as u32);
+    let data: Vec<(f32, f32, f32, f32)> = pixels.iter().map(|p| (p[0], p[1], p[2], p[3])).collect();
+    let mut file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    let mut output = ScanlineOutputFile::new(
+        &mut file,
+        Header::new()
+            .set_resolution(width, height)
+            .add_channel("R", PixelType::FLOAT)
+            .add_channel("G", PixelType::FLOAT)
+            .add_channel("B", PixelType::FLOAT)
+            .add_channel("A", PixelType::FLOAT),
+    )
+    .map_err(|e| e.to_string())?;
+    let mut buffer = FrameBuffer::new(width, height);
+    buffer.insert_channels(&["R", "G", "B", "A"], &data);
+    output.write_pixels(&buffer).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "exr"))]
+fn load_exr(_path: &Path) -> Result<Pixels, String> {
+    Result::Err("octo was built without OpenEXR support (feature \"exr\")".to_owned())
+}
+
+#[cfg(not(feature = "exr"))]
+fn save_exr(_path: &Path, _pixels: &Pixels) -> Result<(), String> {
+    Result::Err("octo was built without OpenEXR support (feature \"exr\")".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::TempFiles;
+
+    fn row(pixels: Vec<ConstantValue>) -> Image {
+        Image {
+            width: pixels.len(),
+            height: 1,
+            pixels,
+        }
+    }
+
+    fn channels(value: &ConstantValue) -> Vec<f64> {
+        match *value {
+            ConstantValue::Float(x) => vec![x],
+            ConstantValue::Vec2(x) => x.to_vec(),
+            ConstantValue::Vec3(x) => x.to_vec(),
+            ConstantValue::Vec4(x) => x.to_vec(),
+            x => panic!("{:?} is not loaded from image", x),
+        }
+    }
+
+    // png stores 8 bits per channel
+    fn assert_pixels(image: &Image, expected: &[ConstantValue]) {
+        assert_eq!(image.pixels.len(), expected.len());
+        for (a, b) in image.pixels.iter().zip(expected) {
+            let (a, b) = (channels(a), channels(b));
+            assert_eq!(a.len(), b.len());
+            assert!(a.iter().zip(&b).all(|(x, y)| (x - y).abs() < 1.0 / 255.0), "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn loaded_values_take_channels_of_type() {
+        let files = TempFiles::new("images-load", &[]);
+        let path = files.path("color.png");
+        save(&path, &row(vec![ConstantValue::Vec4([0.2, 0.4, 0.6, 0.8])])).unwrap();
+        assert_pixels(&load(&path, Type::Float).unwrap(), &[ConstantValue::Float(0.2)]);
+        assert_pixels(&load(&path, Type::Vec2).unwrap(), &[ConstantValue::Vec2([0.2, 0.4])]);
+        assert_pixels(&load(&path, Type::Vec3).unwrap(), &[ConstantValue::Vec3([0.2, 0.4, 0.6])]);
+        assert_pixels(&load(&path, Type::Vec4).unwrap(), &[ConstantValue::Vec4([0.2, 0.4, 0.6, 0.8])]);
+    }
+
+    #[test]
+    fn saved_values_fill_missing_channels() {
+        let files = TempFiles::new("images-save", &[]);
+        let path = files.path("values.png");
+        let image = row(vec![
+            ConstantValue::Float(0.4),
+            ConstantValue::Vec2([0.2, 0.6]),
+            ConstantValue::Vec3([1.5, -0.5, 0.2]),
+        ]);
+        save(&path, &image).unwrap();
+        let loaded = load(&path, Type::Vec4).unwrap();
+        assert_eq!((loaded.width, loaded.height), (3, 1));
+        assert_pixels(
+            &loaded,
+            &[
+                ConstantValue::Vec4([0.4, 0.4, 0.4, 1.0]),
+                ConstantValue::Vec4([0.2, 0.6, 0.0, 1.0]),
+                ConstantValue::Vec4([1.0, 0.0, 0.2, 1.0]),
+            ],
+        );
+    }
+
+    #[test]
+    fn only_float_vectors_are_loaded() {
+        let files = TempFiles::new("images-types", &[]);
+        let path = files.path("color.png");
+        save(&path, &row(vec![ConstantValue::Float(0.5)])).unwrap();
+        assert!(load(&path, Type::Int).is_err());
+        assert!(load(&path, Type::Bool).is_err());
+        assert!(load(&files.path("missing.png"), Type::Vec4).is_err());
+    }
+
+    #[cfg(not(feature = "exr"))]
+    #[test]
+    fn exr_needs_feature() {
+        let files = TempFiles::new("images-exr", &[]);
+        assert!(save(&files.path("color.exr"), &row(vec![ConstantValue::Float(0.5)])).is_err());
+    }
+}
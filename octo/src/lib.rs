@@ -1,155 +1,660 @@
 //extern crate lalrpop_util;
 
 pub mod semantics;
+mod compile;
+mod diagnostics;
+pub mod ide;
+mod images;
 mod static_analysis;
 mod tac_ir;
 mod prototypes;
+#[cfg(test)]
+mod test_files;
 
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use codespan_reporting::Diagnostic;
 use parser::ast;
-use parser::codespan::CodeMap;
 use parser::codespan_reporting;
 
 use std::borrow::ToOwned;
 
-use crate::static_analysis::Diagnostics;
 use log::info;
 
-pub fn process_file(path: &str) -> Result<(), ()> {
+use crate::diagnostics::report;
+
+pub use codespan_reporting::Diagnostic;
+pub use diagnostics::{locate, to_json, JsonDiagnostic, JsonLabel, Location, MessageFormat, Position};
+pub use compile::{compile, compile_with_map, CompileOptions, CompiledPipeline};
+pub use octo_runtime::OctoModule;
+pub use parser::codespan::CodeMap;
+
+/// Format of shaders written into debug directory, compiled module always contains SPIR-V.
+/// WGSL shaders and their bindings are also stored in the module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Spirv,
+    Glsl,
+    Wgsl,
+    Hlsl,
+}
+
+impl std::str::FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spirv" => Result::Ok(Backend::Spirv),
+            "glsl" => Result::Ok(Backend::Glsl),
+            "wgsl" => Result::Ok(Backend::Wgsl),
+            "hlsl" => Result::Ok(Backend::Hlsl),
+            _ => Result::Err(format!("unknown backend \"{}\", expected spirv, glsl, wgsl or hlsl", s)),
+        }
+    }
+}
+
+/// Additional output of `process_file`, written into debug directory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Ast,
+    Ir,
+    IrOpt,
+    Passes,
+    Spirv,
+    SpirvAsm,
+    Json,
+}
+
+impl std::str::FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ast" => Result::Ok(Emit::Ast),
+            "ir" => Result::Ok(Emit::Ir),
+            "ir-opt" => Result::Ok(Emit::IrOpt),
+            "passes" => Result::Ok(Emit::Passes),
+            "spirv" => Result::Ok(Emit::Spirv),
+            "spirv-asm" => Result::Ok(Emit::SpirvAsm),
+            "json" => Result::Ok(Emit::Json),
+            _ => Result::Err(format!(
+                "unknown emit kind \"{}\", expected ast, ir, ir-opt, passes, spirv, spirv-asm or json",
+                s
+            )),
+        }
+    }
+}
+
+impl Emit {
+    /// Emitted from textual dumps of compilation stages, see `CompileOptions::dump_ir`.
+    pub fn is_dump(self) -> bool {
+        match self {
+            Emit::Ast | Emit::Ir | Emit::IrOpt | Emit::Passes => true,
+            _ => false,
+        }
+    }
+}
+
+/// Serialization of compiled module.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModuleFormat {
+    Json,
+    Bin,
+}
+
+impl std::str::FromStr for ModuleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Result::Ok(ModuleFormat::Json),
+            "bin" => Result::Ok(ModuleFormat::Bin),
+            _ => Result::Err(format!("unknown module format \"{}\", expected json or bin", s)),
+        }
+    }
+}
+
+/// Options of `process_file`.
+#[derive(Debug, Clone)]
+pub struct ProcessOptions {
+    pub backend: Backend,
+    /// Path of compiled module, source path with `.octo_bin` extension by default.
+    pub output: Option<PathBuf>,
+    /// Directory of emitted artifacts, source path without extension by default.
+    pub debug_dir: Option<PathBuf>,
+    pub emit: Vec<Emit>,
+    pub format: ModuleFormat,
+    pub report_warnings: bool,
+    pub message_format: MessageFormat,
+    /// See `CompileOptions::unroll_loops`.
+    pub unroll_loops: Option<usize>,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        ProcessOptions {
+            backend: Backend::Spirv,
+            output: None,
+            debug_dir: None,
+            emit: vec![],
+            format: ModuleFormat::Json,
+            report_warnings: true,
+            message_format: MessageFormat::Human,
+            unroll_loops: None,
+        }
+    }
+}
+
+/// Compiles file into module and writes requested artifacts into debug directory.
+/// Shaders of GLSL, WGSL and HLSL backends are always written there, diagnostics are reported to stderr.
+pub fn process_file(path: &str, options: &ProcessOptions) -> Result<(), ()> {
     info!("Processing file at: {}", path);
 
     //info!("rerun-if-changed={}", path);
 
     let p = Path::new(path);
+    let mut map = CodeMap::new();
+    let source = match std::fs::read_to_string(p) {
+        Result::Ok(x) => x,
+        Result::Err(e) => {
+            let message = format!("Cannot read file {}: {}", path, e);
+            report(&map, &[Diagnostic::new_error(message)], options.message_format);
+            return Result::Err(());
+        }
+    };
+    let compile_options = CompileOptions {
+        name: path.to_owned(),
+        backend: options.backend,
+        path: Some(canonical_path(&map, p, options.message_format)?),
+        dump_ir: options.emit.iter().any(|x| x.is_dump()),
+        unroll_loops: options.unroll_loops,
+    };
+    let compiled = match compile_with_map(&mut map, &source, compile_options) {
+        Result::Ok(x) => x,
+        Result::Err(diagnostics) => {
+            report(&map, &diagnostics, options.message_format);
+            return Result::Err(());
+        }
+    };
+    if options.report_warnings {
+        report(&map, &compiled.warnings, options.message_format);
+    }
 
-    if !p.is_file() {
-        panic!("given path is not a file: {}", path);
-    }
-    let result_path = p.with_extension("octo_bin");
-
-    let mut file = File::open(path).unwrap();
-    let mut data = String::new();
-    file.read_to_string(&mut data).unwrap();
-
-    let ast = parse_data(&data, path)?;
-
-    let static_analysis_res = static_analysis::analyze(ast);
-    let Diagnostics { errors, warnings } = static_analysis_res.1;
-    let error_happened = errors.len() > 0;
-    let mut diagnostics: Vec<Diagnostic> = warnings
-        .into_iter()
-        .map(|x| semantics::WarningWrap::new(x).into())
-        .collect();
-    diagnostics.extend(
-        errors
-            .into_iter()
-            .map(|x| semantics::ErrorWrap::new(x).into()),
-    );
-    report_errors(&data, path, &diagnostics);
-    if error_happened {
-        return Result::Err(());
+    let result_path = match &options.output {
+        Some(x) => x.clone(),
+        None => p.with_extension("octo_bin"),
+    };
+    let data = match options.format {
+        ModuleFormat::Json => serde_json::to_vec(&compiled.module).unwrap(),
+        ModuleFormat::Bin => bincode::serialize(&compiled.module).unwrap(),
+    };
+    write_output(&map, &result_path, &data, options.message_format)?;
+
+    let mut files: Vec<(String, Vec<u8>)> = vec![];
+    for emit in &options.emit {
+        match emit {
+            Emit::Ast | Emit::Ir | Emit::IrOpt | Emit::Passes => {
+                if let Some((name, dump)) = dump_file(&compiled, *emit) {
+                    files.push((name.to_owned(), dump.clone().into_bytes()));
+                }
+            }
+            Emit::Spirv => {
+                for (id, shader) in &compiled.module.fragment_shaders {
+                    let data = shader.iter().flat_map(|x| x.to_le_bytes().to_vec()).collect();
+                    files.push((format!("{}.spv", id), data));
+                }
+            }
+            Emit::SpirvAsm => {
+                for (id, shader) in &compiled.module.fragment_shaders {
+                    files.push((format!("{}.spvasm", id), tac_ir::disassemble(shader).into_bytes()));
+                }
+            }
+            Emit::Json => files.push(("module.json".to_owned(), serde_json::to_vec_pretty(&compiled.module).unwrap())),
+        }
     }
-    let valid_ast = match static_analysis_res.0 {
-        None => unreachable!(),
-        Some(x) => x,
+    let extension = match options.backend {
+        Backend::Spirv => None,
+        Backend::Glsl => Some("frag"),
+        Backend::Wgsl => Some("wgsl"),
+        Backend::Hlsl => Some("hlsl"),
     };
+    if let Some(extension) = extension {
+        let sources: Vec<_> = match options.backend {
+            Backend::Wgsl => compiled
+                .module
+                .wgsl_shaders
+                .iter()
+                .map(|(id, shader)| (*id, shader.source.clone()))
+                .collect(),
+            _ => compiled.sources.iter().cloned().enumerate().collect(),
+        };
+        for (id, source) in sources {
+            files.push((format!("{}.{}", id, extension), source.into_bytes()));
+        }
+    }
 
-    let tac = tac_ir::emit_ir(valid_ast);
-    //tac_ir::emit_graph(&tac, &(path.to_owned() + "1"));
-    println!("before constant propagation");
-    println!("{:?}", tac);
+    if files.is_empty() {
+        return Result::Ok(());
+    }
+    let dir_name = match &options.debug_dir {
+        Some(x) => x.clone(),
+        None => p.with_extension(""),
+    };
+    if let Result::Err(e) = std::fs::create_dir_all(&dir_name) {
+        let message = format!("Cannot create directory {}: {}", dir_name.display(), e);
+        report(&map, &[Diagnostic::new_error(message)], options.message_format);
+        return Result::Err(());
+    }
+    for (name, data) in files {
+        write_output(&map, &dir_name.join(name), &data, options.message_format)?;
+    }
 
-    let tac = tac_ir::propagate_constants(tac);
-    //tac_ir::emit_graph(&tac,&(path.to_owned() + "2"));
-    println!("after constant propagation");
-    println!("{:?}", tac);
+    Result::Ok(())
+}
 
-    let tac = tac_ir::remove_unused_operations(tac);
-    println!("after unused operation removal");
-    println!("{:?}", tac);
+// dumps are kept by `compile` only for emits which need them
+fn dump_file(compiled: &CompiledPipeline, emit: Emit) -> Option<(&'static str, &String)> {
+    match emit {
+        Emit::Ast => compiled.ast.as_ref().map(|x| ("ast.txt", x)),
+        Emit::Ir => compiled.ir.first().map(|x| ("ir.txt", &x.1)),
+        Emit::IrOpt => compiled.ir.last().map(|x| ("ir-opt.txt", &x.1)),
+        Emit::Passes => compiled.passes.as_ref().map(|x| ("passes.txt", x)),
+        _ => None,
+    }
+}
 
-    let tac = tac_ir::move_sync_operations(tac);
+fn write_output(map: &CodeMap, path: &Path, data: &[u8], format: MessageFormat) -> Result<(), ()> {
+    match std::fs::write(path, data) {
+        Result::Ok(()) => Result::Ok(()),
+        Result::Err(e) => {
+            let message = format!("Cannot write {}: {}", path.display(), e);
+            report(map, &[Diagnostic::new_error(message)], format);
+            Result::Err(())
+        }
+    }
+}
 
-    println!("after sync movement");
-    println!("{:?}", tac);
+// imports are resolved relative to canonical path
+fn canonical_path(map: &CodeMap, path: &Path, format: MessageFormat) -> Result<PathBuf, ()> {
+    match path.canonicalize() {
+        Result::Ok(x) => Result::Ok(x),
+        Result::Err(e) => {
+            let message = format!("Cannot resolve path {}: {}", path.display(), e);
+            report(map, &[Diagnostic::new_error(message)], format);
+            Result::Err(())
+        }
+    }
+}
 
+/// Rewrites file in canonical style.
+/// With `check` file is left untouched and unformatted file is reported as failure.
+pub fn format_file(path: &str, check: bool, message_format: MessageFormat) -> Result<(), ()> {
+    let p = Path::new(path);
+    let mut map = CodeMap::new();
+    let source = match std::fs::read_to_string(p) {
+        Result::Ok(x) => x,
+        Result::Err(e) => {
+            let message = format!("Cannot read file {}: {}", path, e);
+            report(&map, &[Diagnostic::new_error(message)], message_format);
+            return Result::Err(());
+        }
+    };
+    // first file of map starts at the same index as spans of parser without offset
+    map.add_filemap(path.to_owned().into(), source.clone());
+    let formatted = match parser::format::format(&source) {
+        Result::Ok(x) => x,
+        Result::Err(failure_info) => {
+            let diagnostics: Vec<_> = failure_info
+                .errors
+                .iter()
+                .map(|err| Diagnostic::from(parser::ErrWrap { err }))
+                .collect();
+            report(&map, &diagnostics, message_format);
+            return Result::Err(());
+        }
+    };
+    if formatted == source {
+        return Result::Ok(());
+    }
+    if check {
+        let message = format!("{} is not formatted", path);
+        report(&map, &[Diagnostic::new_error(message)], message_format);
+        return Result::Err(());
+    }
+    write_output(&map, p, formatted.as_bytes(), message_format)
+}
+
+/// Compiles pipeline and runs it on CPU.
+/// Inputs and uniforms are pairs of name and file/value, n-th output file receives n-th pipeline result.
+pub fn run_file(
+    path: &str,
+    inputs: &[(String, String)],
+    uniforms: &[(String, String)],
+    outputs: &[String],
+    message_format: MessageFormat,
+) -> Result<(), ()> {
+    info!("Running file at: {}", path);
+
+    let p = Path::new(path);
+    let mut map = CodeMap::new();
+    let source = match std::fs::read_to_string(p) {
+        Result::Ok(x) => x,
+        Result::Err(e) => {
+            let message = format!("Cannot read file {}: {}", path, e);
+            report(&map, &[Diagnostic::new_error(message)], message_format);
+            return Result::Err(());
+        }
+    };
+    let options = CompileOptions {
+        name: path.to_owned(),
+        path: Some(canonical_path(&map, p, message_format)?),
+        ..CompileOptions::default()
+    };
+    let pipeline = match compile::compile_pipeline(&mut map, &source, &options, &mut compile::Dumps::default()) {
+        Result::Ok((pipeline, warnings)) => {
+            report(&map, &warnings, message_format);
+            pipeline
+        }
+        Result::Err(diagnostics) => {
+            report(&map, &diagnostics, message_format);
+            return Result::Err(());
+        }
+    };
+    let results_num = pipeline
+        .passes
+        .iter()
+        .map(|x| match &x.output {
+            tac_ir::OutputTexture::Result(results) => results.len(),
+            tac_ir::OutputTexture::Generated(_) => 0,
+        })
+        .sum::<usize>();
+
+    let mut errors = vec![];
+    for (name, _) in inputs {
+        if !pipeline.args.iter().any(|x| x.1 == *name) {
+            errors.push(format!("Pipeline has no argument named \"{}\"", name));
+        }
+    }
+    for (name, _) in uniforms {
+        if !pipeline.uniforms.iter().any(|x| x.1 == *name) {
+            errors.push(format!("Pipeline has no uniform named \"{}\"", name));
+        }
+    }
 
-    let tac = tac_ir::unroll_synced_loop(tac);
+    let mut images = vec![];
+    for (typ, name) in &pipeline.args {
+        match inputs.iter().find(|x| x.0 == *name) {
+            None => errors.push(format!("Missing input file for argument \"{}\"", name)),
+            Some((_, file)) => match images::load(Path::new(file), *typ) {
+                Result::Ok(image) => images.push(image),
+                Result::Err(e) => errors.push(format!("Cannot load \"{}\": {}", file, e)),
+            },
+        }
+    }
+    if images.iter().any(|x| x.width != images[0].width || x.height != images[0].height) {
+        errors.push("Input images have different sizes".to_owned());
+    }
+
+    let mut values = vec![];
+    for (typ, name) in &pipeline.uniforms {
+        match uniforms.iter().find(|x| x.0 == *name) {
+            None => errors.push(format!("Missing value for uniform \"{}\"", name)),
+            Some((_, value)) => match parse_uniform(value, *typ) {
+                Result::Ok(value) => values.push(value),
+                Result::Err(e) => errors.push(format!("Invalid value of uniform \"{}\": {}", name, e)),
+            },
+        }
+    }
 
-    println!("after loop reexport");
-    println!("{:?}", tac);
+    if outputs.len() != results_num {
+        errors.push(format!(
+            "Pipeline has {} results, but {} output files were given",
+            results_num,
+            outputs.len()
+        ));
+    }
+    if pipeline.args.is_empty() {
+        errors.push("Pipeline without arguments can't be run, size of result is unknown".to_owned());
+    }
+
+    if errors.len() > 0 {
+        let diagnostics: Vec<Diagnostic> = errors.into_iter().map(Diagnostic::new_error).collect();
+        report(&map, &diagnostics, message_format);
+        return Result::Err(());
+    }
+
+    let (width, height) = (images[0].width, images[0].height);
+    let results = tac_ir::interpret_passes(&pipeline, &images, &values, width, height);
+    for (result, file) in results.iter().zip(outputs.iter()) {
+        if let Result::Err(e) = images::save(Path::new(file), result) {
+            let message = format!("Cannot save \"{}\": {}", file, e);
+            report(&map, &[Diagnostic::new_error(message)], message_format);
+            return Result::Err(());
+        }
+    }
 
+    Result::Ok(())
+}
 
-    let pipeline_definition = tac_ir::split_passes(tac);
+/// Components of vectors are separated with commas.
+fn parse_uniform(text: &str, typ: ast::Type) -> Result<tac_ir::ConstantValue, String> {
+    use ast::Type;
+    use tac_ir::ConstantValue;
+
+    let size = match typ {
+        Type::Int => return text.trim().parse().map(ConstantValue::Int).map_err(|e| format!("{}", e)),
+        Type::Bool => return text.trim().parse().map(ConstantValue::Bool).map_err(|e| format!("{}", e)),
+        Type::Float => 1,
+        Type::Vec2 => 2,
+        Type::Vec3 => 3,
+        Type::Vec4 => 4,
+        _ => return Result::Err(format!("{} uniforms are not supported", typ)),
+    };
+    let v = text
+        .split(',')
+        .map(|x| x.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}", e))?;
+    if v.len() != size {
+        return Result::Err(format!("{} needs {} components, got {}", typ, size, v.len()));
+    }
+    Result::Ok(match size {
+        1 => ConstantValue::Float(v[0]),
+        2 => ConstantValue::Vec2([v[0], v[1]]),
+        3 => ConstantValue::Vec3([v[0], v[1], v[2]]),
+        _ => ConstantValue::Vec4([v[0], v[1], v[2], v[3]]),
+    })
+}
 
-    let module = tac_ir::emit_spirv(path, pipeline_definition);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_files::TempFiles;
+    use ast::Type;
+    use tac_ir::{ConstantValue, Image};
+
+    #[test]
+    fn uniforms_are_parsed_by_type() {
+        assert_eq!(parse_uniform(" 3 ", Type::Int), Result::Ok(ConstantValue::Int(3)));
+        assert_eq!(parse_uniform("true", Type::Bool), Result::Ok(ConstantValue::Bool(true)));
+        assert_eq!(parse_uniform("0.5", Type::Float), Result::Ok(ConstantValue::Float(0.5)));
+        assert_eq!(
+            parse_uniform("0.5, 1,2", Type::Vec3),
+            Result::Ok(ConstantValue::Vec3([0.5, 1.0, 2.0]))
+        );
+    }
 
-    //let shaders: Vec<_> = pipeline_definition.shaders.iter().map(|x| tac_ir::emit_spirv(x)).collect();
+    #[test]
+    fn invalid_uniforms_are_rejected() {
+        assert!(parse_uniform("1.5", Type::Int).is_err());
+        assert!(parse_uniform("half", Type::Float).is_err());
+        assert!(parse_uniform("1, 2, 3", Type::Vec2).is_err());
+        assert!(parse_uniform("1, 2", Type::Vec4).is_err());
+        assert!(parse_uniform("1", Type::Mat3).is_err());
+    }
 
-    //tac_ir::emit_spirv(tac);
+    const DIFFERENCE: &str = "
+        pipeline main(a: vec4, b: vec4) -> (vec4)
+        with {
+            k: float,
+        }
+        {
+            return a * k - b;
+        }
+    ";
 
-    let mut output_file = std::fs::File::create(&result_path).unwrap();
-    let data = serde_json::to_string(&module).unwrap();
-    use std::io::Write;
-    output_file.write_all(data.as_bytes()).unwrap();
+    fn solid(value: f64) -> Image {
+        Image {
+            width: 2,
+            height: 2,
+            pixels: vec![ConstantValue::Vec4([value, value, value, 1.0]); 4],
+        }
+    }
 
-    //emit debug directory
+    fn file(files: &TempFiles, name: &str) -> String {
+        files.path(name).to_string_lossy().into_owned()
+    }
 
-    {
-        let dir_name = p.file_stem().unwrap();
-        println!("dir name:{:?}", dir_name);
-        let _ = std::fs::create_dir_all(&dir_name).unwrap();
+    fn named(name: &str, value: String) -> (String, String) {
+        (name.to_owned(), value)
+    }
 
-        for (id, shader) in module.fragment_shaders {
-            let mut dir_n = std::path::PathBuf::from(dir_name);
-            dir_n.push(id.to_string());
-            dir_n.set_extension("frag");
-            let mut file = std::fs::File::create(&dir_n).unwrap();
-            let data: Vec<u8> = shader
-                .iter()
-                .map(|x| x.to_le_bytes())
-                .collect::<Vec<_>>()
-                .iter()
-                .flatten()
-                .cloned()
-                .collect(); //.flatten().collect();
-            file.write_all(&data).unwrap();
+    #[test]
+    fn run_file_matches_inputs_and_uniforms_by_name() {
+        let files = TempFiles::new("run-names", &[("main.octo", DIFFERENCE)]);
+        images::save(&files.path("a.png"), &solid(0.8)).unwrap();
+        images::save(&files.path("b.png"), &solid(0.2)).unwrap();
+        // inputs are given in different order than arguments
+        let inputs = vec![named("b", file(&files, "b.png")), named("a", file(&files, "a.png"))];
+        let uniforms = vec![named("k", "0.5".to_owned())];
+        let outputs = vec![file(&files, "result.png")];
+        run_file(&file(&files, "main.octo"), &inputs, &uniforms, &outputs, MessageFormat::Human).unwrap();
+        let result = images::load(&files.path("result.png"), Type::Float).unwrap();
+        assert_eq!((result.width, result.height), (2, 2));
+        for pixel in &result.pixels {
+            match *pixel {
+                ConstantValue::Float(x) => assert!((x - 0.2).abs() < 2.0 / 255.0, "{}", x),
+                x => panic!("{:?}", x),
+            }
         }
     }
 
-    Result::Ok(())
-}
+    #[test]
+    fn run_file_rejects_unknown_and_missing_names() {
+        let files = TempFiles::new("run-errors", &[("main.octo", DIFFERENCE)]);
+        images::save(&files.path("a.png"), &solid(0.8)).unwrap();
+        let source = file(&files, "main.octo");
+        let outputs = vec![file(&files, "result.png")];
+        let a = named("a", file(&files, "a.png"));
+        let k = named("k", "0.5".to_owned());
+        // argument b is missing
+        assert!(run_file(&source, &[a.clone()], &[k.clone()], &outputs, MessageFormat::Human).is_err());
+        // there is no argument c
+        let c = named("c", file(&files, "a.png"));
+        let inputs = vec![a.clone(), named("b", file(&files, "a.png")), c];
+        assert!(run_file(&source, &inputs, &[k.clone()], &outputs, MessageFormat::Human).is_err());
+        // uniform k is missing, there is no uniform scale
+        let inputs = vec![a, named("b", file(&files, "a.png"))];
+        let scale = named("scale", "0.5".to_owned());
+        assert!(run_file(&source, &inputs, &[scale], &outputs, MessageFormat::Human).is_err());
+        assert!(!files.path("result.png").exists());
+        assert!(run_file(&source, &inputs, &[k], &outputs, MessageFormat::Human).is_ok());
+    }
 
-fn parse_data(data: &str, path: &str) -> Result<ast::Pipeline, ()> {
-    match parser::parse(data, false) {
-        Err(failure_info) => {
-            println!("{:#?}", failure_info.errors);
-            report_errors(
-                data,
-                path,
-                &[parser::ErrWrap {
-                    err: &failure_info.errors[0],
-                }
-                .into()],
-            );
-            Result::Err(())
+    const SINGLE_PASS: &str = "pipeline main(color: vec4) -> (vec4) { return color * 0.5; }";
+
+    fn process(files: &TempFiles, options: ProcessOptions) {
+        process_file(&file(files, "main.octo"), &options).unwrap();
+    }
+
+    #[test]
+    fn module_is_written_next_to_source_by_default() {
+        let files = TempFiles::new("process-default", &[("main.octo", SINGLE_PASS)]);
+        process(&files, ProcessOptions::default());
+        let data = std::fs::read(files.path("main.octo_bin")).unwrap();
+        let module: OctoModule = serde_json::from_slice(&data).unwrap();
+        assert_eq!(module.passes.len(), 1);
+        // nothing was emitted
+        assert!(!files.path("main").exists());
+    }
+
+    #[test]
+    fn module_is_written_to_output_path_in_binary_format() {
+        let files = TempFiles::new("process-output", &[("main.octo", SINGLE_PASS)]);
+        let options = ProcessOptions {
+            output: Some(files.path("module.bin")),
+            format: ModuleFormat::Bin,
+            ..ProcessOptions::default()
+        };
+        process(&files, options);
+        assert!(!files.path("main.octo_bin").exists());
+        let data = std::fs::read(files.path("module.bin")).unwrap();
+        let module: OctoModule = bincode::deserialize(&data).unwrap();
+        assert_eq!(module.passes.len(), 1);
+    }
+
+    #[test]
+    fn emitted_files_are_written_to_debug_dir() {
+        let files = TempFiles::new("process-emit", &[("main.octo", SINGLE_PASS)]);
+        let options = ProcessOptions {
+            debug_dir: Some(files.path("dumps")),
+            emit: vec![
+                Emit::Ast,
+                Emit::Ir,
+                Emit::IrOpt,
+                Emit::Passes,
+                Emit::Spirv,
+                Emit::SpirvAsm,
+                Emit::Json,
+            ],
+            ..ProcessOptions::default()
+        };
+        process(&files, options);
+        for name in &[
+            "ast.txt",
+            "ir.txt",
+            "ir-opt.txt",
+            "passes.txt",
+            "0.spv",
+            "0.spvasm",
+            "module.json",
+        ] {
+            assert!(files.path("dumps").join(name).exists(), "{} is missing", name);
         }
-        Ok(ast) => Result::Ok(ast),
+        assert!(!files.path("main").exists());
     }
-}
 
-fn report_errors(src: &str, location: &str, messages: &[codespan_reporting::Diagnostic]) {
-    let mut map = CodeMap::new();
-    let src2 = src.to_owned();
-    map.add_filemap(location.to_owned().into(), src2);
-    use codespan_reporting::termcolor::StandardStream;
-    let writer = StandardStream::stderr(codespan_reporting::termcolor::ColorChoice::Auto);
-    for message in messages {
-        let wr = &mut writer.lock();
-        codespan_reporting::emit(wr, &map, message).unwrap();
+    #[test]
+    fn shaders_of_source_backends_are_always_written() {
+        let files = TempFiles::new("process-backend", &[("main.octo", SINGLE_PASS)]);
+        let options = ProcessOptions {
+            backend: Backend::Glsl,
+            emit: vec![Emit::Ir],
+            ..ProcessOptions::default()
+        };
+        process(&files, options);
+        // default debug dir is source path without extension
+        let shader = std::fs::read_to_string(files.path("main").join("0.frag")).unwrap();
+        assert!(shader.starts_with("#version"));
+        assert!(files.path("main").join("ir.txt").exists());
+        assert!(!files.path("main").join("ast.txt").exists());
+    }
+
+    const UNFORMATTED: &str = "pipeline main(color:vec4)->(vec4){return color*0.5;}";
+    const FORMATTED: &str = "pipeline main(color: vec4) -> (vec4) {\n    return color * 0.5;\n}\n";
+
+    #[test]
+    fn check_leaves_unformatted_file_untouched() {
+        let files = TempFiles::new("fmt-check", &[("main.octo", UNFORMATTED), ("done.octo", FORMATTED)]);
+        assert!(format_file(&file(&files, "main.octo"), true, MessageFormat::Json).is_err());
+        assert_eq!(std::fs::read_to_string(files.path("main.octo")).unwrap(), UNFORMATTED);
+        assert!(format_file(&file(&files, "done.octo"), true, MessageFormat::Json).is_ok());
+    }
+
+    #[test]
+    fn unformatted_file_is_rewritten() {
+        let files = TempFiles::new("fmt-write", &[("main.octo", UNFORMATTED)]);
+        assert!(format_file(&file(&files, "main.octo"), false, MessageFormat::Human).is_ok());
+        assert_eq!(std::fs::read_to_string(files.path("main.octo")).unwrap(), FORMATTED);
+        assert!(format_file(&file(&files, "main.octo"), true, MessageFormat::Human).is_ok());
     }
 }
@@ -1,21 +1,130 @@
-use octo::process_file;
+use std::path::PathBuf;
+
+use octo::{format_file, process_file, run_file, Backend, Emit, MessageFormat, ModuleFormat, ProcessOptions};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 struct Parameters {
-    no_test: bool,
+    /// Format of shaders written to debug directory: spirv, glsl, wgsl or hlsl
+    #[structopt(long = "backend", default_value = "spirv")]
+    backend: Backend,
+    /// Path of compiled module, allowed with single input file
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// Artifacts written to debug directory: ast, ir, ir-opt, passes, spirv, spirv-asm, json
+    #[structopt(long = "emit", raw(use_delimiter = "true"))]
+    emit: Vec<Emit>,
+    /// Directory of emitted artifacts, input path without extension by default
+    #[structopt(long = "debug-dir", parse(from_os_str))]
+    debug_dir: Option<PathBuf>,
+    /// Serialization of compiled module: json or bin
+    #[structopt(long = "format", default_value = "json")]
+    format: ModuleFormat,
+    /// Format of diagnostics: human (stderr) or json (one object per line on stdout)
+    #[structopt(long = "message-format", default_value = "human")]
+    message_format: MessageFormat,
+    /// Unrolls loops with constant trip count, up to given number of operations per loop
+    #[structopt(long = "unroll-loops")]
+    unroll_loops: Option<usize>,
+    /// Prints only errors
+    #[structopt(short = "q", long = "quiet")]
+    quiet: bool,
+    /// Increases logging verbosity, may be repeated
+    #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
+    verbose: usize,
     path: Vec<String>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Runs pipeline on CPU over image files
+    #[structopt(name = "run")]
+    Run {
+        path: String,
+        /// Pipeline argument, as name=file
+        #[structopt(long = "input", parse(try_from_str = "parse_assignment"))]
+        inputs: Vec<(String, String)>,
+        /// Uniform value, as name=value (components of vectors separated with commas)
+        #[structopt(long = "uniform", parse(try_from_str = "parse_assignment"))]
+        uniforms: Vec<(String, String)>,
+        /// Result file, given once for every pipeline result
+        #[structopt(short = "o", long = "output")]
+        outputs: Vec<String>,
+    },
+    /// Rewrites files in canonical style
+    #[structopt(name = "fmt")]
+    Fmt {
+        /// Only checks formatting, fails if any file would change
+        #[structopt(long = "check")]
+        check: bool,
+        paths: Vec<String>,
+    },
+}
+
+fn parse_assignment(text: &str) -> Result<(String, String), String> {
+    match text.find('=') {
+        Some(pos) => Result::Ok((text[..pos].to_owned(), text[pos + 1..].to_owned())),
+        None => Result::Err(format!("expected name=value, got \"{}\"", text)),
+    }
+}
+
+fn process_options(opt: &Parameters) -> ProcessOptions {
+    ProcessOptions {
+        backend: opt.backend,
+        output: opt.output.clone(),
+        debug_dir: opt.debug_dir.clone(),
+        emit: opt.emit.clone(),
+        format: opt.format,
+        report_warnings: !opt.quiet,
+        message_format: opt.message_format,
+        unroll_loops: opt.unroll_loops,
+    }
 }
 
 fn main() {
-    stderrlog::new().verbosity(4).init().unwrap();
-    println!("lol");
+    let mut opt = Parameters::from_args();
+
+    // warnings are shown by default
+    stderrlog::new()
+        .quiet(opt.quiet)
+        .verbosity(opt.verbose + 1)
+        .init()
+        .unwrap();
+
+    match opt.command.take() {
+        Some(Command::Run {
+            path,
+            inputs,
+            uniforms,
+            outputs,
+        }) => {
+            let err = run_file(&path, &inputs, &uniforms, &outputs, opt.message_format).is_err();
+            if err {
+                log::error!("Running {} failed", path);
+            }
+            std::process::exit(if err { 1 } else { 0 });
+        }
+        Some(Command::Fmt { check, paths }) => {
+            let mut err = false;
+            for path in paths {
+                err |= format_file(&path, check, opt.message_format).is_err();
+            }
+            std::process::exit(if err { 1 } else { 0 });
+        }
+        None => {}
+    }
 
-    let opt = Parameters::from_args();
+    if opt.output.is_some() && opt.path.len() > 1 {
+        log::error!("Output path can be given only with single input file");
+        std::process::exit(1);
+    }
+    let options = process_options(&opt);
 
     let mut err = false;
     for file in opt.path {
-        match process_file(&file) {
+        match process_file(&file, &options) {
             Result::Ok(()) => {}
             Result::Err(()) => {
                 log::error!("Compilation of {} failed", file);
@@ -27,3 +136,45 @@ fn main() {
 
     std::process::exit(if err { 1 } else { 0 });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> ProcessOptions {
+        let opt = Parameters::from_iter(std::iter::once("octo").chain(args.iter().cloned()));
+        assert!(opt.command.is_none());
+        process_options(&opt)
+    }
+
+    #[test]
+    fn defaults_write_json_module_next_to_source() {
+        let options = options(&["main.octo"]);
+        assert_eq!(options.backend, Backend::Spirv);
+        assert_eq!(options.output, None);
+        assert_eq!(options.debug_dir, None);
+        assert!(options.emit.is_empty());
+        assert_eq!(options.format, ModuleFormat::Json);
+        assert!(options.report_warnings);
+    }
+
+    #[test]
+    fn emit_takes_comma_separated_lists() {
+        let options = options(&["main.octo", "--emit", "ast,ir-opt", "--emit", "spirv-asm"]);
+        assert_eq!(options.emit, vec![Emit::Ast, Emit::IrOpt, Emit::SpirvAsm]);
+    }
+
+    #[test]
+    fn output_paths_and_format_are_passed() {
+        let options = options(&["main.octo", "-o", "out/main.bin", "--debug-dir", "dumps", "--format", "bin"]);
+        assert_eq!(options.output, Some(PathBuf::from("out/main.bin")));
+        assert_eq!(options.debug_dir, Some(PathBuf::from("dumps")));
+        assert_eq!(options.format, ModuleFormat::Bin);
+    }
+
+    #[test]
+    fn quiet_hides_warnings() {
+        assert!(!options(&["-q", "main.octo"]).report_warnings);
+        assert!(!options(&["main.octo", "--quiet"]).report_warnings);
+    }
+}
@@ -2,11 +2,12 @@ use parser::ast::*;
 use std::cell::RefCell;
 use std::clone::Clone as _;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Function {
-    name: String,
-    arguments: Vec<Variable>,
-    results: Vec<Type>,
+    pub name: String,
+    pub span: Span<ByteIndex>,
+    pub arguments: Vec<Variable>,
+    pub results: Vec<Type>,
 }
 
 #[derive(Debug, Clone)]
@@ -17,24 +18,38 @@ pub struct Variable {
     pub used: bool,
 }
 
+/// Variable mentioned at span, definitions are references too.
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub span: Span<ByteIndex>,
+    pub variable: Variable,
+}
+
 #[derive(Debug)]
 pub struct Scope<'a> {
     pub variables: RefCell<Vec<Variable>>,
+    functions: Vec<Function>,
     parent: Option<&'a Scope<'a>>,
+    // filled only in global scope
+    references: RefCell<Vec<Reference>>,
 }
 
 impl<'a> Scope<'a> {
     pub fn global<'b>() -> Scope<'b> {
         Scope {
             variables: RefCell::new(vec![]),
+            functions: vec![],
             parent: None,
+            references: RefCell::new(vec![]),
         }
     }
 
     pub fn child_scope(&self) -> Scope {
         Scope {
             variables: RefCell::new(vec![]),
+            functions: vec![],
             parent: Some(self),
+            references: RefCell::new(vec![]),
         }
     }
     pub fn variable_exists(&self, name: &str) -> Option<Span<ByteIndex>> {
@@ -57,30 +72,55 @@ impl<'a> Scope<'a> {
             Some(span) => return Result::Err(span),
             None => {}
         };
-        self.variables.borrow_mut().push(Variable {
+        let variable = Variable {
             name: name.to_owned(),
             typ,
             span,
             used: false,
+        };
+        self.add_reference(Reference {
+            span,
+            variable: variable.clone(),
         });
+        self.variables.borrow_mut().push(variable);
         Result::Ok(())
     }
 
-    pub fn use_variable(&self, name: &str) -> Option<Type> {
+    /// Marks variable as used at given span and returns its type.
+    pub fn use_variable(&self, name: &str, span: Span<ByteIndex>) -> Option<Type> {
         let mut borr = self.variables.borrow_mut();
         let variable = borr.iter_mut().find(|x| x.name == name);
         match variable {
             None => match self.parent {
                 None => None,
-                Some(parent) => parent.use_variable(name),
+                Some(parent) => parent.use_variable(name, span),
             },
             Some(x) => {
                 x.used = true;
+                self.add_reference(Reference {
+                    span,
+                    variable: x.clone(),
+                });
                 Some(x.typ.clone())
             }
         }
     }
 
+    fn add_reference(&self, reference: Reference) {
+        match self.parent {
+            None => self.references.borrow_mut().push(reference),
+            Some(parent) => parent.add_reference(reference),
+        }
+    }
+
+    /// All definitions and uses of variables seen so far in this scope tree.
+    pub fn references(&self) -> Vec<Reference> {
+        match self.parent {
+            None => self.references.borrow().clone(),
+            Some(parent) => parent.references(),
+        }
+    }
+
     pub fn unused_variables(&self) -> Vec<Variable> {
         let v = self.variables.borrow();
         let vars = v.iter().filter(|x| x.used == false).map(|x| x.clone());
@@ -94,11 +134,35 @@ impl<'a> Scope<'a> {
         }
     }
 
-    //    pub fn add_function(&mut self, func: &GpuFunction) {
-    //        self.functions.push(Function{
-    //            name: func.name.val.to_owned(),
-    //            arguments: func.arguments.clone(),
-    //            results: func.results.iter().map(|x| x.typ.clone()).collect(),
-    //        });
-    //    }
+    pub fn add_function(&mut self, func: &GpuFunction) -> Result<(), Span<ByteIndex>> {
+        if let Some(function) = self.find_function(&func.name.val) {
+            return Result::Err(function.span);
+        }
+        self.functions.push(Function {
+            name: func.name.val.to_owned(),
+            span: func.name.span,
+            arguments: func
+                .arguments
+                .iter()
+                .map(|x| Variable {
+                    name: x.identifier.val.to_owned(),
+                    span: x.identifier.span,
+                    typ: x.typ,
+                    used: false,
+                })
+                .collect(),
+            results: vec![func.result.val],
+        });
+        Result::Ok(())
+    }
+
+    pub fn find_function(&self, name: &str) -> Option<Function> {
+        match self.functions.iter().find(|x| x.name == name) {
+            None => match self.parent {
+                None => None,
+                Some(parent) => parent.find_function(name),
+            },
+            Some(x) => Some(x.clone()),
+        }
+    }
 }
@@ -24,7 +24,8 @@ impl ErrorWrap {
 
 impl From<WarningWrap> for Diagnostic {
     fn from(w: WarningWrap) -> Diagnostic {
-        match w.0 {
+        let code = w.0.code();
+        let diagnostic = match w.0 {
             SemanticWarning::NotUsedArgument(span, name) => {
                 Diagnostic::new_warning(format!("Argument \"{}\" is not used.", name)).with_label(
                     codespan_reporting::Label::new_primary(span)
@@ -37,13 +38,15 @@ impl From<WarningWrap> for Diagnostic {
                         .with_message("variable defined here"),
                 )
             }
-        }
+        };
+        diagnostic.with_code(code)
     }
 }
 
 impl From<ErrorWrap> for Diagnostic {
     fn from(w: ErrorWrap) -> Diagnostic {
-        match w.0 {
+        let code = w.0.code();
+        let diagnostic = match w.0 {
             SemanticError::UndefinedIdentifier(span, name) => {
                 Diagnostic::new_error(format!("Unknown variable \"{}\"", name)).with_label(
                     codespan_reporting::Label::new_primary(span)
@@ -127,8 +130,68 @@ impl From<ErrorWrap> for Diagnostic {
                     codespan_reporting::Label::new_primary(expression_span).with_message(format!("Expression has type {}", type_name))
                 )
             }
+            SemanticError::FunctionRedefinition(name, sp_old, sp_new) => {
+                Diagnostic::new_error(format!("Function redefinition: \"{}\"", name))
+                    .with_label(
+                        codespan_reporting::Label::new_primary(sp_old)
+                            .with_message("Previously defined here"),
+                    )
+                    .with_label(
+                        codespan_reporting::Label::new_primary(sp_new)
+                            .with_message("Redeclared here"),
+                    )
+            }
+            SemanticError::RecursiveFunction(name, span) => {
+                Diagnostic::new_error(format!(
+                    "Function {} calls itself. Recursion is not supported", name
+                ))
+                .with_label(
+                    codespan_reporting::Label::new_primary(span).with_message("Function defined here")
+                )
+            }
+            SemanticError::MisplacedReturn(span) => {
+                Diagnostic::new_error(format!(
+                    "Return has to be the last statement of a function"
+                ))
+                .with_label(
+                    codespan_reporting::Label::new_primary(span).with_message("Return found here")
+                )
+            }
+            SemanticError::UnexpectedTuple(span) => {
+                Diagnostic::new_error(format!(
+                    "Tuples can only be returned from pipeline"
+                ))
+                .with_label(
+                    codespan_reporting::Label::new_primary(span).with_message("Tuple found here")
+                )
+            }
+            SemanticError::UnknownEdgeMode(name, span) => {
+                Diagnostic::new_error(format!(
+                    "Unknown edge mode {}, expected clamp, mirror, repeat or zero", name
+                ))
+                .with_label(
+                    codespan_reporting::Label::new_primary(span).with_message("Edge mode of shift")
+                )
+            }
+            SemanticError::NonConstantScale(span) => {
+                Diagnostic::new_error(format!(
+                    "Scale factor has to be known at compile time"
+                ))
+                .with_label(
+                    codespan_reporting::Label::new_primary(span).with_message("Factor depends on pipeline inputs")
+                )
+            }
+            SemanticError::ScaleOutOfRange(span) => {
+                Diagnostic::new_error(format!(
+                    "Scale factor has to be greater than zero and at most one"
+                ))
+                .with_label(
+                    codespan_reporting::Label::new_primary(span).with_message("Factor of scale")
+                )
+            }
             _ => Diagnostic::new_error(format!("error is not implemented...")),
-        }
+        };
+        diagnostic.with_code(code)
     }
 }
 //UnusedArgument,
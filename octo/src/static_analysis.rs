@@ -1,9 +1,9 @@
-use super::ast::Pipeline as IncomingIR;
-use super::ast::Pipeline as OutgoingIR;
+use super::ast::Program as IncomingIR;
+use super::ast::Program as OutgoingIR;
 use errors::{SemanticError, SemanticWarning, Sp};
-use parser::ast::{Expression, Statement, Spanned, ValueStorage};
+use parser::ast::{Block, EdgeMode, Expression, GpuFunction, Pipeline, Statement, Spanned, ValueStorage};
 
-use super::semantics::env::Scope;
+use super::semantics::env::{Reference, Scope};
 use parser::ast::Type;
 use lazy_static::lazy_static;
 
@@ -27,64 +27,487 @@ impl Diagnostics {
     }
 }
 
-pub fn analyze(pip: IncomingIR) -> (Option<OutgoingIR>, Diagnostics) {
-    let mut pip = pip;
-    let global_scope = Scope::global();
+pub fn analyze(program: IncomingIR) -> (Option<OutgoingIR>, Diagnostics) {
+    let (program, diagnostics, _) = analyze_with_references(program);
+    (program, diagnostics)
+}
 
-    let mut program_scope = Scope::child_scope(&global_scope);
+/// Also returns every definition and use of variables, for editor tooling.
+pub fn analyze_with_references(program: IncomingIR) -> (Option<OutgoingIR>, Diagnostics, Vec<Reference>) {
+    let mut program = program;
+    let mut global_scope = Scope::global();
 
     let mut errs = Diagnostics {
         errors: vec![],
         warnings: vec![],
     };
 
-    // analyze argument and result types here...
-    // and uniforms types...
-    for arg in &pip.arguments {
+    // constants are visible in functions and pipeline
+    for constant in &mut program.constants {
+        let typ = analyze_expression(&mut constant.value, &mut errs, &global_scope);
+        if let Err(span) = global_scope.create_variable(&constant.name.val, typ, constant.name.span) {
+            errs.err(SemanticError::VariableRedefinition(
+                constant.name.val.clone(),
+                span,
+                constant.name.span,
+            ));
+        }
+    }
+
+    for function in &program.functions {
+        if let Err(span) = global_scope.add_function(function) {
+            errs.err(SemanticError::FunctionRedefinition(
+                function.name.val.clone(),
+                span,
+                function.name.span,
+            ));
+        }
+    }
 
+    for function in &mut program.functions {
+        analyze_function(function, &mut errs, &global_scope);
+    }
+
+    check_recursion(&program.functions, &mut errs);
+    let factor_arguments = check_function_scale_factors(&program.functions, &mut errs);
+
+    if let Some(pip) = &mut program.pipeline {
+        analyze_pipeline(pip, &mut errs, &global_scope);
+        check_scale_factors(pip, &factor_arguments, &mut errs);
+    }
 
-        program_scope
-            .create_variable(&arg.identifier.val, arg.typ.clone(), arg.identifier.span)
-            .unwrap();
+    let references = global_scope.references();
+    if errs.errors.len() > 0 {
+        (Option::None, errs, references)
+    } else {
+        (Option::Some(program), errs, references)
     }
+}
+
+fn analyze_pipeline(pip: &mut Pipeline, errs: &mut Diagnostics, global_scope: &Scope) {
+    let mut program_scope = Scope::child_scope(global_scope);
 
+    // analyze argument and result types here...
+    // and uniforms types...
+    let mut inputs: Vec<_> = pip.arguments.iter().collect();
     if pip.uniforms.is_some(){
-        for uniform in &pip.uniforms.as_ref().unwrap().entries {
-            program_scope
-                .create_variable(&uniform.identifier.val, uniform.typ.clone(), uniform.identifier.span)
-                .unwrap();
+        inputs.extend(pip.uniforms.as_ref().unwrap().entries.iter());
+    }
+
+    for input in inputs {
+        // global constants may clash with inputs
+        if let Err(span) = program_scope.create_variable(&input.identifier.val, input.typ.clone(), input.identifier.span) {
+            errs.err(SemanticError::VariableRedefinition(
+                input.identifier.val.clone(),
+                span,
+                input.identifier.span,
+            ));
         }
     }
 
     let mut return_value = None;
 
     for statement in &mut pip.block.statements {
-        analyze_statement(statement, &mut errs, &mut program_scope, &mut return_value);
+        analyze_statement(statement, errs, &mut program_scope, &mut return_value);
     }
 
-    if return_value.is_none() && pip.results.len()>0{
-        let last = pip.results.last().unwrap();
+    match return_value {
+        None => {
+            if let Some(last) = pip.results.last() {
+                errs.err(SemanticError::NotAssignedReturnVariable(last.span, last.val.to_string()));
+            }
+        }
+        Some((types, span)) => {
+            let results: Vec<_> = pip.results.iter().map(|x| x.val).collect();
+            check_returned_types(&results, &types, span, errs);
+        }
+    }
 
-        errs.err(SemanticError::NotAssignedReturnVariable(last.span, last.val.to_string()));
+    // constants from global scope are often shared between files, so they are not reported
+    for var in program_scope.variables.borrow().iter().filter(|x| !x.used) {
+        errs.warning(SemanticWarning::UnusedVariable(var.span, var.name.clone()));
     }
+}
+
+fn analyze_function(function: &mut GpuFunction, diagnostics: &mut Diagnostics, global_scope: &Scope) {
+    let mut function_scope = Scope::child_scope(global_scope);
 
-    if pip.results.len()>1 || pip.results.last().unwrap().val != return_value.unwrap().0 {
-        let ret = return_value.unwrap();
-        errs.err(SemanticError::TypeMismatch(ret.1, ret.0.to_string(), pip.results.last().unwrap().val.to_string()));
+    for arg in &function.arguments {
+        if let Err(span) = function_scope.create_variable(&arg.identifier.val, arg.typ, arg.identifier.span) {
+            diagnostics.err(SemanticError::VariableRedefinition(
+                arg.identifier.val.clone(),
+                span,
+                arg.identifier.span,
+            ));
+        }
     }
 
-    for var in program_scope.unused_variables() {
-        errs.warning(SemanticWarning::UnusedVariable(var.span, var.name));
+    let mut return_value = None;
+    for statement in &mut function.block.statements {
+        analyze_statement(statement, diagnostics, &mut function_scope, &mut return_value);
     }
 
-    if errs.errors.len() > 0 {
-        (Option::None, errs)
+    find_misplaced_returns(&function.block, true, diagnostics);
+
+    match return_value {
+        None => {
+            diagnostics.err(SemanticError::NotAssignedReturnVariable(
+                function.result.span,
+                function.result.val.to_string(),
+            ));
+        }
+        Some((types, span)) => {
+            check_returned_types(&[function.result.val], &types, span, diagnostics);
+        }
+    }
+
+    // arguments are created first in function scope
+    let arguments_count = function.arguments.len();
+    for (id, var) in function_scope.variables.borrow().iter().enumerate() {
+        if var.used {
+            continue;
+        }
+        if id < arguments_count {
+            diagnostics.warning(SemanticWarning::NotUsedArgument(var.span, var.name.clone()));
+        } else {
+            diagnostics.warning(SemanticWarning::UnusedVariable(var.span, var.name.clone()));
+        }
+    }
+}
+
+// unknown types were already reported, so they match anything
+fn check_returned_types(expected: &[Type], returned: &[Type], span: Sp, diagnostics: &mut Diagnostics) {
+    let matches = expected.len() == returned.len()
+        && expected.iter().zip(returned.iter()).all(|(x, y)| match y {
+            Type::Unknown => true,
+            _ => x == y,
+        });
+    if !matches {
+        diagnostics.err(SemanticError::TypeMismatch(
+            span,
+            types_to_string(expected),
+            types_to_string(returned),
+        ));
+    }
+}
+
+fn types_to_string(types: &[Type]) -> String {
+    if types.len() == 1 {
+        types[0].to_string()
     } else {
-        (Option::Some(pip), errs)
+        format!("({})", types.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", "))
+    }
+}
+
+fn find_misplaced_returns(block: &Block, function_body: bool, diagnostics: &mut Diagnostics) {
+    let last = block.statements.len();
+    for (id, statement) in block.statements.iter().enumerate() {
+        match statement {
+            Statement::Return(exp) => {
+                if !function_body || id + 1 != last {
+                    diagnostics.err(SemanticError::MisplacedReturn(exp.span()));
+                }
+            }
+            Statement::For(_, _, _, block) => find_misplaced_returns(block, false, diagnostics),
+            Statement::IfElse(_, block1, block2) => {
+                find_misplaced_returns(block1, false, diagnostics);
+                if let Some(block2) = block2 {
+                    find_misplaced_returns(block2, false, diagnostics);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Size of texture written by scale can't depend on pipeline inputs.
+// Inputs taint variables assigned from them, also inside branches and loops conditioned on them.
+// Arguments of functions used as factors are checked where functions are called.
+fn check_scale_factors(pip: &Pipeline, factor_arguments: &HashMap<String, Vec<usize>>, diagnostics: &mut Diagnostics) {
+    let mut inputs: HashSet<String> = pip.arguments.iter().map(|x| x.identifier.val.clone()).collect();
+    if let Some(uniforms) = &pip.uniforms {
+        inputs.extend(uniforms.entries.iter().map(|x| x.identifier.val.clone()));
+    }
+    let varying = varying_variables(&pip.block, inputs);
+    for factor in block_scale_factors(&pip.block, factor_arguments) {
+        if expression_varying(factor, &varying) {
+            diagnostics.err(SemanticError::NonConstantScale(factor.span()));
+        }
+    }
+}
+
+// Positions of arguments of every function which end up as scale factors, also through calls of other functions.
+// Factors varying regardless of arguments are reported.
+fn check_function_scale_factors(functions: &[GpuFunction], diagnostics: &mut Diagnostics) -> HashMap<String, Vec<usize>> {
+    let mut factor_arguments: HashMap<String, Vec<usize>> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for function in functions {
+            let intrinsic = varying_variables(&function.block, HashSet::new());
+            let factors = block_scale_factors(&function.block, &factor_arguments);
+            for (id, argument) in function.arguments.iter().enumerate() {
+                let inputs = std::iter::once(argument.identifier.val.clone()).collect();
+                let varying = varying_variables(&function.block, inputs);
+                let used = factors
+                    .iter()
+                    .any(|x| expression_varying(x, &varying) && !expression_varying(x, &intrinsic));
+                let positions = factor_arguments.entry(function.name.val.clone()).or_insert_with(Vec::new);
+                if used && !positions.contains(&id) {
+                    positions.push(id);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    for function in functions {
+        let varying = varying_variables(&function.block, HashSet::new());
+        for factor in block_scale_factors(&function.block, &factor_arguments) {
+            if expression_varying(factor, &varying) {
+                diagnostics.err(SemanticError::NonConstantScale(factor.span()));
+            }
+        }
+    }
+    factor_arguments
+}
+
+// literal factor, possibly negated, is checked before it's folded
+fn check_scale_range(factor: &Expression, diagnostics: &mut Diagnostics) {
+    let value = match factor {
+        Expression::Literal(super::ast::Literal::Float(x)) => x.val,
+        Expression::Negation(exp) => match &**exp {
+            Expression::Literal(super::ast::Literal::Float(x)) => -x.val,
+            _ => return,
+        },
+        _ => return,
+    };
+    if value <= 0.0 || value > 1.0 {
+        diagnostics.err(SemanticError::ScaleOutOfRange(factor.span()));
+    }
+}
+
+// loops carry values back to earlier statements
+fn varying_variables(block: &Block, inputs: HashSet<String>) -> HashSet<String> {
+    let mut varying = inputs;
+    loop {
+        let count = varying.len();
+        block_varying(block, false, &mut varying);
+        if varying.len() == count {
+            return varying;
+        }
+    }
+}
+
+fn block_scale_factors<'a>(block: &'a Block, factor_arguments: &HashMap<String, Vec<usize>>) -> Vec<&'a Expression> {
+    let mut factors = vec![];
+    for statement in &block.statements {
+        statement_scale_factors(statement, factor_arguments, &mut factors);
+    }
+    factors
+}
+
+fn block_varying(block: &Block, conditional: bool, varying: &mut HashSet<String>) {
+    for statement in &block.statements {
+        statement_varying(statement, conditional, varying);
+    }
+}
+
+fn statement_varying(statement: &Statement, conditional: bool, varying: &mut HashSet<String>) {
+    match statement {
+        Statement::Assignment(storage, exp) => {
+            if conditional || expression_varying(exp, varying) {
+                let name = match storage {
+                    ValueStorage::Creation(name) => name,
+                    ValueStorage::Existing(path) => &path[0],
+                };
+                varying.insert(name.val.clone());
+            }
+        }
+        Statement::For(init, cond, step, block) => {
+            statement_varying(init, conditional, varying);
+            let conditional = conditional || expression_varying(cond, varying);
+            statement_varying(step, conditional, varying);
+            block_varying(block, conditional, varying);
+        }
+        Statement::IfElse(cond, block1, block2) => {
+            let conditional = conditional || expression_varying(cond, varying);
+            block_varying(block1, conditional, varying);
+            if let Some(block2) = block2 {
+                block_varying(block2, conditional, varying);
+            }
+        }
+        Statement::Expression(_) | Statement::Return(_) => {}
+    }
+}
+
+// samples of textures differ between pixels, functions depend only on their arguments
+fn expression_varying(exp: &Expression, varying: &HashSet<String>) -> bool {
+    use Expression::*;
+    match exp {
+        Variable(var) => varying.contains(&var.identifier.val),
+        Literal(_) => false,
+        Shift(..) | Scale(..) => true,
+        Negation(exp) | Not(exp) | Access(exp, _) => expression_varying(exp, varying),
+        Invocation(_, args) => args.iter().any(|x| expression_varying(x, varying)),
+        Mul(left, right) | Div(left, right) | Add(left, right) | Sub(left, right)
+        | Less(left, right) | LessEqual(left, right) | More(left, right)
+        | MoreEqual(left, right) | Equals(left, right) | NotEquals(left, right)
+        | And(left, right) | Or(left, right) => {
+            expression_varying(left, varying) || expression_varying(right, varying)
+        }
+        Tuple(values) => values.iter().any(|x| expression_varying(x, varying)),
+    }
+}
+
+// factors of scales and arguments passed as factors to functions
+fn statement_scale_factors<'a>(
+    statement: &'a Statement,
+    factor_arguments: &HashMap<String, Vec<usize>>,
+    factors: &mut Vec<&'a Expression>,
+) {
+    match statement {
+        Statement::Expression(exp) | Statement::Assignment(_, exp) | Statement::Return(exp) => {
+            expression_scale_factors(exp, factor_arguments, factors)
+        }
+        Statement::For(init, cond, step, block) => {
+            statement_scale_factors(init, factor_arguments, factors);
+            expression_scale_factors(cond, factor_arguments, factors);
+            statement_scale_factors(step, factor_arguments, factors);
+            for statement in &block.statements {
+                statement_scale_factors(statement, factor_arguments, factors);
+            }
+        }
+        Statement::IfElse(cond, block1, block2) => {
+            expression_scale_factors(cond, factor_arguments, factors);
+            for statement in block1.statements.iter().chain(block2.iter().flat_map(|x| x.statements.iter())) {
+                statement_scale_factors(statement, factor_arguments, factors);
+            }
+        }
+    }
+}
+
+fn expression_scale_factors<'a>(
+    exp: &'a Expression,
+    factor_arguments: &HashMap<String, Vec<usize>>,
+    factors: &mut Vec<&'a Expression>,
+) {
+    use Expression::*;
+    match exp {
+        Variable(_) | Literal(_) => {}
+        Scale(value, factor) => {
+            expression_scale_factors(value, factor_arguments, factors);
+            expression_scale_factors(factor, factor_arguments, factors);
+            factors.push(factor);
+        }
+        Negation(exp) | Not(exp) | Access(exp, _) => expression_scale_factors(exp, factor_arguments, factors),
+        Invocation(name, args) => {
+            for arg in args {
+                expression_scale_factors(arg, factor_arguments, factors);
+            }
+            if let Some(positions) = factor_arguments.get(&name.val) {
+                factors.extend(positions.iter().filter_map(|x| args.get(*x)).map(|x| &**x));
+            }
+        }
+        Tuple(args) => {
+            for arg in args {
+                expression_scale_factors(arg, factor_arguments, factors);
+            }
+        }
+        Mul(left, right) | Div(left, right) | Add(left, right) | Sub(left, right)
+        | Less(left, right) | LessEqual(left, right) | More(left, right)
+        | MoreEqual(left, right) | Equals(left, right) | NotEquals(left, right)
+        | And(left, right) | Or(left, right) | Shift(left, right, _) => {
+            expression_scale_factors(left, factor_arguments, factors);
+            expression_scale_factors(right, factor_arguments, factors);
+        }
+    }
+}
+
+fn check_recursion(functions: &Vec<GpuFunction>, diagnostics: &mut Diagnostics) {
+    let mut calls: HashMap<String, Vec<String>> = HashMap::new();
+    for function in functions {
+        let mut invoked = vec![];
+        block_invocations(&function.block, &mut invoked);
+        calls.insert(function.name.val.clone(), invoked);
+    }
+
+    for function in functions {
+        let mut visited = HashSet::new();
+        let mut to_check = calls[&function.name.val].clone();
+        while let Some(name) = to_check.pop() {
+            if name == function.name.val {
+                diagnostics.err(SemanticError::RecursiveFunction(
+                    function.name.val.clone(),
+                    function.name.span,
+                ));
+                break;
+            }
+            if visited.insert(name.clone()) {
+                if let Some(invoked) = calls.get(&name) {
+                    to_check.extend(invoked.iter().cloned());
+                }
+            }
+        }
+    }
+}
+
+fn block_invocations(block: &Block, invoked: &mut Vec<String>) {
+    for statement in &block.statements {
+        statement_invocations(statement, invoked);
     }
 }
 
-fn analyze_statement(stat: &mut Statement, diagnostics: &mut Diagnostics, scope: &mut Scope, return_value: &mut Option<(Type, super::ast::AstSpan)>) {
+fn statement_invocations(statement: &Statement, invoked: &mut Vec<String>) {
+    match statement {
+        Statement::Expression(exp) => expression_invocations(exp, invoked),
+        Statement::Assignment(_, exp) => expression_invocations(exp, invoked),
+        Statement::Return(exp) => expression_invocations(exp, invoked),
+        Statement::For(init, cond, step, block) => {
+            statement_invocations(init, invoked);
+            expression_invocations(cond, invoked);
+            statement_invocations(step, invoked);
+            block_invocations(block, invoked);
+        }
+        Statement::IfElse(cond, block1, block2) => {
+            expression_invocations(cond, invoked);
+            block_invocations(block1, invoked);
+            if let Some(block2) = block2 {
+                block_invocations(block2, invoked);
+            }
+        }
+    }
+}
+
+fn expression_invocations(exp: &Expression, invoked: &mut Vec<String>) {
+    use Expression::*;
+    match exp {
+        Variable(_) | Literal(_) => {}
+        Negation(exp) | Not(exp) | Access(exp, _) => expression_invocations(exp, invoked),
+        Invocation(name, args) => {
+            invoked.push(name.val.clone());
+            for arg in args {
+                expression_invocations(arg, invoked);
+            }
+        }
+        Mul(left, right) | Div(left, right) | Add(left, right) | Sub(left, right)
+        | Less(left, right) | LessEqual(left, right) | More(left, right)
+        | MoreEqual(left, right) | Equals(left, right) | NotEquals(left, right)
+        | And(left, right) | Or(left, right) | Shift(left, right, _) | Scale(left, right) => {
+            expression_invocations(left, invoked);
+            expression_invocations(right, invoked);
+        }
+        Tuple(values) => {
+            for value in values {
+                expression_invocations(value, invoked);
+            }
+        }
+    }
+}
+
+fn analyze_statement(stat: &mut Statement, diagnostics: &mut Diagnostics, scope: &mut Scope, return_value: &mut Option<(Vec<Type>, super::ast::AstSpan)>) {
     match stat {
         Statement::Expression(ex) => {
             analyze_expression(ex, diagnostics, scope);
@@ -119,11 +542,17 @@ fn analyze_statement(stat: &mut Statement, diagnostics: &mut Diagnostics, scope:
             }
         }
         Statement::Return(val) => {
-            // TODO: do something here to check returned type
             let sp = val.span();
-            
-            let typ = analyze_expression(val, diagnostics, scope);
-            *return_value = Some((typ,sp));
+
+            // tuple is allowed only here
+            let types = match &mut **val {
+                Expression::Tuple(values) => values
+                    .iter_mut()
+                    .map(|x| analyze_expression(x, diagnostics, scope))
+                    .collect(),
+                val => vec![analyze_expression(val, diagnostics, scope)],
+            };
+            *return_value = Some((types, sp));
         }
         Statement::For(stat, exp1, exp2, block) => {
             let mut block_scope = Scope::child_scope(&scope);
@@ -290,7 +719,7 @@ fn analyze_expression(exp: &mut Expression, diagnostics: &mut Diagnostics, scope
 
     match exp {
         Variable(variable) => {
-            let var = scope.use_variable(&variable.identifier.val);
+            let var = scope.use_variable(&variable.identifier.val, variable.identifier.span);
             match var {
                 None => {
                     diagnostics.err(SemanticError::UndefinedIdentifier(
@@ -306,7 +735,7 @@ fn analyze_expression(exp: &mut Expression, diagnostics: &mut Diagnostics, scope
             super::ast::Literal::Float(_) => Type::Float,
             super::ast::Literal::Int(_) => Type::Int,
         },
-        Negation(exp) => analyze_expression(exp, diagnostics, scope),
+        Negation(exp) | Not(exp) => analyze_expression(exp, diagnostics, scope),
         Mul(left, right) => analyze_mul_operation(left, right, scope, diagnostics, &ALLOWED_MUL_OPERATIONS),
         Div(left, right) => analyze_mul_operation(left, right, scope, diagnostics, &ALLOWED_DIV_OPERATIONS),
         Add(left, right) => analyze_binary_operation(left, right, scope, diagnostics),
@@ -377,7 +806,12 @@ fn analyze_expression(exp: &mut Expression, diagnostics: &mut Diagnostics, scope
             }
             Type::Bool
         }
-        Shift(val, vec) => {
+        Shift(val, vec, mode) => {
+            if let Some(mode) = mode {
+                if EdgeMode::from_name(&mode.val).is_none() {
+                    diagnostics.err(SemanticError::UnknownEdgeMode(mode.val.clone(), mode.span));
+                }
+            }
             let value_type = analyze_expression(val, diagnostics, scope);
             let vec_type = analyze_expression(vec, diagnostics, scope);
             if vec_type != Type::Vec2 {
@@ -394,7 +828,24 @@ fn analyze_expression(exp: &mut Expression, diagnostics: &mut Diagnostics, scope
             }
             value_type
         }
-        Scale(_left, _right)=> {Type::Unknown},
+        Scale(val, factor) => {
+            let value_type = analyze_expression(val, diagnostics, scope);
+            let factor_type = analyze_expression(factor, diagnostics, scope);
+            check_scale_range(factor, diagnostics);
+            if factor_type != Type::Float {
+                match factor_type {
+                    Type::Unknown => {}
+                    _ => {
+                        diagnostics.err(SemanticError::TypeMismatch(
+                            factor.span(),
+                            "Float".to_owned(),
+                            factor_type.to_string(),
+                        ));
+                    }
+                };
+            }
+            value_type
+        }
         Invocation(name, args) => {
 
             analyze_invocation(&name.val, name.span, args, diagnostics, scope)
@@ -402,6 +853,13 @@ fn analyze_expression(exp: &mut Expression, diagnostics: &mut Diagnostics, scope
         Access(val, field) => {
             analyze_access(val, field, diagnostics, scope)
         }
+        Tuple(values) => {
+            for value in values.iter_mut() {
+                analyze_expression(value, diagnostics, scope);
+            }
+            diagnostics.err(SemanticError::UnexpectedTuple(values[0].span()));
+            Type::Unknown
+        }
     }
 }
 
@@ -410,7 +868,7 @@ fn analyze_access_path(path: &Vec<Spanned<String>>, diagnostics: &mut Diagnostic
     //println!("{:#?}", path);
     let first = path[0].val.clone();
 
-    let variable = scope.use_variable(&first);
+    let variable = scope.use_variable(&first, path[0].span);
     let mut typ = match variable {
         Some(x) => {
             //println!("type of {} is {:?}", first, x);
@@ -600,6 +1058,21 @@ fn analyze_invocation(name: &str, name_span: Sp, args: &mut Vec<Box<Expression>>
         //println!("lolz");
         return match_constructor(name, name_span, &types, diagnostics, scope);
     }
+    if let Some(function) = scope.find_function(name) {
+        let matches = function.arguments.len() == types.len()
+            && function.arguments.iter().zip(types.iter()).all(|(arg, typ)| match typ {
+                Type::Unknown => true,
+                _ => arg.typ == *typ,
+            });
+        if !matches {
+            diagnostics.err(SemanticError::ArgumentsMismatch(
+                name.to_owned(),
+                name_span,
+                vec![function.arguments.iter().map(|x| x.typ.to_string()).collect()],
+            ));
+        }
+        return function.results[0];
+    }
     match match_prototype(name, &types) {
         Ok(val) => return val,
         Err(e) => {
@@ -624,6 +1097,214 @@ fn analyze_invocation(name: &str, name_span: Sp, args: &mut Vec<Box<Expression>>
         }
     }
 
-    // check user functions here
     Type::Unknown
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(src: &str) -> Vec<SemanticError> {
+        let program = match parser::parse(src, false) {
+            Result::Ok(x) => x,
+            Result::Err(_) => panic!("parsing failed"),
+        };
+        let (program, diagnostics) = analyze(program);
+        assert_eq!(program.is_none(), !diagnostics.errors.is_empty());
+        diagnostics.errors
+    }
+
+    fn pipeline_errors(statements: &str) -> Vec<SemanticError> {
+        errors(&format!(
+            "pipeline main(color: vec4) -> (vec4) {{ {} return color; }}",
+            statements
+        ))
+    }
+
+    fn has_unexpected_tuple(errors: &[SemanticError]) -> bool {
+        errors.iter().any(|x| match x {
+            SemanticError::UnexpectedTuple(_) => true,
+            _ => false,
+        })
+    }
+
+    #[test]
+    fn tuples_are_rejected_outside_of_return() {
+        let statements = [
+            "let t = (color, color);",
+            "let l = length((color, color));",
+            "let x = (color, color).x;",
+            "let n = -(color, color);",
+            "let s = shift((color, color), vec2(1.0, 0.0));",
+            "if ((color.x < 1.0, color.y < 1.0)) { color = color; }",
+            "for (let i = 0; (i < 3, i < 4); i = i + 1) { color = color; }",
+        ];
+        for statement in statements.iter() {
+            assert!(has_unexpected_tuple(&pipeline_errors(statement)), "{}", statement);
+        }
+    }
+
+    #[test]
+    fn nested_tuples_are_rejected() {
+        let errors = errors("pipeline main(color: vec4) -> (vec4, vec4) { return ((color, color), color); }");
+        assert!(has_unexpected_tuple(&errors));
+    }
+
+    #[test]
+    fn tuples_are_rejected_in_constants_and_functions() {
+        assert!(has_unexpected_tuple(&errors("let k = (1.0, 2.0);")));
+        // functions have single result
+        let errors = errors("gpu_fun pair(x: float) -> float { return (x, x); }");
+        assert!(errors.iter().any(|x| match x {
+            SemanticError::TypeMismatch(..) => true,
+            _ => false,
+        }));
+    }
+
+    #[test]
+    fn results_are_returned_as_tuple() {
+        let errors = errors("pipeline main(color: vec4) -> (vec4, float) { return (color, color.x); }");
+        assert!(errors.is_empty());
+    }
+
+    fn count(errors: &[SemanticError], matches: impl Fn(&SemanticError) -> bool) -> usize {
+        errors.iter().filter(|x| matches(x)).count()
+    }
+
+    #[test]
+    fn recursion_is_rejected() {
+        let errors = errors("gpu_fun f(x: float) -> float { return f(x); }");
+        assert_eq!(count(&errors, |x| match x {
+            SemanticError::RecursiveFunction(name, _) => name == "f",
+            _ => false,
+        }), 1);
+    }
+
+    #[test]
+    fn mutual_recursion_is_rejected() {
+        let errors = errors("
+            gpu_fun even(x: float) -> float { return odd(x - 1.0); }
+            gpu_fun odd(x: float) -> float { return even(x - 1.0); }
+            gpu_fun caller(x: float) -> float { return even(x); }
+        ");
+        let recursive: Vec<_> = errors
+            .iter()
+            .filter_map(|x| match x {
+                SemanticError::RecursiveFunction(name, _) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(recursive, vec!["even", "odd"]);
+    }
+
+    #[test]
+    fn shared_callees_are_not_recursion() {
+        let errors = errors("
+            gpu_fun d(x: float) -> float { return x * 2.0; }
+            gpu_fun b(x: float) -> float { return d(x); }
+            gpu_fun c(x: float) -> float { return d(x) + 1.0; }
+            gpu_fun a(x: float) -> float { return b(x) + c(x); }
+        ");
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn returns_have_to_end_function() {
+        let misplaced = |errors: &[SemanticError]| count(errors, |x| match x {
+            SemanticError::MisplacedReturn(_) => true,
+            _ => false,
+        });
+        let in_branch = errors("
+            gpu_fun f(x: float) -> float {
+                if (x < 0.0) {
+                    return 0.0;
+                }
+                return x;
+            }
+        ");
+        assert_eq!(misplaced(&in_branch), 1);
+        let in_loop = errors("
+            gpu_fun f(x: float) -> float {
+                for (let i = 0; i < 2; i = i + 1) {
+                    return x;
+                }
+                return x;
+            }
+        ");
+        assert_eq!(misplaced(&in_loop), 1);
+        let not_last = errors("
+            gpu_fun f(x: float) -> float {
+                return x;
+                x = x + 1.0;
+            }
+        ");
+        assert_eq!(misplaced(&not_last), 1);
+    }
+
+    #[test]
+    fn functions_are_defined_once() {
+        let errors = errors("
+            gpu_fun f(x: float) -> float { return x; }
+            gpu_fun f(x: vec2) -> vec2 { return x; }
+        ");
+        assert_eq!(count(&errors, |x| match x {
+            SemanticError::FunctionRedefinition(name, _, _) => name == "f",
+            _ => false,
+        }), 1);
+    }
+
+    fn non_constant_scales(errors: &[SemanticError]) -> usize {
+        count(errors, |x| match x {
+            SemanticError::NonConstantScale(_) => true,
+            _ => false,
+        })
+    }
+
+    #[test]
+    fn scale_factors_can_not_depend_on_inputs() {
+        assert_eq!(non_constant_scales(&pipeline_errors("let s = scale(color, color.x);")), 1);
+        // loop conditioned on input taints values assigned in it
+        let in_loop = "
+            let f = 0.5;
+            for (let i = 0.0; i < color.x; i = i + 1.0) {
+                f = f * 0.5;
+            }
+            let s = scale(color, f);
+        ";
+        assert_eq!(non_constant_scales(&pipeline_errors(in_loop)), 1);
+        assert_eq!(non_constant_scales(&pipeline_errors("let f = 0.25; let s = scale(color, f * 2.0);")), 0);
+    }
+
+    #[test]
+    fn factors_passed_to_functions_are_checked_at_calls() {
+        let errors = errors("
+            gpu_fun shrink(x: vec4, factor: float) -> vec4 { return scale(x, factor * 0.5); }
+            gpu_fun half(x: vec4, k: float) -> vec4 { return shrink(x, k); }
+            pipeline main(color: vec4) -> (vec4) {
+                return half(color, 0.5) + half(color, color.x) + shrink(color, length(color));
+            }
+        ");
+        assert_eq!(non_constant_scales(&errors), 2);
+    }
+
+    #[test]
+    fn varying_factors_in_functions_are_rejected() {
+        let errors = errors("
+            gpu_fun blur(x: vec4) -> vec4 { return scale(x, shift(x, vec2(1.0, 0.0)).x); }
+        ");
+        assert_eq!(non_constant_scales(&errors), 1);
+    }
+
+    #[test]
+    fn scale_factors_have_to_be_in_range() {
+        let out_of_range = |statement: &str| count(&pipeline_errors(statement), |x| match x {
+            SemanticError::ScaleOutOfRange(_) => true,
+            _ => false,
+        });
+        assert_eq!(out_of_range("let s = scale(color, 0.0);"), 1);
+        assert_eq!(out_of_range("let s = scale(color, -0.5);"), 1);
+        assert_eq!(out_of_range("let s = scale(color, 2.0);"), 1);
+        assert_eq!(out_of_range("let s = scale(color, 1.0);"), 0);
+        assert_eq!(out_of_range("let s = scale(color, 0.25);"), 0);
+    }
+}
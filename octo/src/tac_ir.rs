@@ -1,20 +1,32 @@
 mod code;
+mod emit_glsl;
+mod emit_hlsl;
 mod emit_ir_from_ast;
+mod emit_source;
 mod emit_spirv;
+mod emit_wgsl;
+mod interpreter;
 mod ir;
 mod optimalizations;
 mod split_passes;
 mod emit_builtins;
 mod special_builtins;
+#[cfg(test)]
+mod test_utils;
 mod utils;
 
 use super::ast;
 
 pub use emit_ir_from_ast::emit as emit_ir;
-pub use emit_spirv::emit_spirv;
+pub use emit_glsl::emit_glsl;
+pub use emit_hlsl::emit_hlsl;
+pub use emit_spirv::{disassemble, emit_spirv};
+pub use emit_wgsl::emit_wgsl;
 pub use optimalizations::*;
 pub use split_passes::split as split_passes;
-pub use split_passes::{PipelineDef, ShaderDef};
+pub use split_passes::{OutputTexture, PipelineDef, ShaderDef};
+pub use interpreter::{interpret, interpret_passes, Image};
+pub use ir::ConstantValue;
 
 use std::collections::HashMap;
 
@@ -104,7 +116,7 @@ pub fn emit_graph(code: &PipelineIR, path: &str) {
                 graph.add_edge(l, node_idx, "");
                 graph.add_edge(r, node_idx, "");
             }
-            Shift(l, r) => {
+            Shift(l, r, _) => {
                 let l = nodes[l];
                 let r = nodes[r];
                 graph.add_edge(l, node_idx, "");
@@ -142,10 +154,20 @@ pub fn emit_graph(code: &PipelineIR, path: &str) {
                 graph.add_edge(l, node_idx, "");
                 graph.add_edge(r, node_idx, "");
             }
+            Output(_, l) => {
+                let l = nodes[l];
+                graph.add_edge(l, node_idx, "");
+            }
             Sync(l) => {
                 let l = nodes[l];
                 graph.add_edge(l, node_idx, "");
             }
+            Scale(l, r) => {
+                let l = nodes[l];
+                let r = nodes[r];
+                graph.add_edge(l, node_idx, "");
+                graph.add_edge(r, node_idx, "factor");
+            }
             JumpIfElse(a, b, c) => {
                 let cond = nodes[a];
                 let tru = nodes[b];
@@ -1,4 +1,5 @@
 
+use super::ast;
 use super::ir::{Address, ConstantValue, Op, Operation, PhiRecord, PipelineIR, replace};
 
 use std::collections::HashMap;
@@ -46,6 +47,7 @@ pub struct Code {
     pub code: Vec<Op>,
     variables: Vec<HashMap<String, Address>>,
     constants: HashMap<Address, ConstantValue>,
+    functions: HashMap<String, ast::GpuFunction>,
 
     phi_observer: Option<PhiObserver>,
     synchronized_nodes: HashMap<Address, Address>,
@@ -66,6 +68,7 @@ impl Code {
             code: vec![],
             variables: vec![HashMap::new()],
             constants: HashMap::new(),
+            functions: HashMap::new(),
             counter: 0,
             phi_observer: None,
             synchronized_nodes: HashMap::new(),
@@ -124,6 +127,23 @@ impl Code {
         ret.map(|x| x.collection)
     }
 
+    pub fn add_function(&mut self, function: ast::GpuFunction) {
+        self.functions.insert(function.name.val.clone(), function);
+    }
+
+    // functions are inlined, so every call gets its own copy of body
+    pub fn get_function(&self, name: &str) -> Option<ast::GpuFunction> {
+        self.functions.get(name).cloned()
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.variables.push(HashMap::new());
+    }
+
+    pub fn exit_scope(&mut self) {
+        self.variables.pop();
+    }
+
     pub fn new_label(&mut self) -> Address {
         self.counter += 1;
         self.counter
@@ -221,6 +241,13 @@ impl Code {
         }
     }
 
+    pub fn scale(&mut self, address: Address, factor: Address) -> Address {
+        let new_addr = self.push(Operation::Scale(address, factor));
+        // scaled value already lives in a texture, so it can be shifted without another sync
+        self.synchronized_nodes.insert(new_addr, new_addr);
+        new_addr
+    }
+
     pub fn get_const_address(&self, value: &ConstantValue) -> Option<Address> {
         self.constants.iter().find(|x| *x.1 == *value).map(|x| *x.0)
     }
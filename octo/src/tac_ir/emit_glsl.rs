@@ -0,0 +1,240 @@
+use super::emit_source::{emit_body, Dialect};
+use super::emit_spirv::uniform_size;
+use super::ir::ValueType;
+use super::{PipelineDef, ShaderDef};
+
+/// Emits GLSL 450 (Vulkan flavour) source for every shader of pipeline.
+/// Bindings and push constants match the layout of emitted SPIR-V.
+pub fn emit_glsl(code: &PipelineDef) -> Vec<String> {
+    code.shaders
+        .iter()
+        .map(|x| emit_single_shader(x, &code.uniforms))
+        .collect()
+}
+
+struct Glsl;
+
+impl Dialect for Glsl {
+    fn type_name(&self, typ: ValueType) -> String {
+        match typ {
+            ValueType::Float => "float",
+            ValueType::Vec2 => "vec2",
+            ValueType::Vec3 => "vec3",
+            ValueType::Vec4 => "vec4",
+            ValueType::Mat3 => "mat3",
+            ValueType::Mat4 => "mat4",
+            ValueType::Int => "int",
+            ValueType::Bool => "bool",
+            _ => panic!("internal compiler error: no glsl type for {:?}", typ),
+        }
+        .to_owned()
+    }
+
+    fn declare(&self, name: &str, typ: ValueType) -> String {
+        format!("{} {};", self.type_name(typ), name)
+    }
+
+    fn sample(&self, texture: usize, uv: &str) -> String {
+        format!("texture(sampler2D(textures[{}], samp), {})", texture, uv)
+    }
+
+    fn result(&self, id: usize) -> String {
+        format!("result_{}", id)
+    }
+
+    fn call(&self, name: &str, args: &[(String, ValueType)], _result: ValueType) -> String {
+        let name = match name {
+            "atan2" => "atan",
+            x => x,
+        };
+        let args: Vec<_> = args.iter().map(|x| x.0.clone()).collect();
+        format!("{}({})", name, args.join(", "))
+    }
+}
+
+fn emit_single_shader(info: &ShaderDef, uniforms: &Vec<(ValueType, String)>) -> String {
+    let glsl = Glsl;
+    let body = emit_body(&glsl, info, uniforms);
+
+    let mut source = String::from("#version 450\n\n");
+    source.push_str("layout(location = 0) in vec2 uv;\n\n");
+    for (id, typ) in info.output_type.iter().enumerate() {
+        source.push_str(&format!("layout(location = {}) out {} result_{};\n", id, glsl.type_name(*typ), id));
+    }
+    source.push('\n');
+
+    if info.input_type.len() > 0 {
+        source.push_str("layout(set = 0, binding = 0) uniform sampler samp;\n");
+        source.push_str(&format!(
+            "layout(set = 0, binding = 1) uniform texture2D textures[{}];\n\n",
+            info.input_type.len()
+        ));
+    }
+
+    if uniforms.len() > 0 {
+        source.push_str("layout(push_constant) uniform Uniforms {\n");
+        let mut offset = 0;
+        for (typ, name) in uniforms {
+            source.push_str(&format!("    layout(offset = {}) {} {};\n", offset, glsl.type_name(*typ), name));
+            offset += uniform_size(*typ);
+        }
+        source.push_str("} uniforms;\n\n");
+    }
+
+    source.push_str("void main() {\n");
+    for (address, typ) in &body.variables {
+        source.push_str(&format!("    {}\n", glsl.declare(&format!("v{}", address), *typ)));
+    }
+    if body.variables.len() > 0 {
+        source.push('\n');
+    }
+    for line in body.lines {
+        source.push_str(&line);
+        source.push('\n');
+    }
+    source.push_str("}\n");
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{
+        loop_shader, passes, split_keeping_results, BRANCHES, EDGE, MATRICES, PACKED, RESULTS, SHIFTED_CONSTANTS,
+    };
+
+    fn edge_shader(mode: &str) -> String {
+        emit_glsl(&split_keeping_results(&EDGE.replace("MODE", mode))).remove(0)
+    }
+
+    // target of assignment on given line
+    fn assigned(line: &str) -> &str {
+        line.trim().split(" = ").next().unwrap()
+    }
+
+    #[test]
+    fn loop_with_uniform() {
+        let (shader, uniforms) = loop_shader();
+        let expected = "\
+#version 450
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out float result_0;
+
+layout(set = 0, binding = 0) uniform sampler samp;
+layout(set = 0, binding = 1) uniform texture2D textures[1];
+
+layout(push_constant) uniform Uniforms {
+    layout(offset = 0) float gain;
+} uniforms;
+
+void main() {
+    vec4 v2;
+    float v3;
+    int v10;
+    float v11;
+    bool v15;
+    float v18;
+    float v19;
+    float v20;
+    int v23;
+
+    v2 = texture(sampler2D(textures[0], samp), uv);
+    v3 = uniforms.gain;
+    v10 = 0;
+    v11 = 0.0;
+    while (true) {
+        v15 = v10 < 3;
+        if (!v15) {
+            break;
+        }
+        v18 = length(v2);
+        v19 = v18 * v3;
+        v20 = v11 + v19;
+        v23 = v10 + 1;
+        v10 = v23;
+        v11 = v20;
+    }
+    result_0 = v11;
+}
+";
+        assert_eq!(emit_single_shader(&shader, &uniforms), expected);
+    }
+
+    #[test]
+    fn edge_modes_wrap_shifted_coordinates() {
+        let sampled = "texture(sampler2D(textures[0], samp), ";
+        assert!(edge_shader("clamp").contains(&format!("{}clamp(v", sampled)));
+        assert!(edge_shader("clamp").contains("_uv, vec2(0.0, 0.0), vec2(1.0, 1.0)))"));
+        assert!(edge_shader("repeat").contains(&format!("{}fract(v", sampled)));
+        assert!(edge_shader("mirror").contains(&format!("{}vec2(1.0, 1.0) - abs(fract(v", sampled)));
+
+        // zero mode samples shifted coordinates as they are and masks the value outside of texture
+        let zero = edge_shader("zero");
+        assert!(zero.contains("vec2 v"));
+        assert!(zero.contains("_uv = uv + v"));
+        assert!(zero.contains(" * (step(0.0, v"));
+        assert!(zero.contains("_uv.y, 1.0))"));
+    }
+
+    #[test]
+    fn packed_texture_is_unpacked_by_components() {
+        let shaders = emit_glsl(&split_keeping_results(PACKED));
+        assert_eq!(shaders.len(), 2);
+        // three values are written to the only output of the first pass
+        assert!(shaders[0].contains("layout(location = 0) out vec4 result_0;\n\n"));
+        assert!(shaders[0].contains(" = vec4(v"));
+        for channel in 0..4 {
+            assert!(shaders[1].contains(&format!("[{}];", channel)));
+        }
+    }
+
+    #[test]
+    fn results_go_to_separate_outputs() {
+        let shaders = emit_glsl(&split_keeping_results(RESULTS));
+        let last = shaders.last().unwrap();
+        assert!(last.contains(
+            "layout(location = 0) out vec4 result_0;\n\
+             layout(location = 1) out float result_1;\n\
+             layout(location = 2) out vec2 result_2;\n"
+        ));
+        for id in 0..3 {
+            assert!(last.contains(&format!("    result_{} = ", id)));
+        }
+    }
+
+    #[test]
+    fn matrices_are_multiplied_by_operator() {
+        let shader = emit_glsl(&passes(MATRICES)).remove(0);
+        assert!(shader.contains(
+            "    layout(offset = 0) float gain;\n\
+             \x20   layout(offset = 16) mat3 transform;\n\
+             \x20   layout(offset = 64) vec3 offset;\n\
+             \x20   layout(offset = 80) vec3 tint;\n"
+        ));
+        assert!(shader.contains("    mat3 v"));
+        assert!(shader.contains("uniforms.transform;"));
+        assert!(!shader.contains("mul("));
+    }
+
+    #[test]
+    fn both_branches_assign_phi() {
+        let shader = emit_glsl(&split_keeping_results(BRANCHES)).remove(0);
+        let lines: Vec<&str> = shader.lines().map(|x| x.trim()).collect();
+        let else_line = lines.iter().position(|x| *x == "} else {").unwrap();
+        let end_line = else_line + lines[else_line..].iter().position(|x| *x == "}").unwrap();
+        let phi = assigned(lines[else_line - 1]);
+        assert_eq!(assigned(lines[end_line - 1]), phi);
+        assert!(lines.contains(&format!("result_0 = {};", phi).as_str()));
+    }
+
+    #[test]
+    fn shifted_constants_need_no_texture() {
+        let shader = emit_glsl(&split_keeping_results(SHIFTED_CONSTANTS)).remove(0);
+        assert!(shader.contains(" = 0.25;"));
+        assert!(shader.contains(" = 0.5 * (step(0.0, v"));
+        // only input is sampled
+        assert_eq!(shader.matches("texture(").count(), 1);
+    }
+}
@@ -0,0 +1,271 @@
+use super::emit_source::{emit_body, Dialect};
+use super::emit_spirv::uniform_size;
+use super::ir::ValueType;
+use super::{PipelineDef, ShaderDef};
+
+/// Emits HLSL (shader model 5) source of pixel shader for every shader of pipeline.
+/// Textures are bound from t0, sampler to s0 and uniforms to cbuffer at b0.
+pub fn emit_hlsl(code: &PipelineDef) -> Vec<String> {
+    code.shaders
+        .iter()
+        .map(|x| emit_single_shader(x, &code.uniforms))
+        .collect()
+}
+
+struct Hlsl;
+
+impl Dialect for Hlsl {
+    fn type_name(&self, typ: ValueType) -> String {
+        match typ {
+            ValueType::Float => "float",
+            ValueType::Vec2 => "float2",
+            ValueType::Vec3 => "float3",
+            ValueType::Vec4 => "float4",
+            ValueType::Mat3 => "float3x3",
+            ValueType::Mat4 => "float4x4",
+            ValueType::Int => "int",
+            ValueType::Bool => "bool",
+            _ => panic!("internal compiler error: no hlsl type for {:?}", typ),
+        }
+        .to_owned()
+    }
+
+    // zero initialized, fxc warns about possibly uninitialized variables
+    fn declare(&self, name: &str, typ: ValueType) -> String {
+        format!("{} {} = ({})0;", self.type_name(typ), name, self.type_name(typ))
+    }
+
+    // explicit level, gradients are not available in divergent loops
+    fn sample(&self, texture: usize, uv: &str) -> String {
+        format!("textures[{}].SampleLevel(samp, {}, 0)", texture, uv)
+    }
+
+    // members of cbuffer are global, prefix keeps them apart from locals
+    fn uniform(&self, name: &str, _typ: ValueType) -> String {
+        format!("u_{}", name)
+    }
+
+    fn result(&self, id: usize) -> String {
+        format!("output.result_{}", id)
+    }
+
+    fn equal(&self, left: &str, right: &str, vector: bool) -> String {
+        if vector {
+            format!("all({} == {})", left, right)
+        } else {
+            format!("{} == {}", left, right)
+        }
+    }
+
+    fn not_equal(&self, left: &str, right: &str, vector: bool) -> String {
+        if vector {
+            format!("any({} != {})", left, right)
+        } else {
+            format!("{} != {}", left, right)
+        }
+    }
+
+    // operator * is component-wise in hlsl, matrix product needs mul
+    fn multiply(&self, left: &str, right: &str, types: (ValueType, ValueType)) -> String {
+        match types {
+            (ValueType::Mat3, ValueType::Float)
+            | (ValueType::Mat4, ValueType::Float)
+            | (ValueType::Float, ValueType::Mat3)
+            | (ValueType::Float, ValueType::Mat4) => format!("{} * {}", left, right),
+            (ValueType::Mat3, _) | (ValueType::Mat4, _) | (_, ValueType::Mat3) | (_, ValueType::Mat4) => {
+                format!("mul({}, {})", left, right)
+            }
+            _ => format!("{} * {}", left, right),
+        }
+    }
+
+    fn call(&self, name: &str, args: &[(String, ValueType)], result: ValueType) -> String {
+        let x = &args[0].0;
+        match name {
+            "fract" => format!("frac({})", x),
+            // atan2(y, x) takes arguments in the same order as glsl atan(y, x)
+            "atan2" => format!("atan2({}, {})", x, args[1].0),
+            // sign returns int type in hlsl
+            "sign" => format!("({})sign({})", self.type_name(result), x),
+            // no inverse hyperbolic functions in hlsl
+            "asinh" => format!("log(({0}) + sqrt(({0}) * ({0}) + 1.0))", x),
+            "acosh" => format!("log(({0}) + sqrt(({0}) * ({0}) - 1.0))", x),
+            "atanh" => format!("(0.5 * log((1.0 + ({0})) / (1.0 - ({0}))))", x),
+            _ => {
+                let args: Vec<_> = args.iter().map(|x| x.0.clone()).collect();
+                format!("{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+fn emit_single_shader(info: &ShaderDef, uniforms: &Vec<(ValueType, String)>) -> String {
+    let hlsl = Hlsl;
+    let body = emit_body(&hlsl, info, uniforms);
+
+    let mut source = String::new();
+    if info.input_type.len() > 0 {
+        source.push_str("SamplerState samp : register(s0);\n");
+        source.push_str(&format!("Texture2D textures[{}] : register(t0);\n\n", info.input_type.len()));
+    }
+
+    if uniforms.len() > 0 {
+        // offsets are the same as of push constants of SPIR-V, each register takes 16 bytes
+        source.push_str("cbuffer Uniforms : register(b0) {\n");
+        let mut offset = 0;
+        for (typ, name) in uniforms {
+            let component = ["x", "y", "z", "w"][offset % 16 / 4];
+            source.push_str(&format!(
+                "    {} u_{} : packoffset(c{}.{});\n",
+                hlsl.type_name(*typ),
+                name,
+                offset / 16,
+                component
+            ));
+            offset += uniform_size(*typ);
+        }
+        source.push_str("};\n\n");
+    }
+
+    source.push_str("struct Output {\n");
+    for (id, typ) in info.output_type.iter().enumerate() {
+        source.push_str(&format!("    {} result_{} : SV_Target{};\n", hlsl.type_name(*typ), id, id));
+    }
+    source.push_str("};\n\n");
+
+    source.push_str("Output main(float4 position : SV_Position, float2 uv : TEXCOORD0) {\n");
+    source.push_str("    Output output;\n");
+    for (address, typ) in &body.variables {
+        source.push_str(&format!("    {}\n", hlsl.declare(&format!("v{}", address), *typ)));
+    }
+    source.push('\n');
+    for line in body.lines {
+        source.push_str(&line);
+        source.push('\n');
+    }
+    source.push_str("    return output;\n}\n");
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{loop_shader, passes, MATRICES};
+
+    // sign of float and of vector
+    const SIGNS: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        {
+            return sign(color) * sign(color.x);
+        }
+    ";
+
+    // every function gets one component of color
+    const INVERSE_HYPERBOLIC: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        {
+            return vec4(asinh(color.x), acosh(color.y), atanh(color.z), 1.0);
+        }
+    ";
+
+    fn shader(src: &str) -> String {
+        emit_hlsl(&passes(src)).remove(0)
+    }
+
+    // variable holding given component of input
+    fn component(source: &str, id: usize) -> String {
+        let suffix = format!("[{}];", id);
+        let line = source.lines().find(|x| x.ends_with(&suffix)).unwrap();
+        line.trim().split(" = ").next().unwrap().to_owned()
+    }
+
+    #[test]
+    fn loop_with_uniform() {
+        let (shader, uniforms) = loop_shader();
+        let expected = "\
+SamplerState samp : register(s0);
+Texture2D textures[1] : register(t0);
+
+cbuffer Uniforms : register(b0) {
+    float u_gain : packoffset(c0.x);
+};
+
+struct Output {
+    float result_0 : SV_Target0;
+};
+
+Output main(float4 position : SV_Position, float2 uv : TEXCOORD0) {
+    Output output;
+    float4 v2 = (float4)0;
+    float v3 = (float)0;
+    int v10 = (int)0;
+    float v11 = (float)0;
+    bool v15 = (bool)0;
+    float v18 = (float)0;
+    float v19 = (float)0;
+    float v20 = (float)0;
+    int v23 = (int)0;
+
+    v2 = textures[0].SampleLevel(samp, uv, 0);
+    v3 = u_gain;
+    v10 = 0;
+    v11 = 0.0;
+    while (true) {
+        v15 = v10 < 3;
+        if (!v15) {
+            break;
+        }
+        v18 = length(v2);
+        v19 = v18 * v3;
+        v20 = v11 + v19;
+        v23 = v10 + 1;
+        v10 = v23;
+        v11 = v20;
+    }
+    output.result_0 = v11;
+    return output;
+}
+";
+        assert_eq!(emit_single_shader(&shader, &uniforms), expected);
+    }
+
+    #[test]
+    fn matrix_products_need_mul() {
+        // matrix scaled by float is component-wise, only matrix times vector needs mul
+        let source = shader(MATRICES);
+        assert_eq!(source.matches("mul(").count(), 1);
+        assert!(source.contains("    float3x3 v"));
+        assert!(source.contains(" = mul(v"));
+    }
+
+    #[test]
+    fn sign_keeps_float_type() {
+        let source = shader(SIGNS);
+        assert!(source.contains(" = (float4)sign(v"));
+        assert!(source.contains(" = (float)sign(v"));
+    }
+
+    #[test]
+    fn inverse_hyperbolic_functions_use_logarithm() {
+        let source = shader(INVERSE_HYPERBOLIC);
+        let (x, y, z) = (component(&source, 0), component(&source, 1), component(&source, 2));
+        assert!(source.contains(&format!(" = log(({0}) + sqrt(({0}) * ({0}) + 1.0));", x)));
+        assert!(source.contains(&format!(" = log(({0}) + sqrt(({0}) * ({0}) - 1.0));", y)));
+        assert!(source.contains(&format!(" = (0.5 * log((1.0 + ({0})) / (1.0 - ({0}))));", z)));
+        assert!(!source.contains("asinh"));
+    }
+
+    #[test]
+    fn matrices_take_whole_registers() {
+        // mat3 takes three registers, vectors after it start at the next free one
+        let source = shader(MATRICES);
+        assert!(source.contains(
+            "cbuffer Uniforms : register(b0) {\n\
+             \x20   float u_gain : packoffset(c0.x);\n\
+             \x20   float3x3 u_transform : packoffset(c1.x);\n\
+             \x20   float3 u_offset : packoffset(c4.x);\n\
+             \x20   float3 u_tint : packoffset(c5.x);\n\
+             };\n"
+        ));
+    }
+}
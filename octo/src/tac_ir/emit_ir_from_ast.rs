@@ -10,8 +10,20 @@ use super::emit_builtins::emit_builtin;
 
 use super::code::{Code, PhiCollection};
 
-pub fn emit(ast: ast::Pipeline) -> PipelineIR {
+pub fn emit(program: ast::Program) -> PipelineIR {
     let mut code = Code::new();
+    for function in program.functions {
+        code.add_function(function);
+    }
+    for constant in program.constants {
+        let addr = emit_expression(*constant.value, &mut code);
+        let addr = code.push(Operation::Store(addr));
+        code.store(&constant.name.val, addr, true);
+    }
+    let ast = match program.pipeline {
+        Some(pipeline) => pipeline,
+        None => panic!("internal compiler error"),
+    };
     let mut arguments = vec![];
     let mut uniforms = vec![];
 
@@ -55,8 +67,15 @@ fn emit_statement(statement: ast::Statement, code: &mut Code) {
             emit_expression(*exp, code);
         }
         ast::Statement::Return(exp) => {
-            let ret_add = emit_expression(*exp, code);
-            code.exit(ret_add);
+            let values = match *exp {
+                ast::Expression::Tuple(values) => values,
+                exp => vec![Box::new(exp)],
+            };
+            let addresses: Vec<_> = values.into_iter().map(|x| emit_expression(*x, code)).collect();
+            for (id, address) in addresses.iter().enumerate().skip(1) {
+                code.push(Operation::Output(id, *address));
+            }
+            code.exit(addresses[0]);
         }
         ast::Statement::Assignment(storage,exp) => {
             let addr = emit_expression(*exp, code);
@@ -148,7 +167,7 @@ fn emit_statement(statement: ast::Statement, code: &mut Code) {
                 rec.old_label = post_init_label;
 
                 let address = code.insert_at(Operation::Phi(rec), phi_insert_index);
-                println!("renaming {} to {}", phi.1.old, address);
+                log::debug!("renaming {} to {}", phi.1.old, address);
                 code.update_variable_by_address(phi.1.old, address);
 
                 code.store(&phi.0, address, false);
@@ -159,7 +178,7 @@ fn emit_statement(statement: ast::Statement, code: &mut Code) {
                     address,
                 );
             }
-            println!("\tfor renaming finished");
+            log::debug!("\tfor renaming finished");
         }
         ast::Statement::IfElse(condition, true_block, false_block) => {
             let cond = emit_expression(*condition, code);
@@ -254,7 +273,7 @@ fn emit_expression(exp: ast::Expression, code: &mut Code) -> Address {
             ast::Literal::Int(val) => code.store_constant(ConstantValue::Int(val.val)),
             ast::Literal::Float(val) => code.store_constant(ConstantValue::Float(val.val)),
         },
-        Negation(exp) => {
+        Negation(exp) | Not(exp) => {
             let exp_address = emit_expression(*exp, code);
             code.push(Operation::Neg(exp_address))
         }
@@ -318,13 +337,19 @@ fn emit_expression(exp: ast::Expression, code: &mut Code) -> Address {
             let right_address = emit_expression(*exp_right, code);
             code.push(Operation::Or(left_address, right_address))
         }
-        Shift(shifted, shift_by) => {
+        Shift(shifted, shift_by, mode) => {
             let left_address = emit_expression(*shifted, code);
             let right_address = emit_expression(*shift_by, code);
             let left_synced = code.synchronize(left_address);
-            code.push(Operation::Shift(left_synced, right_address))
+            // name was checked by static analysis
+            let mode = mode.and_then(|x| ast::EdgeMode::from_name(&x.val));
+            code.push(Operation::Shift(left_synced, right_address, mode))
+        }
+        Scale(scaled, scale_by) => {
+            let left_address = emit_expression(*scaled, code);
+            let right_address = emit_expression(*scale_by, code);
+            code.scale(left_address, right_address)
         }
-        Scale(_scaled, _scale_by) => 0,
         Invocation(name, exps) => {
             let mut addresses = Vec::with_capacity(exps.len());
             for exp in exps {
@@ -335,6 +360,7 @@ fn emit_expression(exp: ast::Expression, code: &mut Code) -> Address {
         Access(value, field) => {
             emit_access(*value, field.val, code)
         }
+        Tuple(..) => panic!("internal compiler error"),
     }
 }
 
@@ -400,7 +426,126 @@ fn emit_invocation(name: &str, addresses: &Vec<Address>, code: &mut Code) -> Add
     if TYPE_SET.contains(name) {
         //println!("hehe");
         emit_constructor(name, addresses, code)
+    } else if let Some(function) = code.get_function(name) {
+        emit_function_call(function, addresses, code)
     } else {
         emit_builtin(name, addresses, code).unwrap()
     }
 }
+
+fn emit_function_call(function: ast::GpuFunction, addresses: &Vec<Address>, code: &mut Code) -> Address {
+    code.enter_scope();
+    for (arg, address) in function.arguments.iter().zip(addresses.iter()) {
+        // copy, so loop renaming inside function doesn't touch caller variables
+        let addr = code.push(Operation::Store(*address));
+        code.store(&arg.identifier.val, addr, true);
+    }
+
+    let mut result = None;
+    for statement in function.block.statements {
+        match statement {
+            // static analysis guarantees that return is the last statement
+            ast::Statement::Return(exp) => {
+                result = Some(emit_expression(*exp, code));
+            }
+            statement => emit_statement(statement, code),
+        }
+    }
+    code.exit_scope();
+    match result {
+        Some(address) => address,
+        None => panic!("internal compiler error"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::interpreter::{as_components, Image};
+    use super::super::test_utils::{assert_close, compile, gradient, optimize, run, split_keeping_results};
+    use super::*;
+
+    // runs pipeline with single vec4 result on test gradient and compares it with expected pixels
+    fn assert_results(src: &str, expected: impl Fn([f64; 4]) -> [f64; 4]) {
+        let input = gradient(5, 3);
+        let mut image = Image::new(5, 3, ConstantValue::Vec4([0.0; 4]));
+        for y in 0..3 {
+            for x in 0..5 {
+                let color = as_components(input.get(x, y));
+                image.set(x, y, ConstantValue::Vec4(expected([color[0], color[1], color[2], color[3]])));
+            }
+        }
+        assert_close(&run(&compile(src))[0], &image);
+        assert_close(&run(&optimize(compile(src)))[0], &image);
+        split_keeping_results(src);
+    }
+
+    #[test]
+    fn functions_are_inlined() {
+        let src = "
+            let k = 3.0;
+
+            gpu_fun square(x: float) -> float {
+                return x * x;
+            }
+
+            // constants are visible in functions
+            gpu_fun scaled(x: float) -> float {
+                return x * k;
+            }
+
+            pipeline main(color: vec4) -> (vec4) {
+                return vec4(square(color.x), scaled(color.y), square(scaled(color.z)), 1.0);
+            }
+        ";
+        assert_results(src, |c| [c[0] * c[0], c[1] * 3.0, (c[2] * 3.0) * (c[2] * 3.0), 1.0]);
+    }
+
+    #[test]
+    fn arguments_are_copied() {
+        let src = "
+            gpu_fun bump(x: float) -> float {
+                x = x + 1.0;
+                return x;
+            }
+
+            pipeline main(color: vec4) -> (vec4) {
+                let a = color.x;
+                let b = bump(a);
+                return vec4(a, b, bump(bump(a)), 1.0);
+            }
+        ";
+        assert_results(src, |c| [c[0], c[0] + 1.0, c[0] + 2.0, 1.0]);
+    }
+
+    #[test]
+    fn calls_inside_loops() {
+        let src = "
+            gpu_fun square(x: float) -> float {
+                return x * x;
+            }
+
+            // loop of function is nested in loop of caller
+            gpu_fun power(x: float, n: int) -> float {
+                let result = 1.0;
+                for (let i = 0; i < n; i = i + 1) {
+                    result = result * x;
+                }
+                return result;
+            }
+
+            pipeline main(color: vec4) -> (vec4) {
+                let total = 0.0;
+                let powers = 0.0;
+                for (let i = 0; i < 3; i = i + 1) {
+                    total = total + square(color.x + 1.0);
+                    powers = powers + power(color.y, i);
+                }
+                return vec4(total, powers, color.z, 1.0);
+            }
+        ";
+        assert_results(src, |c| {
+            let total = 3.0 * (c[0] + 1.0) * (c[0] + 1.0);
+            [total, 1.0 + c[1] + c[1] * c[1], c[2], 1.0]
+        });
+    }
+}
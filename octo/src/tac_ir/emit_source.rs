@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+
+use super::ir::{Address, EdgeMode, Op, Operation, PhiRecord, StdFunction, ValueType};
+use super::utils::{find_if_else, find_loop, IfElseCode, LoopCode, PeekableCode};
+use super::ShaderDef;
+
+/// Syntax of shading language emitted from structured code of a pass.
+pub trait Dialect {
+    fn type_name(&self, typ: ValueType) -> String;
+
+    /// Declaration of variable without initial value.
+    fn declare(&self, name: &str, typ: ValueType) -> String;
+
+    fn declare_init(&self, name: &str, typ: ValueType, value: &str) -> String {
+        format!("{} {} = {};", self.type_name(typ), name, value)
+    }
+
+    /// Value of n-th input texture of pass at given coordinates, as vec4 of all channels.
+    fn sample(&self, texture: usize, uv: &str) -> String;
+
+    fn uniform(&self, name: &str, _typ: ValueType) -> String {
+        format!("uniforms.{}", name)
+    }
+
+    /// Left side of assignment to n-th result.
+    fn result(&self, id: usize) -> String;
+
+    fn loop_start(&self) -> &'static str {
+        "while (true) {"
+    }
+
+    fn equal(&self, left: &str, right: &str, _vector: bool) -> String {
+        format!("{} == {}", left, right)
+    }
+
+    fn not_equal(&self, left: &str, right: &str, _vector: bool) -> String {
+        format!("{} != {}", left, right)
+    }
+
+    fn multiply(&self, left: &str, right: &str, _types: (ValueType, ValueType)) -> String {
+        format!("{} * {}", left, right)
+    }
+
+    /// Call of std function, names follow GLSL except of atan2.
+    fn call(&self, name: &str, args: &[(String, ValueType)], _result: ValueType) -> String {
+        let args: Vec<_> = args.iter().map(|x| x.0.clone()).collect();
+        format!("{}({})", name, args.join(", "))
+    }
+
+    fn float_literal(&self, x: f64) -> String {
+        // float literal always needs a dot or exponent
+        let text = format!("{:?}", x);
+        if text.contains('.') || text.contains('e') {
+            text
+        } else {
+            text + ".0"
+        }
+    }
+}
+
+/// Body of main function, variables need to be declared before lines are emitted.
+pub struct ShaderBody {
+    pub variables: Vec<(Address, ValueType)>,
+    pub lines: Vec<String>,
+}
+
+/// Emits code of pass as structured source, every line is indented by one level.
+pub fn emit_body<D: Dialect>(dialect: &D, info: &ShaderDef, uniforms: &Vec<(ValueType, String)>) -> ShaderBody {
+    let mut emitter = SourceEmitter {
+        dialect,
+        lines: vec![],
+        indent: 1,
+        variables: HashMap::new(),
+        constants: HashMap::new(),
+        args: HashMap::new(),
+        input_type: info.input_type.clone(),
+        uniforms: uniforms.clone(),
+        last_label: 1,
+        loop_phis: vec![],
+    };
+    let mut code = PeekableCode::new(info.code.iter());
+    emitter.emit_all(&mut code);
+
+    let mut variables: Vec<_> = emitter.variables.into_iter().collect();
+    variables.sort_by_key(|x| x.0);
+    ShaderBody {
+        variables,
+        lines: emitter.lines,
+    }
+}
+
+// arithmetic on float and vector (or matrix) gives vector
+pub fn arithmetic_type(left: ValueType, right: ValueType) -> ValueType {
+    match (left, right) {
+        (ValueType::Float, x) => x,
+        (x, ValueType::Float) => x,
+        (ValueType::Vec3, ValueType::Mat3) | (ValueType::Mat3, ValueType::Vec3) => ValueType::Vec3,
+        (ValueType::Vec4, ValueType::Mat4) | (ValueType::Mat4, ValueType::Vec4) => ValueType::Vec4,
+        (x, _) => x,
+    }
+}
+
+fn is_vector(typ: ValueType) -> bool {
+    match typ {
+        ValueType::Vec2 | ValueType::Vec3 | ValueType::Vec4 => true,
+        _ => false,
+    }
+}
+
+struct SourceEmitter<'a, D: Dialect> {
+    dialect: &'a D,
+    lines: Vec<String>,
+    indent: usize,
+    // every value gets variable declared at the beginning of main, so it's visible in all blocks
+    variables: HashMap<Address, ValueType>,
+    constants: HashMap<Address, (String, ValueType)>,
+    args: HashMap<Address, usize>,
+    input_type: Vec<ValueType>,
+    uniforms: Vec<(ValueType, String)>,
+    last_label: Address,
+    loop_phis: Vec<(Address, PhiRecord)>,
+}
+
+impl<'a, D: Dialect> SourceEmitter<'a, D> {
+    fn line(&mut self, text: String) {
+        self.lines.push(format!("{}{}", "    ".repeat(self.indent), text));
+    }
+
+    fn value(&self, address: Address) -> String {
+        match self.constants.get(&address) {
+            Some(x) => x.0.clone(),
+            None => format!("v{}", address),
+        }
+    }
+
+    fn get_type(&self, address: Address) -> ValueType {
+        match self.constants.get(&address) {
+            Some(x) => x.1,
+            None => match self.variables.get(&address) {
+                Some(x) => *x,
+                None => ValueType::Unknown,
+            },
+        }
+    }
+
+    fn assign(&mut self, ret: Address, typ: ValueType, expression: String) {
+        self.variables.insert(ret, typ);
+        self.line(format!("v{} = {};", ret, expression));
+    }
+
+    fn constant(&mut self, ret: Address, typ: ValueType, literal: String) {
+        self.constants.insert(ret, (literal, typ));
+    }
+
+    // textures always have four channels, value takes as many of them as it has components
+    fn sample(&self, texture: usize, uv: &str, typ: ValueType) -> String {
+        let swizzle = match typ {
+            ValueType::Float => ".x",
+            ValueType::Vec2 => ".xy",
+            ValueType::Vec3 => ".xyz",
+            ValueType::Vec4 => "",
+            _ => panic!("can't store non-float in texture!"),
+        };
+        format!("{}{}", self.dialect.sample(texture, uv), swizzle)
+    }
+
+    fn vector_literal(&self, typ: ValueType, components: &[f64]) -> String {
+        let components: Vec<_> = components.iter().map(|x| self.dialect.float_literal(*x)).collect();
+        format!("{}({})", self.dialect.type_name(typ), components.join(", "))
+    }
+
+    // coordinates are wrapped by edge mode, zero mode masks samples outside of texture instead
+    fn sample_with_edge(&self, texture: usize, uv: &str, typ: ValueType, mode: EdgeMode) -> String {
+        let one = self.vector_literal(ValueType::Vec2, &[1.0, 1.0]);
+        let call = |name: &str, args: &[&str]| {
+            let args: Vec<_> = args.iter().map(|x| (x.to_string(), ValueType::Vec2)).collect();
+            self.dialect.call(name, &args, ValueType::Vec2)
+        };
+        let wrapped = match mode {
+            EdgeMode::Clamp => {
+                let zero = self.vector_literal(ValueType::Vec2, &[0.0, 0.0]);
+                call("clamp", &[uv, zero.as_str(), one.as_str()])
+            }
+            EdgeMode::Repeat => call("fract", &[uv]),
+            // 1 - |2 * fract(uv / 2) - 1| reflects every other repetition
+            EdgeMode::Mirror => {
+                let halved = format!("{} * {}", uv, self.dialect.float_literal(0.5));
+                let centered = format!("{} * {} - {}", call("fract", &[halved.as_str()]), self.dialect.float_literal(2.0), one);
+                format!("{} - {}", one, call("abs", &[centered.as_str()]))
+            }
+            EdgeMode::Zero => uv.to_owned(),
+        };
+        let value = self.sample(texture, &wrapped, typ);
+        match mode {
+            EdgeMode::Zero => self.masked(&value, uv),
+            _ => value,
+        }
+    }
+
+    // value multiplied by one inside of texture and by zero outside of it
+    fn masked(&self, value: &str, uv: &str) -> String {
+        let step = |edge: String, x: String| {
+            self.dialect.call("step", &[(edge, ValueType::Float), (x, ValueType::Float)], ValueType::Float)
+        };
+        let zero = self.dialect.float_literal(0.0);
+        let one = self.dialect.float_literal(1.0);
+        let inside = [
+            step(zero.clone(), format!("{}.x", uv)),
+            step(zero, format!("{}.y", uv)),
+            step(format!("{}.x", uv), one.clone()),
+            step(format!("{}.y", uv), one),
+        ];
+        format!("{} * ({})", value, inside.join(" * "))
+    }
+
+    // shifted coordinates are used more than once, so they get their own variable
+    fn shifted_uv(&mut self, ret: Address, uv: &str) -> String {
+        let name = format!("v{}_uv", ret);
+        let line = self.dialect.declare_init(&name, ValueType::Vec2, uv);
+        self.line(line);
+        name
+    }
+
+    fn emit_shift(&mut self, ret: Address, what: Address, by: Address, mode: Option<EdgeMode>) {
+        let uv = format!("uv + {}", self.value(by));
+        let arg_id = match self.args.get(&what).copied() {
+            Some(x) => x,
+            // shifted constant is the same everywhere, only zero mode masks it outside of texture
+            None => {
+                let typ = self.get_type(what);
+                let value = match mode {
+                    Some(EdgeMode::Zero) => {
+                        let name = self.shifted_uv(ret, &uv);
+                        self.masked(&self.value(what), &name)
+                    }
+                    _ => self.value(what),
+                };
+                self.assign(ret, typ, value);
+                return;
+            }
+        };
+        let typ = self.input_type[arg_id];
+        let value = match mode {
+            None => self.sample(arg_id, &uv, typ),
+            Some(mode) => {
+                let name = self.shifted_uv(ret, &uv);
+                self.sample_with_edge(arg_id, &name, typ, mode)
+            }
+        };
+        self.assign(ret, typ, value);
+    }
+
+    fn binary(&mut self, ret: Address, left: Address, right: Address, operator: &str, typ: ValueType) {
+        let expression = format!("{} {} {}", self.value(left), operator, self.value(right));
+        self.assign(ret, typ, expression);
+    }
+
+    fn emit_all<'b, I: std::iter::Iterator<Item = &'b Op>>(&mut self, code: &mut PeekableCode<'b, I>) {
+        while let Some((ret, op_code)) = code.next().copied() {
+            self.emit_next(ret, op_code, code);
+        }
+    }
+
+    fn emit_next<'b, I: std::iter::Iterator<Item = &'b Op>>(
+        &mut self,
+        ret: Address,
+        op_code: Operation,
+        code: &mut PeekableCode<'b, I>,
+    ) {
+        match op_code {
+            Operation::JumpIfElse(..) => {
+                let if_data = find_if_else(ret, op_code, code);
+                self.emit_if_else(if_data);
+            }
+            Operation::LoopMerge(..) => {
+                let loop_data = find_loop(ret, op_code, code, self.last_label);
+                self.emit_loop(loop_data);
+            }
+            // phi nodes of if-else are taken by find_if_else, these belong to loop header
+            Operation::Phi(rec) => {
+                self.loop_phis.push((ret, rec));
+            }
+            _ => {
+                self.emit_operation(ret, op_code);
+            }
+        }
+    }
+
+    fn emit_block(&mut self, label: Address, block: &[Op]) {
+        self.last_label = label;
+        self.indent += 1;
+        let mut code = PeekableCode::new(block.iter());
+        self.emit_all(&mut code);
+        self.indent -= 1;
+    }
+
+    fn emit_if_else(&mut self, data: IfElseCode) {
+        let phis: Vec<(Address, PhiRecord)> = data
+            .phi_nodes
+            .iter()
+            .map(|x| match x.1 {
+                Operation::Phi(rec) => (x.0, rec),
+                _ => panic!("internal compiler error"),
+            })
+            .collect();
+
+        let condition = self.value(data.condition_label);
+        self.line(format!("if ({}) {{", condition));
+        self.emit_block(data.if_label, &data.true_block);
+        let post_true_label = self.last_label;
+        self.indent += 1;
+        for (ret, rec) in &phis {
+            let value = if rec.label == post_true_label { rec.new } else { rec.old };
+            let typ = self.get_type(value);
+            self.assign(*ret, typ, self.value(value));
+        }
+        self.indent -= 1;
+
+        if data.false_block.is_some() || phis.len() > 0 {
+            self.line("} else {".to_owned());
+            if let Some(block) = &data.false_block {
+                self.emit_block(data.else_label.unwrap(), block);
+            }
+            self.indent += 1;
+            for (ret, rec) in &phis {
+                let value = if rec.label == post_true_label { rec.old } else { rec.new };
+                let typ = self.get_type(*ret);
+                self.assign(*ret, typ, self.value(value));
+            }
+            self.indent -= 1;
+        }
+        self.line("}".to_owned());
+        self.last_label = data.end_label;
+    }
+
+    fn emit_loop(&mut self, data: LoopCode) {
+        let phis: Vec<_> = self.loop_phis.drain(..).collect();
+        for (ret, rec) in &phis {
+            let typ = self.get_type(rec.old);
+            self.assign(*ret, typ, self.value(rec.old));
+        }
+
+        self.line(self.dialect.loop_start().to_owned());
+        self.emit_block(data.condition_label, &data.condition);
+        self.indent += 1;
+        let condition = self.value(data.condition_value);
+        self.line(format!("if (!{}) {{", condition));
+        self.line("    break;".to_owned());
+        self.line("}".to_owned());
+        self.indent -= 1;
+        self.emit_block(data.body_label, &data.body);
+        self.emit_block(data.continue_label, &data.continue_code);
+
+        // all phi nodes take new values at once, temporary copies are needed if one phi feeds another
+        self.indent += 1;
+        let needs_copy = phis.iter().any(|x| phis.iter().any(|y| y.0 == (x.1).new));
+        if needs_copy {
+            for (ret, rec) in &phis {
+                let typ = self.get_type(*ret);
+                let line = self.dialect.declare_init(&format!("v{}_next", ret), typ, &self.value(rec.new));
+                self.line(line);
+            }
+            for (ret, _) in &phis {
+                self.line(format!("v{} = v{}_next;", ret, ret));
+            }
+        } else {
+            for (ret, rec) in &phis {
+                let typ = self.get_type(*ret);
+                self.assign(*ret, typ, self.value(rec.new));
+            }
+        }
+        self.indent -= 1;
+        self.line("}".to_owned());
+        self.last_label = data.exit_label;
+    }
+
+    fn emit_operation(&mut self, ret: Address, operation: Operation) {
+        match operation {
+            Operation::Arg(x) => {
+                let value = self.sample(x, "uv", self.input_type[x]);
+                self.assign(ret, self.input_type[x], value);
+                self.args.insert(ret, x);
+            }
+            Operation::Uniform(x) => {
+                let (typ, name) = self.uniforms[x].clone();
+                self.assign(ret, typ, self.dialect.uniform(&name, typ));
+            }
+            Operation::StoreInt(x) => self.constant(ret, ValueType::Int, x.to_string()),
+            Operation::StoreFloat(x) => self.constant(ret, ValueType::Float, self.dialect.float_literal(x)),
+            Operation::StoreBool(x) => self.constant(ret, ValueType::Bool, x.to_string()),
+            Operation::StoreVec2(x) => self.constant(ret, ValueType::Vec2, self.vector_literal(ValueType::Vec2, &x)),
+            Operation::StoreVec3(x) => self.constant(ret, ValueType::Vec3, self.vector_literal(ValueType::Vec3, &x)),
+            Operation::StoreVec4(x) => self.constant(ret, ValueType::Vec4, self.vector_literal(ValueType::Vec4, &x)),
+            Operation::Store(addr) => {
+                let typ = self.get_type(addr);
+                self.assign(ret, typ, self.value(addr));
+            }
+            Operation::ConstructVec2(a, b) => self.construct(ret, ValueType::Vec2, &[a, b]),
+            Operation::ConstructVec3(a, b, c) => self.construct(ret, ValueType::Vec3, &[a, b, c]),
+            Operation::ConstructVec4(a, b, c, d) => self.construct(ret, ValueType::Vec4, &[a, b, c, d]),
+            Operation::ExtractComponent(vec, id) => {
+                let expression = format!("{}[{}]", self.value(vec), id);
+                self.assign(ret, ValueType::Float, expression);
+            }
+            Operation::StoreComponent(vec, id, val) => {
+                let typ = self.get_type(vec);
+                self.assign(ret, typ, self.value(vec));
+                self.line(format!("v{}[{}] = {};", ret, id, self.value(val)));
+            }
+            Operation::Add(l, r) => {
+                let typ = arithmetic_type(self.get_type(l), self.get_type(r));
+                self.binary(ret, l, r, "+", typ);
+            }
+            Operation::Sub(l, r) => {
+                let typ = arithmetic_type(self.get_type(l), self.get_type(r));
+                self.binary(ret, l, r, "-", typ);
+            }
+            Operation::Mul(l, r) => {
+                let types = (self.get_type(l), self.get_type(r));
+                let expression = self.dialect.multiply(&self.value(l), &self.value(r), types);
+                self.assign(ret, arithmetic_type(types.0, types.1), expression);
+            }
+            Operation::Div(l, r) => {
+                let typ = arithmetic_type(self.get_type(l), self.get_type(r));
+                self.binary(ret, l, r, "/", typ);
+            }
+            Operation::Less(l, r) => self.binary(ret, l, r, "<", ValueType::Bool),
+            Operation::LessEq(l, r) => self.binary(ret, l, r, "<=", ValueType::Bool),
+            Operation::Eq(l, r) => {
+                let vector = is_vector(self.get_type(l));
+                let expression = self.dialect.equal(&self.value(l), &self.value(r), vector);
+                self.assign(ret, ValueType::Bool, expression);
+            }
+            Operation::Neq(l, r) => {
+                let vector = is_vector(self.get_type(l));
+                let expression = self.dialect.not_equal(&self.value(l), &self.value(r), vector);
+                self.assign(ret, ValueType::Bool, expression);
+            }
+            Operation::And(l, r) => self.binary(ret, l, r, "&&", ValueType::Bool),
+            Operation::Or(l, r) => self.binary(ret, l, r, "||", ValueType::Bool),
+            Operation::Neg(val) => {
+                let typ = self.get_type(val);
+                let operator = match typ {
+                    ValueType::Bool => "!",
+                    _ => "-",
+                };
+                let expression = format!("{}{}", operator, self.value(val));
+                self.assign(ret, typ, expression);
+            }
+            Operation::Invoke(function) => self.emit_invoke(function, ret),
+            Operation::Shift(what, by, mode) => self.emit_shift(ret, what, by, mode),
+            Operation::Exit(val, _label) => {
+                self.line(format!("{} = {};", self.dialect.result(0), self.value(val)));
+            }
+            Operation::Output(id, val) => {
+                self.line(format!("{} = {};", self.dialect.result(id), self.value(val)));
+            }
+            Operation::Label => {
+                self.last_label = ret;
+            }
+            // control flow is structured by if and loop
+            Operation::Jump(_) => {}
+            Operation::Sync(..) | Operation::Scale(..) => {
+                panic!("internal compiler error: should never happen");
+            }
+            Operation::Phi(..) | Operation::JumpIfElse(..) | Operation::LoopMerge(..) => {
+                panic!("compiler bug. unreachable match arm: {:?}", operation);
+            }
+        }
+    }
+
+    fn construct(&mut self, ret: Address, typ: ValueType, components: &[Address]) {
+        let components: Vec<_> = components.iter().map(|x| self.value(*x)).collect();
+        let expression = format!("{}({})", self.dialect.type_name(typ), components.join(", "));
+        self.assign(ret, typ, expression);
+    }
+
+    fn emit_invoke(&mut self, function: StdFunction, ret: Address) {
+        use StdFunction::*;
+        let (name, args) = match function {
+            Round(a) => ("round", vec![a]),
+            Trunc(a) => ("trunc", vec![a]),
+            Abs(a) => ("abs", vec![a]),
+            Sign(a) => ("sign", vec![a]),
+            Floor(a) => ("floor", vec![a]),
+            Ceil(a) => ("ceil", vec![a]),
+            Fract(a) => ("fract", vec![a]),
+            Radians(a) => ("radians", vec![a]),
+            Degrees(a) => ("degrees", vec![a]),
+            Sin(a) => ("sin", vec![a]),
+            Cos(a) => ("cos", vec![a]),
+            Tan(a) => ("tan", vec![a]),
+            Asin(a) => ("asin", vec![a]),
+            Acos(a) => ("acos", vec![a]),
+            Atan(a) => ("atan", vec![a]),
+            Sinh(a) => ("sinh", vec![a]),
+            Cosh(a) => ("cosh", vec![a]),
+            Tanh(a) => ("tanh", vec![a]),
+            Asinh(a) => ("asinh", vec![a]),
+            Acosh(a) => ("acosh", vec![a]),
+            Atanh(a) => ("atanh", vec![a]),
+            Atan2(a, b) => ("atan2", vec![a, b]),
+            Pow(a, b) => ("pow", vec![a, b]),
+            Exp(a) => ("exp", vec![a]),
+            Log(a) => ("log", vec![a]),
+            Exp2(a) => ("exp2", vec![a]),
+            Log2(a) => ("log2", vec![a]),
+            Sqrt(a) => ("sqrt", vec![a]),
+            Dot(a, b) => ("dot", vec![a, b]),
+            Min(a, b) => ("min", vec![a, b]),
+            Max(a, b) => ("max", vec![a, b]),
+            Clamp(a, b, c) => ("clamp", vec![a, b, c]),
+            Length(a) => ("length", vec![a]),
+            Cross(a, b) => ("cross", vec![a, b]),
+            Normalize(a) => ("normalize", vec![a]),
+        };
+        let typ = match function {
+            Dot(..) | Length(..) => ValueType::Float,
+            Cross(..) => ValueType::Vec3,
+            _ => args
+                .iter()
+                .fold(ValueType::Float, |acc, x| arithmetic_type(acc, self.get_type(*x))),
+        };
+        let args: Vec<_> = args.iter().map(|x| (self.value(*x), self.get_type(*x))).collect();
+        let expression = self.dialect.call(name, &args, typ);
+        self.assign(ret, typ, expression);
+    }
+}
@@ -1,7 +1,6 @@
 use super::ir;
 use super::{PipelineDef, ShaderDef};
 use ir::{Address, Op, Operation, PipelineIR, ValueType};
-use log::error;
 use octo_runtime::{
     TextureType as RTTextureType,
     ValueType as RTValueType,
@@ -14,19 +13,27 @@ use rspirv::mr::*;
 use spirv_headers as spirv;
 use std::collections::HashMap;
 
-use shaderc::ShaderKind as Shader;
-
-static VERTEX: &str = include_str!("../basic_vertex.glsl");
-
 mod ids;
 mod main_emitter;
 mod emit_std;
+// shaderc build is compared against it in tests
+#[cfg(any(not(feature = "shaderc"), test))]
+mod vertex;
 
 use ids::SpirvIds;
 use main_emitter::MainEmitter;
 
-// TODO: move this to library compilation stage (build.rs)
+#[cfg(not(feature = "shaderc"))]
+use vertex::create_basic_vertex;
+
+// reference implementation of vertex::create_basic_vertex
+#[cfg(feature = "shaderc")]
 fn create_basic_vertex() -> Vec<u32> {
+    use log::error;
+    use shaderc::ShaderKind as Shader;
+
+    static VERTEX: &str = include_str!("../basic_vertex.glsl");
+
     let mut compiler = shaderc::Compiler::new()
         .ok_or("shaderc not found!")
         .unwrap();
@@ -50,14 +57,15 @@ fn version() -> u32 {
     final_version
 }
 
-fn uniform_size(typ: ValueType) -> usize {
+pub fn uniform_size(typ: ValueType) -> usize {
     use ValueType::*;
     match typ {
         ValueType::Float => 16,
         ValueType::Vec2 => 16,
         ValueType::Vec3 => 16,
         ValueType::Vec4 => 16,
-        ValueType::Mat3 => 36,
+        // columns are padded to vec4, like in std140 and WGSL
+        ValueType::Mat3 => 48,
         ValueType::Mat4 => 64,
         ValueType::Int => 16,
         ValueType::Bool => 16,
@@ -83,6 +91,14 @@ fn map_type(typ: ir::ValueType) -> octo_runtime::ValueType {
 
 }
 
+/// Textual form of SPIR-V binary.
+pub fn disassemble(words: &[u32]) -> String {
+    match rspirv::mr::load_words(words) {
+        Result::Ok(module) => module.disassemble(),
+        Result::Err(e) => format!("; invalid SPIR-V: {:?}", e),
+    }
+}
+
 pub fn emit_spirv(module_name: &str, code: PipelineDef) -> OctoModule {
     let mut code = code;
     //println!("Emitting spirv module");
@@ -125,17 +141,17 @@ pub fn emit_spirv(module_name: &str, code: PipelineDef) -> OctoModule {
         module.passes.push(octo_pass);
     }
 
-    for (id, tex) in code.textures.drain(0..code.textures.len()).enumerate() {
+    for (id, (tex, size)) in code.textures.drain(0..code.textures.len()).enumerate() {
         let typ = match tex {
             ValueType::Vec2 => RTTextureType::Vec2,
             ValueType::Vec3 => RTTextureType::Vec3,
             ValueType::Vec4 => RTTextureType::Vec4,
             _ => RTTextureType::Float,
         };
-        // probably to be changed once Scale operation is implemented
-        let size = TextureSize::Original;
+        let size: TextureSize = size.into();
         module.textures.push((id, typ, size));
     }
+    module.texture_slots = code.texture_slots;
 
 
     module.uniform_block_size = code.uniforms.iter().map(|x| uniform_size(x.0)).sum();
@@ -148,9 +164,9 @@ pub fn emit_spirv(module_name: &str, code: PipelineDef) -> OctoModule {
 fn emit_single_shader(info: ShaderDef, uniforms: &Vec<(ValueType, String)>) -> Vec<u32> {
 
     if true {
-        println!("Emitting single fragment shader for:\n\n");
+        log::debug!("Emitting single fragment shader for:\n\n");
         for c in &info.code {
-            println!("{:?}", c);
+            log::debug!("{:?}", c);
         }
     }
 
@@ -208,3 +224,62 @@ fn emit_single_shader(info: ShaderDef, uniforms: &Vec<(ValueType, String)>) -> V
     //println!("{}", m.disassemble());
     m.assemble()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{passes, SHIFTED_CONSTANTS};
+    use crate::tac_ir;
+
+    const UNIFORMS: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        with {
+            gain: float,
+            transform: mat3,
+            bias: vec2,
+        }
+        {
+            let c = transform * color.xyz * gain;
+            return vec4(c.x + bias.x, c.y + bias.y, c.z, 1.0);
+        }
+    ";
+
+    fn pipeline() -> PipelineDef {
+        passes(UNIFORMS)
+    }
+
+    #[test]
+    fn uniform_layout_is_shared_by_backends() {
+        // mat3 columns are padded to vec4, so vector after it starts at 16 + 48
+        let module = emit_spirv("layout", pipeline());
+        assert_eq!(module.uniform_block_size, 80);
+        let spirv = disassemble(&module.fragment_shaders[&0]);
+        for (id, offset) in [0, 16, 64].iter().enumerate() {
+            assert!(spirv.contains(&format!("{} Offset {}\n", id, offset)), "{}", spirv);
+        }
+        assert!(spirv.contains("1 MatrixStride 16\n"), "{}", spirv);
+
+        let glsl = &tac_ir::emit_glsl(&pipeline())[0];
+        assert!(glsl.contains("    layout(offset = 0) float gain;\n"), "{}", glsl);
+        assert!(glsl.contains("    layout(offset = 16) mat3 transform;\n"), "{}", glsl);
+        assert!(glsl.contains("    layout(offset = 64) vec2 bias;\n"), "{}", glsl);
+
+        let wgsl = &tac_ir::emit_wgsl(&pipeline())[0].source;
+        assert!(wgsl.contains("    @size(16) gain: f32,\n"), "{}", wgsl);
+        assert!(wgsl.contains("    @size(48) transform: mat3x3<f32>,\n"), "{}", wgsl);
+        assert!(wgsl.contains("    @size(16) bias: vec2<f32>,\n"), "{}", wgsl);
+
+        let hlsl = &tac_ir::emit_hlsl(&pipeline())[0];
+        assert!(hlsl.contains("    float u_gain : packoffset(c0.x);\n"), "{}", hlsl);
+        assert!(hlsl.contains("    float3x3 u_transform : packoffset(c1.x);\n"), "{}", hlsl);
+        assert!(hlsl.contains("    float2 u_bias : packoffset(c4.x);\n"), "{}", hlsl);
+    }
+
+    #[test]
+    fn shifted_constants_are_not_sampled() {
+        let module = emit_spirv("constants", passes(SHIFTED_CONSTANTS));
+        let spirv = disassemble(&module.fragment_shaders[&0]);
+        // only input is sampled
+        assert_eq!(spirv.matches("OpImageSampleImplicitLod").count(), 1, "{}", spirv);
+    }
+}
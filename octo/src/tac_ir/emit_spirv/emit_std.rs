@@ -62,7 +62,7 @@ fn emit_round<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting round of {:?}", data_0_type);
+    log::debug!("emitting round of {:?}", data_0_type);
 
 
     
@@ -94,7 +94,7 @@ fn emit_trunc<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting trunc of {:?}", data_0_type);
+    log::debug!("emitting trunc of {:?}", data_0_type);
 
 
     
@@ -127,7 +127,7 @@ fn emit_abs<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting abs of {:?}", data_0_type);
+    log::debug!("emitting abs of {:?}", data_0_type);
 
 
     
@@ -164,7 +164,7 @@ fn emit_sign<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting sign of {:?}", data_0_type);
+    log::debug!("emitting sign of {:?}", data_0_type);
 
 
     
@@ -200,7 +200,7 @@ fn emit_floor<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting floor of {:?}", data_0_type);
+    log::debug!("emitting floor of {:?}", data_0_type);
 
 
     
@@ -232,7 +232,7 @@ fn emit_ceil<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting ceil of {:?}", data_0_type);
+    log::debug!("emitting ceil of {:?}", data_0_type);
 
 
     
@@ -264,7 +264,7 @@ fn emit_fract<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting fract of {:?}", data_0_type);
+    log::debug!("emitting fract of {:?}", data_0_type);
 
 
     
@@ -296,7 +296,7 @@ fn emit_radians<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_ad
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting radians of {:?}", data_0_type);
+    log::debug!("emitting radians of {:?}", data_0_type);
 
 
     
@@ -328,7 +328,7 @@ fn emit_degrees<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_ad
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting degrees of {:?}", data_0_type);
+    log::debug!("emitting degrees of {:?}", data_0_type);
 
 
     
@@ -360,7 +360,7 @@ fn emit_sin<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting sin of {:?}", data_0_type);
+    log::debug!("emitting sin of {:?}", data_0_type);
 
 
     
@@ -392,7 +392,7 @@ fn emit_cos<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting cos of {:?}", data_0_type);
+    log::debug!("emitting cos of {:?}", data_0_type);
 
 
     
@@ -424,7 +424,7 @@ fn emit_tan<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting tan of {:?}", data_0_type);
+    log::debug!("emitting tan of {:?}", data_0_type);
 
 
     
@@ -456,7 +456,7 @@ fn emit_asin<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting asin of {:?}", data_0_type);
+    log::debug!("emitting asin of {:?}", data_0_type);
 
 
     
@@ -488,7 +488,7 @@ fn emit_acos<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting acos of {:?}", data_0_type);
+    log::debug!("emitting acos of {:?}", data_0_type);
 
 
     
@@ -520,7 +520,7 @@ fn emit_atan<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting atan of {:?}", data_0_type);
+    log::debug!("emitting atan of {:?}", data_0_type);
 
 
     
@@ -552,7 +552,7 @@ fn emit_sinh<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting sinh of {:?}", data_0_type);
+    log::debug!("emitting sinh of {:?}", data_0_type);
 
 
     
@@ -584,7 +584,7 @@ fn emit_cosh<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting cosh of {:?}", data_0_type);
+    log::debug!("emitting cosh of {:?}", data_0_type);
 
 
     
@@ -616,7 +616,7 @@ fn emit_tanh<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting tanh of {:?}", data_0_type);
+    log::debug!("emitting tanh of {:?}", data_0_type);
 
 
     
@@ -648,7 +648,7 @@ fn emit_asinh<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting asinh of {:?}", data_0_type);
+    log::debug!("emitting asinh of {:?}", data_0_type);
 
 
     
@@ -680,7 +680,7 @@ fn emit_acosh<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting acosh of {:?}", data_0_type);
+    log::debug!("emitting acosh of {:?}", data_0_type);
 
 
     
@@ -712,7 +712,7 @@ fn emit_atanh<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting atanh of {:?}", data_0_type);
+    log::debug!("emitting atanh of {:?}", data_0_type);
 
 
     
@@ -751,7 +751,7 @@ fn emit_atan2<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address,data_1: A
     let data_1_type = emitter.get_single_type(data_1);
     
     let args =[data_0_type, data_1_type,  ];
-    println!("emitting atan2 of {:?}", data_0_type);
+    log::debug!("emitting atan2 of {:?}", data_0_type);
 
 
     
@@ -821,7 +821,7 @@ fn emit_pow<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address,data_1: Add
     let data_1_type = emitter.get_single_type(data_1);
     
     let args =[data_0_type, data_1_type,  ];
-    println!("emitting pow of {:?}", data_0_type);
+    log::debug!("emitting pow of {:?}", data_0_type);
 
 
     
@@ -866,7 +866,7 @@ fn emit_exp<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting exp of {:?}", data_0_type);
+    log::debug!("emitting exp of {:?}", data_0_type);
 
 
     
@@ -898,7 +898,7 @@ fn emit_log<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting log of {:?}", data_0_type);
+    log::debug!("emitting log of {:?}", data_0_type);
 
 
     
@@ -930,7 +930,7 @@ fn emit_exp2<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting exp2 of {:?}", data_0_type);
+    log::debug!("emitting exp2 of {:?}", data_0_type);
 
 
     
@@ -962,7 +962,7 @@ fn emit_log2<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting log2 of {:?}", data_0_type);
+    log::debug!("emitting log2 of {:?}", data_0_type);
 
 
     
@@ -994,7 +994,7 @@ fn emit_sqrt<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_addr:
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting sqrt of {:?}", data_0_type);
+    log::debug!("emitting sqrt of {:?}", data_0_type);
 
 
     
@@ -1039,7 +1039,7 @@ fn emit_min<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address,data_1: Add
     let data_1_type = emitter.get_single_type(data_1);
     
     let args =[data_0_type, data_1_type,  ];
-    println!("emitting min of {:?}", data_0_type);
+    log::debug!("emitting min of {:?}", data_0_type);
 
 
     
@@ -1101,7 +1101,7 @@ fn emit_max<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address,data_1: Add
     let data_1_type = emitter.get_single_type(data_1);
     
     let args =[data_0_type, data_1_type,  ];
-    println!("emitting max of {:?}", data_0_type);
+    log::debug!("emitting max of {:?}", data_0_type);
 
 
     
@@ -1164,7 +1164,7 @@ fn emit_clamp<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address,data_1: A
     let data_2_type = emitter.get_single_type(data_2);
     
     let args =[data_0_type, data_1_type, data_2_type,  ];
-    println!("emitting clamp of {:?}", data_0_type);
+    log::debug!("emitting clamp of {:?}", data_0_type);
 
 
     
@@ -1232,7 +1232,7 @@ fn emit_dot<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address,data_1: Add
     let data_1_type = emitter.get_single_type(data_1);
     
     let args =[data_0_type, data_1_type,  ];
-    println!("emitting dot of {:?}", data_0_type);
+    log::debug!("emitting dot of {:?}", data_0_type);
 
 
     
@@ -1295,7 +1295,7 @@ fn emit_length<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_add
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting length of {:?}", data_0_type);
+    log::debug!("emitting length of {:?}", data_0_type);
 
 
     
@@ -1347,7 +1347,7 @@ fn emit_cross<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address,data_1: A
     let data_1_type = emitter.get_single_type(data_1);
     
     let args =[data_0_type, data_1_type,  ];
-    println!("emitting cross of {:?}", data_0_type);
+    log::debug!("emitting cross of {:?}", data_0_type);
 
 
     
@@ -1396,7 +1396,7 @@ fn emit_normalize<'a, I: std::iter::Iterator<Item=&'a Op>>(data_0: Address, ret_
     let data_0_type = emitter.get_single_type(data_0);
     
     let args =[data_0_type,  ];
-    println!("emitting normalize of {:?}", data_0_type);
+    log::debug!("emitting normalize of {:?}", data_0_type);
 
 
     
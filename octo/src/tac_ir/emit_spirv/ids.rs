@@ -111,7 +111,7 @@ impl SpirvIds {
         }
 
         let num_of_textures = info.input_type.len() as u32;
-        println!("num of texture: {}", num_of_textures);
+        log::debug!("num of texture: {}", num_of_textures);
         if num_of_textures > 0 {
             // textures array
             self.texture_type = module.type_image(
@@ -128,7 +128,7 @@ impl SpirvIds {
             let num_id = module.constant_u32(self.map_type(ValueType::Int), num_of_textures);
 
             let array_type_id = module.type_array(self.texture_type, num_id);
-            println!("emitting array type pointer: {}, for type: {}", array_type_id, self.texture_type);
+            log::debug!("emitting array type pointer: {}, for type: {}", array_type_id, self.texture_type);
 
             self.texture_array_type =
                 module.type_pointer(None, spirv::StorageClass::UniformConstant, array_type_id);
@@ -256,7 +256,7 @@ impl SpirvIds {
         self.textures_location = module.id();
         self.sampler_location = module.id();
         self.push_constants_location = module.id();
-        println!("uv: {}, tex: {}, sampler: {}, push_constants_location: {}", self.uv_location, self.textures_location, self.sampler_location, self.push_constants_location);
+        log::debug!("uv: {}, tex: {}, sampler: {}, push_constants_location: {}", self.uv_location, self.textures_location, self.sampler_location, self.push_constants_location);
         self.output_locations = Vec::with_capacity(info.output_type.len());
         for _ret in &info.output_type {
             self.output_locations.push(module.id());
@@ -272,7 +272,7 @@ impl SpirvIds {
     }
 
     pub fn create_uniform_variables(&mut self, module: &mut Builder, info: &ShaderDef, uniforms: &Vec<(ValueType, String)>) {
-        println!("texture array type: {}, sampler pointer type: {}", self.texture_array_type, self.sampler_pointer_type);
+        log::debug!("texture array type: {}, sampler pointer type: {}", self.texture_array_type, self.sampler_pointer_type);
 
         if info.input_type.len() > 0{
             module.variable(
@@ -347,17 +347,7 @@ impl SpirvIds {
 
         let mut offset = 0u32;
         for (id, member) in uniforms.iter().enumerate() {
-            let member_size = match member.0 {
-                ValueType::Float => 16,
-                ValueType::Vec2 => 16,
-                ValueType::Vec3 => 16,
-                ValueType::Vec4 => 16,
-                ValueType::Mat3 => 36,
-                ValueType::Mat4 => 64,
-                ValueType::Int => 16,
-                ValueType::Bool => 16,
-                _ => panic!(),
-            };
+            let member_size = super::uniform_size(member.0) as u32;
             
             module.member_decorate(
                 self.push_constants_type,
@@ -377,7 +367,7 @@ impl SpirvIds {
                         self.push_constants_type,
                         id as u32,
                         spirv::Decoration::MatrixStride,
-                        &[9u32.into()],
+                        &[16u32.into()],
                     );
                 }
                 ValueType::Mat4=>{
@@ -4,7 +4,7 @@ use super::super::utils::{find_loop, LoopCode, PeekableCode, find_if_else, IfEls
 use super::ids::SpirvIds;
 use super::emit_std::emit_std_function;
 
-use super::ir::{Address, Op, Operation, ValueType, StdFunction};
+use super::ir::{Address, EdgeMode, Op, Operation, ValueType, StdFunction};
 
 use super::spirv;
 use super::emit_std::*;
@@ -625,7 +625,7 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
         let value_type = self.get_single_type(value);
         let result_address = self.map(ret);
 
-        println!("negating type: {:?}", value_type);
+        log::debug!("negating type: {:?}", value_type);
         let return_type = self.ids.map_type(value_type);
 
         let float_negation = |builder: &mut rspirv::mr::Builder, map: &mut HashMap<Address, ValueType>|{
@@ -746,7 +746,7 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
     }
 
     fn emit_block(&mut self, id: Option<SpirvAddress>) -> SpirvAddress {
-        println!("emitting block {:?}", id);
+        log::debug!("emitting block {:?}", id);
         self.current_block = self.builder.begin_basic_block(id).unwrap();
         self.current_block
     }
@@ -761,6 +761,52 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
         ret_addr
     }
 
+    // coordinates wrapped into texture according to edge mode
+    fn edge_uv(&mut self, uv: SpirvAddress, mode: EdgeMode) -> SpirvAddress {
+        let float_type = self.ids.map_type(ValueType::Float);
+        let vec2_type = self.ids.map_type(ValueType::Vec2);
+        let zero_f = self.builder.constant_f32(float_type, 0.0);
+        let one_f = self.builder.constant_f32(float_type, 1.0);
+        let zero = self.builder.constant_composite(vec2_type, &[zero_f, zero_f]);
+        let one = self.builder.constant_composite(vec2_type, &[one_f, one_f]);
+        match mode {
+            // FClamp
+            EdgeMode::Clamp => self.builder.ext_inst(vec2_type, None, self.glsl_id, 43, &[uv, zero, one]).unwrap(),
+            // Fract
+            EdgeMode::Repeat => self.builder.ext_inst(vec2_type, None, self.glsl_id, 10, &[uv]).unwrap(),
+            // 1 - |2 * fract(uv / 2) - 1| reflects every other repetition
+            EdgeMode::Mirror => {
+                let half_f = self.builder.constant_f32(float_type, 0.5);
+                let two_f = self.builder.constant_f32(float_type, 2.0);
+                let halved = self.builder.vector_times_scalar(vec2_type, None, uv, half_f).unwrap();
+                let fract = self.builder.ext_inst(vec2_type, None, self.glsl_id, 10, &[halved]).unwrap();
+                let doubled = self.builder.vector_times_scalar(vec2_type, None, fract, two_f).unwrap();
+                let centered = self.builder.fsub(vec2_type, None, doubled, one).unwrap();
+                let abs = self.builder.ext_inst(vec2_type, None, self.glsl_id, 4, &[centered]).unwrap();
+                self.builder.fsub(vec2_type, None, one, abs).unwrap()
+            }
+            // sampled as is, result is masked afterwards
+            EdgeMode::Zero => uv,
+        }
+    }
+
+    // 1.0 if coordinates lie inside texture, 0.0 otherwise
+    fn inside_mask(&mut self, uv: SpirvAddress) -> SpirvAddress {
+        let float_type = self.ids.map_type(ValueType::Float);
+        let vec2_type = self.ids.map_type(ValueType::Vec2);
+        let zero_f = self.builder.constant_f32(float_type, 0.0);
+        let one_f = self.builder.constant_f32(float_type, 1.0);
+        let zero = self.builder.constant_composite(vec2_type, &[zero_f, zero_f]);
+        let one = self.builder.constant_composite(vec2_type, &[one_f, one_f]);
+        // Step
+        let above = self.builder.ext_inst(vec2_type, None, self.glsl_id, 48, &[zero, uv]).unwrap();
+        let below = self.builder.ext_inst(vec2_type, None, self.glsl_id, 48, &[uv, one]).unwrap();
+        let both = self.builder.fmul(vec2_type, None, above, below).unwrap();
+        let x = self.builder.composite_extract(float_type, None, both, &[0]).unwrap();
+        let y = self.builder.composite_extract(float_type, None, both, &[1]).unwrap();
+        self.builder.fmul(float_type, None, x, y).unwrap()
+    }
+
     pub fn emit_passthrough(&mut self, id: SpirvAddress, arg: Address, ret: Address) -> SpirvAddress {
         self.emit_glsl_ext_many(id, &[arg], ret)
     }
@@ -768,7 +814,7 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
     fn emit_glsl_ext_many(&mut self, id: SpirvAddress, args: &[Address], ret: Address) -> SpirvAddress{
         let typ = self.get_single_type(args[0]);
         let ret_type = self.ids.map_type(typ);
-        println!("{:?} is now of type {:?}", ret, typ);
+        log::debug!("{:?} is now of type {:?}", ret, typ);
         let spirv_addresses: Vec<_> = args.iter().map(|x| self.map(*x)).collect();
         self.type_map.insert(ret, typ);
         let ret = self.map(ret);
@@ -778,7 +824,7 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
 
     pub fn emit_prototyped(&mut self, id: SpirvAddress, args: &[Address], ret: Address, ret_type: ValueType) -> SpirvAddress {
         self.type_map.insert(ret, ret_type);
-        println!("{:?} is now of type {:?}", ret, ret_type);
+        log::debug!("{:?} is now of type {:?}", ret, ret_type);
         let ret_type = self.ids.map_type(ret_type);
 
         let spirv_addresses: Vec<_> = args.iter().map(|x| self.map(*x)).collect();
@@ -1096,7 +1142,7 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
                 self.emit_or(left, right, ret);
             }
             Operation::Label => {
-                println!("emitting label: {}", ret);
+                log::debug!("emitting label: {}", ret);
                 self.last_label = ret;
                 let id = self.map(ret);
                 self.emit_block(Some(id));
@@ -1105,6 +1151,10 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
                 let value_addr = self.value_map[&val];
                 self.ids.store_result(0, value_addr, self.builder);
             }
+            Operation::Output(id, val) => {
+                let value_addr = self.value_map[&val];
+                self.ids.store_result(id, value_addr, self.builder);
+            }
             Operation::Invoke(function) => {
                 self.emit_invoke(function, ret);
             }
@@ -1139,20 +1189,37 @@ impl<'a, I: std::iter::Iterator<Item = &'a Op>> MainEmitter<'a, I> {
             Operation::StoreVec4(..) => (),
             Operation::StoreBool(..) => (),
 
-            Operation::Shift(what, by_how_much) => {
+            Operation::Shift(what, by_how_much, mode) => {
                 let uv = self.ids.access_uv(self.builder);
                 let shift = self.map(by_how_much);
                 let vec2type = self.ids.map_type(ValueType::Vec2);
                 let shifted_uv = self.builder.fadd(vec2type, None, shift, uv).unwrap();
-                let arg_id = self.args[&what];
-                let input_type = self.input_type[arg_id];
-
-                let value = self.ids.sample_arg_at(arg_id, ret, shifted_uv, input_type, self.builder);
+                let (mut value, input_type) = match self.args.get(&what).copied() {
+                    Some(arg_id) => {
+                        let input_type = self.input_type[arg_id];
+                        let sampled_uv = match mode {
+                            Some(mode) => self.edge_uv(shifted_uv, mode),
+                            None => shifted_uv,
+                        };
+                        let value = self.ids.sample_arg_at(arg_id, ret, sampled_uv, input_type, self.builder);
+                        (value, input_type)
+                    }
+                    // shifted constant is the same everywhere, only zero mode masks it outside of texture
+                    None => (self.map(what), self.get_single_type(what)),
+                };
+                if mode == Some(EdgeMode::Zero) {
+                    let inside = self.inside_mask(shifted_uv);
+                    let value_type = self.ids.map_type(input_type);
+                    value = match input_type {
+                        ValueType::Float => self.builder.fmul(value_type, None, value, inside).unwrap(),
+                        _ => self.builder.vector_times_scalar(value_type, None, value, inside).unwrap(),
+                    };
+                }
 
                 self.insert(ret, value);
-                self.set_type(ret, self.input_type[arg_id]);
+                self.set_type(ret, input_type);
             }
-            Operation::Sync(..) => {
+            Operation::Sync(..) | Operation::Scale(..) => {
                 panic!("internal compiler error: should never happen");
             }
             Operation::Jump(label) => {
@@ -0,0 +1,145 @@
+use rspirv::binary::Assemble;
+use rspirv::mr::{Builder, Operand};
+use spirv_headers as spirv;
+
+/// Fullscreen triangle, the same as basic_vertex.glsl:
+///
+/// outUV = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+/// gl_Position = vec4(outUV * 2.0f + -1.0f, 0.0f, 1.0f);
+pub fn create_basic_vertex() -> Vec<u32> {
+    let mut module = Builder::new();
+    module.capability(spirv::Capability::Shader);
+    module.memory_model(spirv::AddressingModel::Logical, spirv::MemoryModel::GLSL450);
+
+    let void_type = module.type_void();
+    let int_type = module.type_int(32, 1);
+    let float_type = module.type_float(32);
+    let vec2_type = module.type_vector(float_type, 2);
+    let vec4_type = module.type_vector(float_type, 4);
+    let int_input_type = module.type_pointer(None, spirv::StorageClass::Input, int_type);
+    let vec2_output_type = module.type_pointer(None, spirv::StorageClass::Output, vec2_type);
+    let vec4_output_type = module.type_pointer(None, spirv::StorageClass::Output, vec4_type);
+    let main_type = module.type_function(void_type, vec![]);
+
+    let one = module.constant_u32(int_type, 1);
+    let two = module.constant_u32(int_type, 2);
+    let two_f = module.constant_f32(float_type, 2.0);
+    let minus_one_f = module.constant_f32(float_type, -1.0);
+    let zero_f = module.constant_f32(float_type, 0.0);
+    let one_f = module.constant_f32(float_type, 1.0);
+    let minus_one_vec = module.constant_composite(vec2_type, &[minus_one_f, minus_one_f]);
+
+    let vertex_index = module.variable(int_input_type, None, spirv::StorageClass::Input, None);
+    let position = module.variable(vec4_output_type, None, spirv::StorageClass::Output, None);
+    let out_uv = module.variable(vec2_output_type, None, spirv::StorageClass::Output, None);
+
+    module.decorate(
+        vertex_index,
+        spirv::Decoration::BuiltIn,
+        &[Operand::BuiltIn(spirv::BuiltIn::VertexIndex)],
+    );
+    module.decorate(
+        position,
+        spirv::Decoration::BuiltIn,
+        &[Operand::BuiltIn(spirv::BuiltIn::Position)],
+    );
+    module.decorate(out_uv, spirv::Decoration::Location, &[0u32.into()]);
+
+    let function_id = module.id();
+    module.entry_point(
+        spirv::ExecutionModel::Vertex,
+        function_id,
+        "main",
+        &[vertex_index, position, out_uv],
+    );
+
+    module
+        .begin_function(void_type, Some(function_id), spirv::FunctionControl::NONE, main_type)
+        .unwrap();
+    module.begin_basic_block(None).unwrap();
+
+    let index = module.load(int_type, None, vertex_index, None, &[]).unwrap();
+    let shifted = module.shift_left_logical(int_type, None, index, one).unwrap();
+    let x = module.bitwise_and(int_type, None, shifted, two).unwrap();
+    let y = module.bitwise_and(int_type, None, index, two).unwrap();
+    let x = module.convert_s_to_f(float_type, None, x).unwrap();
+    let y = module.convert_s_to_f(float_type, None, y).unwrap();
+    let uv = module.composite_construct(vec2_type, None, &[x, y]).unwrap();
+    module.store(out_uv, uv, None, &[]).unwrap();
+
+    let scaled = module.vector_times_scalar(vec2_type, None, uv, two_f).unwrap();
+    let moved = module.fadd(vec2_type, None, scaled, minus_one_vec).unwrap();
+    let x = module.composite_extract(float_type, None, moved, &[0]).unwrap();
+    let y = module.composite_extract(float_type, None, moved, &[1]).unwrap();
+    let pos = module.composite_construct(vec4_type, None, &[x, y, zero_f, one_f]).unwrap();
+    module.store(position, pos, None, &[]).unwrap();
+
+    module.ret().unwrap();
+    module.end_function().unwrap();
+
+    module.module().assemble()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what render passes rely on: vertex entry point, built-ins and location of uv
+    fn interface(words: &[u32]) -> Vec<String> {
+        let module = match rspirv::mr::load_words(words) {
+            Result::Ok(x) => x,
+            Result::Err(err) => panic!("invalid SPIR-V: {:?}", err),
+        };
+        assert_eq!(module.entry_points.len(), 1);
+        let entry = &module.entry_points[0];
+        let mut interface = vec![format!("{:?}", entry.operands[0]), format!("{:?}", entry.operands[2])];
+        for annotation in &module.annotations {
+            for operand in &annotation.operands {
+                if let Operand::BuiltIn(spirv::BuiltIn::VertexIndex) | Operand::BuiltIn(spirv::BuiltIn::Position) = operand {
+                    interface.push(format!("{:?}", operand));
+                }
+            }
+            if let [_, Operand::Decoration(spirv::Decoration::Location), location] = annotation.operands.as_slice() {
+                interface.push(format!("Location {:?}", location));
+            }
+        }
+        interface.sort();
+        interface.dedup();
+        interface
+    }
+
+    // SPIRV-Tools aren't a dependency, full validation runs only where spirv-val is installed
+    fn validate(words: &[u32]) {
+        let path = std::env::temp_dir().join(format!("octo-vertex-{}.spv", std::process::id()));
+        let bytes: Vec<u8> = words.iter().flat_map(|x| x.to_le_bytes().to_vec()).collect();
+        std::fs::write(&path, bytes).unwrap();
+        let status = std::process::Command::new("spirv-val").arg(&path).status();
+        let _ = std::fs::remove_file(&path);
+        match status {
+            Result::Ok(status) => assert!(status.success(), "spirv-val rejected vertex shader"),
+            Result::Err(_) => log::warn!("spirv-val not found, vertex shader was only parsed"),
+        }
+    }
+
+    #[test]
+    fn vertex_module_is_valid() {
+        let words = create_basic_vertex();
+        let mut expected = vec![
+            format!("{:?}", Operand::ExecutionModel(spirv::ExecutionModel::Vertex)),
+            format!("{:?}", Operand::LiteralString("main".to_owned())),
+            format!("{:?}", Operand::BuiltIn(spirv::BuiltIn::VertexIndex)),
+            format!("{:?}", Operand::BuiltIn(spirv::BuiltIn::Position)),
+            format!("Location {:?}", Operand::LiteralInt32(0)),
+        ];
+        expected.sort();
+        assert_eq!(interface(&words), expected);
+        validate(&words);
+    }
+
+    #[cfg(feature = "shaderc")]
+    #[test]
+    fn same_interface_as_shaderc_build() {
+        let reference = super::super::create_basic_vertex();
+        assert_eq!(interface(&create_basic_vertex()), interface(&reference));
+    }
+}
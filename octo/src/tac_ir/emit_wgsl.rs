@@ -0,0 +1,336 @@
+use octo_runtime as or;
+
+use super::emit_source::{emit_body, Dialect};
+use super::emit_spirv::uniform_size;
+use super::ir::ValueType;
+use super::{PipelineDef, ShaderDef};
+
+// sampler and textures go to first bind group, uniform buffer to the second one
+const TEXTURES_GROUP: u32 = 0;
+const UNIFORMS_GROUP: u32 = 1;
+
+/// Emits WGSL source for every shader of pipeline, together with its bind group layout.
+pub fn emit_wgsl(code: &PipelineDef) -> Vec<or::WgslShader> {
+    code.shaders
+        .iter()
+        .map(|x| emit_single_shader(x, &code.uniforms))
+        .collect()
+}
+
+struct Wgsl;
+
+impl Dialect for Wgsl {
+    fn type_name(&self, typ: ValueType) -> String {
+        match typ {
+            ValueType::Float => "f32",
+            ValueType::Vec2 => "vec2<f32>",
+            ValueType::Vec3 => "vec3<f32>",
+            ValueType::Vec4 => "vec4<f32>",
+            ValueType::Mat3 => "mat3x3<f32>",
+            ValueType::Mat4 => "mat4x4<f32>",
+            ValueType::Int => "i32",
+            ValueType::Bool => "bool",
+            _ => panic!("internal compiler error: no wgsl type for {:?}", typ),
+        }
+        .to_owned()
+    }
+
+    fn declare(&self, name: &str, typ: ValueType) -> String {
+        format!("var {}: {};", name, self.type_name(typ))
+    }
+
+    fn declare_init(&self, name: &str, typ: ValueType, value: &str) -> String {
+        format!("let {}: {} = {};", name, self.type_name(typ), value)
+    }
+
+    // explicit level, implicit one is allowed only in uniform control flow
+    fn sample(&self, texture: usize, uv: &str) -> String {
+        format!("textureSampleLevel(texture_{}, samp, {}, 0.0)", texture, uv)
+    }
+
+    // bool can't be stored in uniform buffer
+    fn uniform(&self, name: &str, typ: ValueType) -> String {
+        match typ {
+            ValueType::Bool => format!("(uniforms.{} != 0u)", name),
+            _ => format!("uniforms.{}", name),
+        }
+    }
+
+    fn result(&self, id: usize) -> String {
+        format!("output.result_{}", id)
+    }
+
+    fn loop_start(&self) -> &'static str {
+        "loop {"
+    }
+
+    fn equal(&self, left: &str, right: &str, vector: bool) -> String {
+        if vector {
+            format!("all({} == {})", left, right)
+        } else {
+            format!("{} == {}", left, right)
+        }
+    }
+
+    fn not_equal(&self, left: &str, right: &str, vector: bool) -> String {
+        if vector {
+            format!("any({} != {})", left, right)
+        } else {
+            format!("{} != {}", left, right)
+        }
+    }
+
+    // wgsl doesn't mix scalars and vectors in calls, so scalars are splatted
+    fn call(&self, name: &str, args: &[(String, ValueType)], result: ValueType) -> String {
+        let args: Vec<_> = args
+            .iter()
+            .map(|(value, typ)| match (name, *typ, result) {
+                ("dot", _, _) | ("length", _, _) => value.clone(),
+                (_, ValueType::Float, ValueType::Vec2)
+                | (_, ValueType::Float, ValueType::Vec3)
+                | (_, ValueType::Float, ValueType::Vec4) => format!("{}({})", self.type_name(result), value),
+                _ => value.clone(),
+            })
+            .collect();
+        format!("{}({})", name, args.join(", "))
+    }
+}
+
+fn emit_single_shader(info: &ShaderDef, uniforms: &Vec<(ValueType, String)>) -> or::WgslShader {
+    let wgsl = Wgsl;
+    let body = emit_body(&wgsl, info, uniforms);
+
+    let mut bindings = or::WgslBindings {
+        sampler: None,
+        textures: vec![],
+        uniforms: None,
+    };
+    let mut source = String::new();
+
+    if uniforms.len() > 0 {
+        source.push_str("struct Uniforms {\n");
+        for (typ, name) in uniforms {
+            let type_name = match typ {
+                ValueType::Bool => "u32".to_owned(),
+                x => wgsl.type_name(*x),
+            };
+            // wgsl has no offset attribute, explicit sizes give the same offsets as push constants of SPIR-V
+            source.push_str(&format!("    @size({}) {}: {},\n", uniform_size(*typ), name, type_name));
+        }
+        source.push_str("};\n\n");
+    }
+
+    source.push_str("struct Output {\n");
+    for (id, typ) in info.output_type.iter().enumerate() {
+        source.push_str(&format!("    @location({}) result_{}: {},\n", id, id, wgsl.type_name(*typ)));
+    }
+    source.push_str("};\n\n");
+
+    if info.input_type.len() > 0 {
+        source.push_str(&format!("@group({}) @binding(0) var samp: sampler;\n", TEXTURES_GROUP));
+        bindings.sampler = Some((TEXTURES_GROUP, 0));
+        for id in 0..info.input_type.len() {
+            let binding = id as u32 + 1;
+            source.push_str(&format!(
+                "@group({}) @binding({}) var texture_{}: texture_2d<f32>;\n",
+                TEXTURES_GROUP, binding, id
+            ));
+            bindings.textures.push((TEXTURES_GROUP, binding));
+        }
+    }
+    if uniforms.len() > 0 {
+        source.push_str(&format!("@group({}) @binding(0) var<uniform> uniforms: Uniforms;\n", UNIFORMS_GROUP));
+        bindings.uniforms = Some((UNIFORMS_GROUP, 0));
+    }
+    source.push('\n');
+
+    source.push_str("@fragment\nfn main(@location(0) uv: vec2<f32>) -> Output {\n");
+    source.push_str("    var output: Output;\n");
+    for (address, typ) in &body.variables {
+        source.push_str(&format!("    {}\n", wgsl.declare(&format!("v{}", address), *typ)));
+    }
+    source.push('\n');
+    for line in body.lines {
+        source.push_str(&line);
+        source.push('\n');
+    }
+    source.push_str("    return output;\n}\n");
+
+    or::WgslShader { source, bindings }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{loop_shader, passes, MATRICES};
+
+    // bool uniform is stored as integer
+    const FLAGS: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        with {
+            invert: bool,
+            gain: float,
+        }
+        {
+            let c = color * gain;
+            if (invert) {
+                c = vec4(1.0, 1.0, 1.0, 1.0) - c;
+            }
+            return c;
+        }
+    ";
+
+    // exponent is scalar, while base is vector
+    const POWER: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        {
+            return pow(color, 2.2) * length(color);
+        }
+    ";
+
+    const INPUTS: &str = "
+        pipeline main(a: vec4, b: vec4, c: float) -> (vec4)
+        {
+            return a + b * c;
+        }
+    ";
+
+    const UNIFORMS_ONLY: &str = "
+        pipeline main() -> (vec4)
+        with {
+            tint: vec4,
+        }
+        {
+            return tint;
+        }
+    ";
+
+    fn shader(src: &str) -> or::WgslShader {
+        emit_wgsl(&passes(src)).remove(0)
+    }
+
+    #[test]
+    fn loop_with_uniform() {
+        let (shader, uniforms) = loop_shader();
+        let expected = "\
+struct Uniforms {
+    @size(16) gain: f32,
+};
+
+struct Output {
+    @location(0) result_0: f32,
+};
+
+@group(0) @binding(0) var samp: sampler;
+@group(0) @binding(1) var texture_0: texture_2d<f32>;
+@group(1) @binding(0) var<uniform> uniforms: Uniforms;
+
+@fragment
+fn main(@location(0) uv: vec2<f32>) -> Output {
+    var output: Output;
+    var v2: vec4<f32>;
+    var v3: f32;
+    var v10: i32;
+    var v11: f32;
+    var v15: bool;
+    var v18: f32;
+    var v19: f32;
+    var v20: f32;
+    var v23: i32;
+
+    v2 = textureSampleLevel(texture_0, samp, uv, 0.0);
+    v3 = uniforms.gain;
+    v10 = 0;
+    v11 = 0.0;
+    loop {
+        v15 = v10 < 3;
+        if (!v15) {
+            break;
+        }
+        v18 = length(v2);
+        v19 = v18 * v3;
+        v20 = v11 + v19;
+        v23 = v10 + 1;
+        v10 = v23;
+        v11 = v20;
+    }
+    output.result_0 = v11;
+    return output;
+}
+";
+        let emitted = emit_single_shader(&shader, &uniforms);
+        assert_eq!(emitted.source, expected);
+        assert_eq!(
+            emitted.bindings,
+            or::WgslBindings {
+                sampler: Some((0, 0)),
+                textures: vec![(0, 1)],
+                uniforms: Some((1, 0)),
+            }
+        );
+    }
+
+    #[test]
+    fn bool_uniforms_are_compared_to_zero() {
+        let source = shader(FLAGS).source;
+        assert!(source.contains(
+            "struct Uniforms {\n\
+             \x20   @size(16) invert: u32,\n\
+             \x20   @size(16) gain: f32,\n\
+             };\n"
+        ));
+        assert!(source.contains(" = (uniforms.invert != 0u);\n"));
+    }
+
+    #[test]
+    fn scalars_are_splatted_in_calls() {
+        let source = shader(POWER).source;
+        assert!(source.contains(" = pow(v"));
+        assert!(source.contains(", vec4<f32>(2.2));\n"));
+        // arguments of dot and length are never splatted
+        assert!(source.contains(" = length(v"));
+    }
+
+    #[test]
+    fn textures_and_uniforms_have_separate_groups() {
+        let inputs = shader(INPUTS);
+        assert_eq!(
+            inputs.bindings,
+            or::WgslBindings {
+                sampler: Some((0, 0)),
+                textures: vec![(0, 1), (0, 2), (0, 3)],
+                uniforms: None,
+            }
+        );
+        assert!(inputs.source.contains("@group(0) @binding(3) var texture_2: texture_2d<f32>;\n"));
+        assert!(!inputs.source.contains("var<uniform>"));
+
+        let uniforms = shader(UNIFORMS_ONLY);
+        assert_eq!(
+            uniforms.bindings,
+            or::WgslBindings {
+                sampler: None,
+                textures: vec![],
+                uniforms: Some((1, 0)),
+            }
+        );
+        assert!(uniforms.source.contains("@group(1) @binding(0) var<uniform> uniforms: Uniforms;\n"));
+        assert!(!uniforms.source.contains("sampler"));
+    }
+
+    #[test]
+    fn matrix_uniforms_are_padded() {
+        // mat3x3 columns are aligned to 16 bytes, every vec3 after it takes whole 16 bytes as well
+        let source = shader(MATRICES).source;
+        assert!(source.contains(
+            "struct Uniforms {\n\
+             \x20   @size(16) gain: f32,\n\
+             \x20   @size(48) transform: mat3x3<f32>,\n\
+             \x20   @size(16) offset: vec3<f32>,\n\
+             \x20   @size(16) tint: vec3<f32>,\n\
+             };\n"
+        ));
+        assert!(source.contains("    var v"));
+        assert!(source.contains(": mat3x3<f32>;\n"));
+    }
+}
@@ -0,0 +1,604 @@
+use std::collections::HashMap;
+
+use super::ir::{Address, ConstantValue, EdgeMode, Op, Operation, PipelineIR, StdFunction};
+use super::split_passes::{InputTexture, OutputTexture, PipelineDef, TextureSize};
+
+/// Image kept in memory, one value per pixel.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<ConstantValue>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, value: ConstantValue) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![value; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> ConstantValue {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, value: ConstantValue) {
+        self.pixels[y * self.width + x] = value;
+    }
+
+    /// Nearest texel, coordinates outside of image are clamped to edge.
+    pub fn sample(&self, uv: [f64; 2]) -> ConstantValue {
+        let clamp = |v: f64, size: usize| {
+            let v = (v * size as f64).floor();
+            if v < 0.0 {
+                0
+            } else if v as usize >= size {
+                size - 1
+            } else {
+                v as usize
+            }
+        };
+        self.get(clamp(uv[0], self.width), clamp(uv[1], self.height))
+    }
+}
+
+// coordinates sampled by shift with given edge mode, sampling itself clamps to edge
+fn edge_uv(uv: [f64; 2], mode: Option<EdgeMode>) -> [f64; 2] {
+    let wrap = |x: f64| match mode {
+        Some(EdgeMode::Clamp) => x.max(0.0).min(1.0),
+        Some(EdgeMode::Repeat) => x - x.floor(),
+        Some(EdgeMode::Mirror) => 1.0 - ((x * 0.5 - (x * 0.5).floor()) * 2.0 - 1.0).abs(),
+        Some(EdgeMode::Zero) | None => x,
+    };
+    [wrap(uv[0]), wrap(uv[1])]
+}
+
+pub fn scaled_size(width: usize, height: usize, factor: f64) -> (usize, usize) {
+    let scale = |x: usize| std::cmp::max(1, (x as f64 * factor).round() as usize);
+    (scale(width), scale(height))
+}
+
+fn pixel_uv(x: usize, y: usize, width: usize, height: usize) -> [f64; 2] {
+    [(x as f64 + 0.5) / width as f64, (y as f64 + 0.5) / height as f64]
+}
+
+/// Runs not split pipeline. Sync and Scale operations wait for all pixels before storing value into texture.
+pub fn interpret(
+    program: &PipelineIR,
+    inputs: &[Image],
+    uniforms: &[ConstantValue],
+    width: usize,
+    height: usize,
+) -> Vec<Image> {
+    let code: Vec<Op> = program.operations().cloned().collect();
+    let inputs: Vec<&Image> = inputs.iter().collect();
+    execute(&code, &inputs, uniforms, width, height, program.outputs.len())
+}
+
+/// Runs pipeline split into passes, pass by pass, the same way as GPU does.
+pub fn interpret_passes(
+    pipeline: &PipelineDef,
+    inputs: &[Image],
+    uniforms: &[ConstantValue],
+    width: usize,
+    height: usize,
+) -> Vec<Image> {
+    let mut textures: Vec<Option<Image>> = vec![None; pipeline.textures.len()];
+    let mut results = vec![];
+
+    for pass in &pipeline.passes {
+        let shader = &pipeline.shaders[pass.shader_id];
+        let pass_inputs: Vec<&Image> = pass
+            .input
+            .iter()
+            .map(|x| match x {
+                InputTexture::Arg(id) => &inputs[*id],
+                InputTexture::Generated(id) => match &textures[*id] {
+                    Some(texture) => texture,
+                    None => panic!("Texture {} used before it was generated", id),
+                },
+            })
+            .collect();
+
+        match &pass.output {
+            OutputTexture::Result(_) => {
+                results = execute(&shader.code, &pass_inputs, uniforms, width, height, shader.output_type.len());
+            }
+            OutputTexture::Generated(ids) => {
+                let (w, h) = match pipeline.textures[ids[0]].1 {
+                    TextureSize::Original => (width, height),
+                    TextureSize::Scaled(factor) => scaled_size(width, height, factor as f64),
+                };
+                let outputs = execute(&shader.code, &pass_inputs, uniforms, w, h, ids.len());
+                for (id, output) in ids.iter().zip(outputs.into_iter()) {
+                    textures[*id] = Some(output);
+                }
+            }
+        }
+    }
+    results
+}
+
+#[derive(PartialEq)]
+enum ThreadState {
+    Running,
+    Waiting,
+    Finished,
+}
+
+// single pixel
+struct Thread {
+    uv: [f64; 2],
+    pc: usize,
+    label: Address,
+    previous_label: Address,
+    values: HashMap<Address, ConstantValue>,
+    // size of values relative to output, missing for values same in every pixel
+    scales: HashMap<Address, f64>,
+    outputs: Vec<Option<ConstantValue>>,
+    state: ThreadState,
+}
+
+struct Machine<'a> {
+    code: &'a [Op],
+    labels: HashMap<Address, usize>,
+    operations: HashMap<Address, Operation>,
+    inputs: &'a [&'a Image],
+    uniforms: &'a [ConstantValue],
+    // values stored by Sync and Scale
+    textures: HashMap<Address, Image>,
+    width: usize,
+    height: usize,
+}
+
+fn execute(
+    code: &[Op],
+    inputs: &[&Image],
+    uniforms: &[ConstantValue],
+    width: usize,
+    height: usize,
+    outputs_num: usize,
+) -> Vec<Image> {
+    let mut machine = Machine {
+        code,
+        labels: code
+            .iter()
+            .enumerate()
+            .filter(|x| (x.1).1 == Operation::Label)
+            .map(|(id, x)| (x.0, id))
+            .collect(),
+        operations: code.iter().cloned().collect(),
+        inputs,
+        uniforms,
+        textures: HashMap::new(),
+        width,
+        height,
+    };
+
+    let mut threads: Vec<Thread> = (0..width * height)
+        .map(|id| Thread {
+            uv: pixel_uv(id % width, id / width, width, height),
+            pc: 0,
+            label: 0,
+            previous_label: 0,
+            values: HashMap::new(),
+            scales: HashMap::new(),
+            outputs: vec![None; outputs_num],
+            state: ThreadState::Running,
+        })
+        .collect();
+
+    loop {
+        for thread in threads.iter_mut().filter(|x| x.state == ThreadState::Running) {
+            machine.run(thread);
+        }
+        // first sync in code goes first, with uniform control flow all pixels wait on the same one
+        match threads.iter().filter(|x| x.state == ThreadState::Waiting).map(|x| x.pc).min() {
+            None => break,
+            Some(index) => machine.synchronize(&mut threads, index),
+        }
+    }
+
+    (0..outputs_num)
+        .map(|id| Image {
+            width,
+            height,
+            pixels: threads
+                .iter()
+                .map(|x| match x.outputs[id] {
+                    Some(value) => value,
+                    None => panic!("Result {} was not written", id),
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+impl<'a> Machine<'a> {
+    fn run(&self, thread: &mut Thread) {
+        loop {
+            let (ret, op) = self.code[thread.pc];
+            thread.pc += 1;
+            let value = match op {
+                Operation::Sync(..) | Operation::Scale(..) => {
+                    thread.pc -= 1;
+                    thread.state = ThreadState::Waiting;
+                    return;
+                }
+                Operation::Exit(val, _) => {
+                    thread.outputs[0] = Some(thread.values[&val]);
+                    thread.state = ThreadState::Finished;
+                    return;
+                }
+                Operation::Output(id, val) => {
+                    thread.outputs[id] = Some(thread.values[&val]);
+                    continue;
+                }
+                Operation::Label => {
+                    thread.previous_label = thread.label;
+                    thread.label = ret;
+                    continue;
+                }
+                Operation::Jump(label) => {
+                    thread.pc = self.labels[&label];
+                    continue;
+                }
+                Operation::JumpIfElse(cond, if_label, else_label) => {
+                    let label = if as_bool(thread.values[&cond]) { if_label } else { else_label };
+                    thread.pc = self.labels[&label];
+                    continue;
+                }
+                Operation::LoopMerge(..) => continue,
+                Operation::Phi(record) => {
+                    if thread.previous_label == record.label {
+                        thread.values[&record.new]
+                    } else {
+                        thread.values[&record.old]
+                    }
+                }
+                Operation::Arg(id) => self.inputs[id].sample(thread.uv),
+                Operation::Uniform(id) => self.uniforms[id],
+                Operation::Shift(what, by, mode) => {
+                    let offset = as_components(thread.values[&by]);
+                    let uv = [thread.uv[0] + offset[0], thread.uv[1] + offset[1]];
+                    let value = match self.texture(what) {
+                        Some(texture) => texture.sample(edge_uv(uv, mode)),
+                        // shifting constant
+                        None => thread.values[&what],
+                    };
+                    let outside = uv.iter().any(|x| *x < 0.0 || *x > 1.0);
+                    match mode {
+                        Some(EdgeMode::Zero) if outside => zero_like(value),
+                        _ => value,
+                    }
+                }
+                op => evaluate(op, &thread.values),
+            };
+            let scale = match op {
+                Operation::Phi(record) if thread.previous_label == record.label => thread.scales.get(&record.new).cloned(),
+                Operation::Phi(record) => thread.scales.get(&record.old).cloned(),
+                op => op.operands_scale(|x| thread.scales.get(&x).cloned()),
+            };
+            match scale {
+                Some(scale) => thread.scales.insert(ret, scale),
+                None => thread.scales.remove(&ret),
+            };
+            thread.values.insert(ret, value);
+        }
+    }
+
+    // texture read by shift
+    fn texture(&self, address: Address) -> Option<&Image> {
+        match self.operations.get(&address) {
+            Some(Operation::Arg(id)) => Some(self.inputs[*id]),
+            Some(Operation::Sync(..)) | Some(Operation::Scale(..)) => Some(&self.textures[&address]),
+            Some(Operation::Store(x)) => self.texture(*x),
+            _ => None,
+        }
+    }
+
+    fn synchronize(&mut self, threads: &mut Vec<Thread>, index: usize) {
+        let (address, op) = self.code[index];
+        let synced = match op.synced_value() {
+            Some(x) => x,
+            None => panic!("internal compiler error"),
+        };
+        let waiting: Vec<usize> = (0..threads.len())
+            .filter(|x| threads[*x].state == ThreadState::Waiting && threads[*x].pc == index)
+            .collect();
+
+        let zero = zero_like(threads[waiting[0]].values[&synced]);
+        let full = Image {
+            width: self.width,
+            height: self.height,
+            pixels: threads.iter().map(|x| x.values.get(&synced).cloned().unwrap_or(zero)).collect(),
+        };
+
+        // factor is relative to size of scaled value
+        let scale = match op {
+            Operation::Scale(_, factor) => match threads[waiting[0]].values[&factor] {
+                ConstantValue::Float(x) => Some(threads[waiting[0]].scales.get(&synced).cloned().unwrap_or(1.0) * x),
+                _ => panic!("internal compiler error"),
+            },
+            _ => threads[waiting[0]].scales.get(&synced).cloned(),
+        };
+        let texture = match op {
+            Operation::Scale(..) => {
+                let factor = scale.unwrap();
+                let (w, h) = scaled_size(self.width, self.height, factor);
+                let mut scaled = Image::new(w, h, zero);
+                for y in 0..h {
+                    for x in 0..w {
+                        scaled.set(x, y, full.sample(pixel_uv(x, y, w, h)));
+                    }
+                }
+                scaled
+            }
+            _ => full,
+        };
+
+        for id in waiting {
+            let thread = &mut threads[id];
+            thread.values.insert(address, texture.sample(thread.uv));
+            match scale {
+                Some(scale) => thread.scales.insert(address, scale),
+                None => thread.scales.remove(&address),
+            };
+            thread.pc += 1;
+            thread.state = ThreadState::Running;
+        }
+        self.textures.insert(address, texture);
+    }
+}
+
+/// Also used to fold operations with constant operands.
+pub(crate) fn evaluate(op: Operation, values: &HashMap<Address, ConstantValue>) -> ConstantValue {
+    use ConstantValue::*;
+    let get = |x: &Address| values[x];
+    match op {
+        Operation::StoreInt(x) => Int(x),
+        Operation::StoreFloat(x) => Float(x),
+        Operation::StoreVec2(x) => Vec2(x),
+        Operation::StoreVec3(x) => Vec3(x),
+        Operation::StoreVec4(x) => Vec4(x),
+        Operation::StoreBool(x) => Bool(x),
+        Operation::Store(a) => get(&a),
+        Operation::ConstructVec2(a, b) => from_components(&[as_float(get(&a)), as_float(get(&b))]),
+        Operation::ConstructVec3(a, b, c) => {
+            from_components(&[as_float(get(&a)), as_float(get(&b)), as_float(get(&c))])
+        }
+        Operation::ConstructVec4(a, b, c, d) => from_components(&[
+            as_float(get(&a)),
+            as_float(get(&b)),
+            as_float(get(&c)),
+            as_float(get(&d)),
+        ]),
+        Operation::ExtractComponent(a, id) => Float(as_components(get(&a))[id]),
+        Operation::StoreComponent(a, id, b) => {
+            let mut components = as_components(get(&a));
+            components[id] = as_float(get(&b));
+            from_components(&components)
+        }
+        Operation::Add(a, b) => arithmetic(get(&a), get(&b), |x, y| x + y, |x, y| x + y),
+        Operation::Sub(a, b) => arithmetic(get(&a), get(&b), |x, y| x - y, |x, y| x - y),
+        Operation::Mul(a, b) => arithmetic(get(&a), get(&b), |x, y| x * y, |x, y| x * y),
+        Operation::Div(a, b) => arithmetic(get(&a), get(&b), |x, y| x / y, |x, y| x / y),
+        Operation::Less(a, b) => match (get(&a), get(&b)) {
+            (Int(x), Int(y)) => Bool(x < y),
+            (x, y) => Bool(as_float(x) < as_float(y)),
+        },
+        Operation::LessEq(a, b) => match (get(&a), get(&b)) {
+            (Int(x), Int(y)) => Bool(x <= y),
+            (x, y) => Bool(as_float(x) <= as_float(y)),
+        },
+        Operation::Eq(a, b) => Bool(get(&a) == get(&b)),
+        Operation::Neq(a, b) => Bool(get(&a) != get(&b)),
+        Operation::And(a, b) => Bool(as_bool(get(&a)) && as_bool(get(&b))),
+        Operation::Or(a, b) => Bool(as_bool(get(&a)) || as_bool(get(&b))),
+        Operation::Neg(a) => match get(&a) {
+            Bool(x) => Bool(!x),
+            Int(x) => Int(-x),
+            x => map_floats(x, |v| -v),
+        },
+        Operation::Invoke(function) => evaluate_std(function, values),
+        x => panic!("internal compiler error: {:?} can't be evaluated", x),
+    }
+}
+
+/// Also used to fold invocations with constant arguments.
+pub(crate) fn evaluate_std(function: StdFunction, values: &HashMap<Address, ConstantValue>) -> ConstantValue {
+    use ConstantValue::*;
+    use StdFunction::*;
+    let get = |x: &Address| values[x];
+    match function {
+        Round(a) => map_floats(get(&a), f64::round),
+        Trunc(a) => map_floats(get(&a), f64::trunc),
+        Abs(a) => match get(&a) {
+            Int(x) => Int(x.abs()),
+            x => map_floats(x, f64::abs),
+        },
+        Sign(a) => match get(&a) {
+            Int(x) => Int(x.signum()),
+            x => map_floats(x, |v| if v == 0.0 { 0.0 } else { v.signum() }),
+        },
+        Floor(a) => map_floats(get(&a), f64::floor),
+        Ceil(a) => map_floats(get(&a), f64::ceil),
+        Fract(a) => map_floats(get(&a), |v| v - v.floor()),
+        Radians(a) => map_floats(get(&a), f64::to_radians),
+        Degrees(a) => map_floats(get(&a), f64::to_degrees),
+        Sin(a) => map_floats(get(&a), f64::sin),
+        Cos(a) => map_floats(get(&a), f64::cos),
+        Tan(a) => map_floats(get(&a), f64::tan),
+        Asin(a) => map_floats(get(&a), f64::asin),
+        Acos(a) => map_floats(get(&a), f64::acos),
+        Atan(a) => map_floats(get(&a), f64::atan),
+        Sinh(a) => map_floats(get(&a), f64::sinh),
+        Cosh(a) => map_floats(get(&a), f64::cosh),
+        Tanh(a) => map_floats(get(&a), f64::tanh),
+        Asinh(a) => map_floats(get(&a), f64::asinh),
+        Acosh(a) => map_floats(get(&a), f64::acosh),
+        Atanh(a) => map_floats(get(&a), f64::atanh),
+        Atan2(a, b) => zip_floats(get(&a), get(&b), f64::atan2),
+        Pow(a, b) => zip_floats(get(&a), get(&b), f64::powf),
+        Exp(a) => map_floats(get(&a), f64::exp),
+        Log(a) => map_floats(get(&a), f64::ln),
+        Exp2(a) => map_floats(get(&a), f64::exp2),
+        Log2(a) => map_floats(get(&a), f64::log2),
+        Sqrt(a) => map_floats(get(&a), f64::sqrt),
+        Dot(a, b) => Float(dot(get(&a), get(&b))),
+        Min(a, b) => arithmetic(get(&a), get(&b), f64::min, std::cmp::min),
+        Max(a, b) => arithmetic(get(&a), get(&b), f64::max, std::cmp::max),
+        Clamp(a, b, c) => {
+            let lower = arithmetic(get(&a), get(&b), f64::max, std::cmp::max);
+            arithmetic(lower, get(&c), f64::min, std::cmp::min)
+        }
+        Length(a) => Float(dot(get(&a), get(&a)).sqrt()),
+        Cross(a, b) => {
+            let x = as_components(get(&a));
+            let y = as_components(get(&b));
+            Vec3([
+                x[1] * y[2] - x[2] * y[1],
+                x[2] * y[0] - x[0] * y[2],
+                x[0] * y[1] - x[1] * y[0],
+            ])
+        }
+        Normalize(a) => {
+            let length = dot(get(&a), get(&a)).sqrt();
+            map_floats(get(&a), |v| v / length)
+        }
+    }
+}
+
+fn as_bool(value: ConstantValue) -> bool {
+    match value {
+        ConstantValue::Bool(x) => x,
+        x => panic!("internal compiler error: {:?} is not bool", x),
+    }
+}
+
+fn as_float(value: ConstantValue) -> f64 {
+    match value {
+        ConstantValue::Float(x) => x,
+        x => panic!("internal compiler error: {:?} is not float", x),
+    }
+}
+
+pub(crate) fn as_components(value: ConstantValue) -> Vec<f64> {
+    match value {
+        ConstantValue::Float(x) => vec![x],
+        ConstantValue::Vec2(x) => x.to_vec(),
+        ConstantValue::Vec3(x) => x.to_vec(),
+        ConstantValue::Vec4(x) => x.to_vec(),
+        x => panic!("internal compiler error: {:?} has no float components", x),
+    }
+}
+
+fn from_components(components: &[f64]) -> ConstantValue {
+    match components.len() {
+        1 => ConstantValue::Float(components[0]),
+        2 => ConstantValue::Vec2([components[0], components[1]]),
+        3 => ConstantValue::Vec3([components[0], components[1], components[2]]),
+        4 => ConstantValue::Vec4([components[0], components[1], components[2], components[3]]),
+        _ => panic!("internal compiler error"),
+    }
+}
+
+fn zero_like(value: ConstantValue) -> ConstantValue {
+    match value {
+        ConstantValue::Int(_) => ConstantValue::Int(0),
+        ConstantValue::Bool(_) => ConstantValue::Bool(false),
+        x => map_floats(x, |_| 0.0),
+    }
+}
+
+fn map_floats<F: Fn(f64) -> f64>(value: ConstantValue, f: F) -> ConstantValue {
+    from_components(&as_components(value).iter().map(|x| f(*x)).collect::<Vec<_>>())
+}
+
+// component-wise, float operand is used for every component of vector
+fn zip_floats<F: Fn(f64, f64) -> f64>(a: ConstantValue, b: ConstantValue, f: F) -> ConstantValue {
+    let a = as_components(a);
+    let b = as_components(b);
+    let at = |v: &Vec<f64>, id: usize| if v.len() == 1 { v[0] } else { v[id] };
+    let len = std::cmp::max(a.len(), b.len());
+    from_components(&(0..len).map(|id| f(at(&a, id), at(&b, id))).collect::<Vec<_>>())
+}
+
+fn arithmetic<F: Fn(f64, f64) -> f64, I: Fn(i64, i64) -> i64>(
+    a: ConstantValue,
+    b: ConstantValue,
+    float_op: F,
+    int_op: I,
+) -> ConstantValue {
+    match (a, b) {
+        (ConstantValue::Int(x), ConstantValue::Int(y)) => ConstantValue::Int(int_op(x, y)),
+        (a, b) => zip_floats(a, b, float_op),
+    }
+}
+
+fn dot(a: ConstantValue, b: ConstantValue) -> f64 {
+    as_components(a).iter().zip(as_components(b).iter()).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::test_utils::{
+        assert_close, assert_same_results, compile, gradient, optimize, run, split_keeping_results, BLUR, EDGE,
+    };
+    use crate::tac_ir;
+
+    #[test]
+    fn uniform_image() {
+        let input = Image::new(4, 4, ConstantValue::Vec4([0.3, 0.6, 0.9, 1.0]));
+        let uniforms = [ConstantValue::Vec2([4.0, 4.0])];
+        let result = interpret(&compile(BLUR), &[input], &uniforms, 4, 4);
+
+        let total = 3.0 * (0.3f64 * 0.3 + 0.6 * 0.6 + 0.9 * 0.9).sqrt();
+        assert_close(&result[0], &Image::new(4, 4, ConstantValue::Vec4([0.3, 0.6, total, 1.0])));
+    }
+
+    #[test]
+    fn passes_keep_results() {
+        let expected = run(&compile(BLUR));
+        assert_same_results(&expected, &run(&optimize(compile(BLUR))));
+        split_keeping_results(BLUR);
+    }
+
+    #[test]
+    fn edge_modes() {
+        let input = gradient(5, 3);
+        let uniforms = [ConstantValue::Vec2([5.0, 3.0])];
+        let shifted = |mode: &str| {
+            let program = optimize(compile(&EDGE.replace("MODE", mode)));
+            interpret(&program, &[input.clone()], &uniforms, 5, 3).remove(0)
+        };
+        let clamp = shifted("clamp");
+        let mirror = shifted("mirror");
+        let repeat = shifted("repeat");
+        let zero = shifted("zero");
+        for y in 0..3 {
+            assert_eq!(clamp.get(0, y), input.get(2, y));
+            assert_eq!(clamp.get(4, y), input.get(4, y));
+            assert_eq!(mirror.get(4, y), input.get(3, y));
+            assert_eq!(repeat.get(4, y), input.get(1, y));
+            assert_eq!(zero.get(2, y), input.get(4, y));
+            assert_eq!(zero.get(4, y), ConstantValue::Vec4([0.0; 4]));
+        }
+    }
+
+    #[test]
+    fn unrolled_loops_keep_results() {
+        let program = tac_ir::propagate_constants(compile(BLUR));
+        let unrolled = tac_ir::unroll_constant_loops(program, 64);
+        assert!(!unrolled.operations().any(|x| x.1.is_jump_if_else()));
+        assert_same_results(&run(&compile(BLUR)), &run(&unrolled));
+
+        // loop doesn't fit into budget
+        let program = tac_ir::propagate_constants(compile(BLUR));
+        let kept = tac_ir::unroll_constant_loops(program, 2);
+        assert!(kept.operations().any(|x| x.1.is_jump_if_else()));
+    }
+}
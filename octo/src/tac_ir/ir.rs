@@ -2,6 +2,7 @@ pub type Address = usize;
 
 pub type Op = (Address, Operation);
 
+pub use super::ast::EdgeMode;
 pub use super::ast::Type as ValueType;
 
 pub struct PipelineIR {
@@ -185,9 +186,11 @@ pub enum Operation {
 
     Neg(Address),
 
-    Shift(Address, Address),
+    Shift(Address, Address, Option<EdgeMode>), // texture, offset, edge mode (sampler decides without it)
     Exit(Address, Address),
+    Output(usize, Address), // result id (other than 0, which is stored by Exit), value
     Sync(Address),
+    Scale(Address, Address), // scaled value, scale factor
 
     Invoke(StdFunction),
 
@@ -212,7 +215,49 @@ impl Operation {
         }
     }
 
-    
+    /// Values used by operation, labels are not included.
+    pub fn deps(&self) -> Vec<Address> {
+        use Operation::*;
+        match *self {
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Less(a, b) | LessEq(a, b) | Eq(a, b) | Neq(a, b)
+            | And(a, b) | Or(a, b) | Shift(a, b, _) | Scale(a, b) | ConstructVec2(a, b) | StoreComponent(a, _, b) => {
+                vec![a, b]
+            }
+            ConstructVec3(a, b, c) => vec![a, b, c],
+            ConstructVec4(a, b, c, d) => vec![a, b, c, d],
+            Neg(a) | Sync(a) | Store(a) | ExtractComponent(a, _) | Output(_, a) | Exit(a, _) | JumpIfElse(a, ..) => {
+                vec![a]
+            }
+            Phi(record) => vec![record.new, record.old],
+            Invoke(function) => function.deps(),
+            Arg(..) | Uniform(..) | StoreInt(..) | StoreFloat(..) | StoreVec2(..) | StoreVec3(..) | StoreVec4(..)
+            | StoreBool(..) | Jump(..) | LoopMerge(..) | Label => vec![],
+        }
+    }
+
+    /// Size of value relative to output, given sizes of operands. `None` stands for values which are
+    /// the same in every pixel. Shifted value keeps size of its texture, others take the finest size of
+    /// their operands. Size of Scale depends on value of its factor, so it's left to caller.
+    pub fn operands_scale<F: Fn(Address) -> Option<f64>>(&self, scale_of: F) -> Option<f64> {
+        let operands = match *self {
+            Operation::Arg(..) => return Some(1.0),
+            Operation::Shift(what, ..) => vec![what],
+            _ => self.deps(),
+        };
+        operands
+            .into_iter()
+            .filter_map(scale_of)
+            .fold(None, |finest, x| Some(finest.map_or(x, |y: f64| y.max(x))))
+    }
+
+    /// Value stored into intermediate texture if operation splits the pipeline (Sync or Scale).
+    pub fn synced_value(&self) -> Option<Address> {
+        match self {
+            Operation::Sync(a) => Some(*a),
+            Operation::Scale(a, _) => Some(*a),
+            _ => None,
+        }
+    }
 }
 
 impl std::string::ToString for Operation {
@@ -248,7 +293,9 @@ impl std::string::ToString for Operation {
             Phi(..) => "Phi".to_string(),
             Neg(..) => "Neg".to_string(),
             Exit(..) => "Exit".to_string(),
+            Output(..) => "Output".to_string(),
             Sync(..) => "Sync".to_string(),
+            Scale(..) => "Scale".to_string(),
             Invoke(..) => "Std()".to_string(),
             JumpIfElse(..) => "JumpIfElse".to_string(),
             Jump(..) => "Jump".to_string(),
@@ -391,7 +438,7 @@ pub fn replace(op: &mut (Address, Operation), from: Address, to: Address, replac
             replace!(l, from, to);
             replace!(r, from, to);
         },
-        Operation::Shift(l, r)=>{
+        Operation::Shift(l, r, _)=>{
             replace!(l, from, to);
             replace!(r, from, to);
         },
@@ -413,12 +460,19 @@ pub fn replace(op: &mut (Address, Operation), from: Address, to: Address, replac
             replace!(a, from, to);
             replace!(b, from, to);
         },
+        Operation::Output(_, a) => {
+            replace!(a, from, to)
+        },
         Operation::Store(a) => {
             replace!(a, from, to)
         },
         Operation::Sync(a) => {
             replace!(a, from, to)
         },
+        Operation::Scale(a, b) => {
+            replace!(a, from, to);
+            replace!(b, from, to);
+        },
         Operation::JumpIfElse(a, b, c) => {
             replace!(a, from, to);
             replace!(b, from, to);
@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ir::{replace, Address, Op, Operation, PipelineIR};
+use super::utils::{find_blocks, successors};
+
+/// Removes operations computing value already available in dominating block.
+/// Uses of removed operation are redirected to the first one.
+pub fn eliminate_common_subexpressions(code: PipelineIR) -> PipelineIR {
+    let (code, inputs, outputs, uniforms) = code.take();
+
+    let blocks = find_blocks(&code);
+    let dominators = find_dominators(&code, &blocks);
+
+    // key of operation -> (result, block of result)
+    let mut available: HashMap<String, Vec<(Address, Address)>> = HashMap::new();
+    let mut renamed: HashMap<Address, Address> = HashMap::new();
+    let mut result = Vec::with_capacity(code.len());
+
+    for (id, op) in code.into_iter().enumerate() {
+        let mut op = op;
+        rename(&mut op, &renamed);
+
+        let key = match key(&op.1) {
+            Some(x) => x,
+            None => {
+                result.push(op);
+                continue;
+            }
+        };
+        let block = blocks[id];
+        let candidates = available.entry(key).or_insert_with(Vec::new);
+        match candidates.iter().find(|x| dominators[&block].contains(&x.1)) {
+            Some((address, _)) => {
+                log::debug!("{} is the same as {}", op.0, address);
+                renamed.insert(op.0, *address);
+            }
+            None => {
+                candidates.push((op.0, block));
+                result.push(op);
+            }
+        }
+    }
+
+    // phi nodes of loops use values defined later
+    for op in &mut result {
+        rename(op, &renamed);
+    }
+
+    PipelineIR::construct(result, inputs, outputs, uniforms)
+}
+
+fn rename(op: &mut Op, renamed: &HashMap<Address, Address>) {
+    for operand in op.1.deps() {
+        if let Some(new) = renamed.get(&operand) {
+            replace(op, operand, *new, false);
+        }
+    }
+}
+
+// operations which have no side effects and depend only on operands
+// syncs are kept, they decide about pass boundaries and loop unrolling
+fn key(op: &Operation) -> Option<String> {
+    use Operation::*;
+    let op = match *op {
+        // operands of commutative operations are ordered
+        Add(a, b) if b < a => Add(b, a),
+        Mul(a, b) if b < a => Mul(b, a),
+        Eq(a, b) if b < a => Eq(b, a),
+        Neq(a, b) if b < a => Neq(b, a),
+        And(a, b) if b < a => And(b, a),
+        Or(a, b) if b < a => Or(b, a),
+        Exit(..) | Output(..) | Sync(..) | Scale(..) | Phi(..) | JumpIfElse(..) | Jump(..) | LoopMerge(..)
+        | Label => return None,
+        x => x,
+    };
+    // Debug output tells apart all floats, including 0.0 and -0.0
+    Some(format!("{:?}", op))
+}
+
+fn find_dominators(code: &[Op], blocks: &[Address]) -> HashMap<Address, HashSet<Address>> {
+    let mut order = vec![0];
+    order.extend(code.iter().filter(|x| x.1 == Operation::Label).map(|x| x.0));
+    let mut predecessors: HashMap<Address, Vec<Address>> = order.iter().map(|x| (*x, vec![])).collect();
+    for (block, next) in successors(code, blocks) {
+        for label in next {
+            predecessors.entry(label).or_insert_with(Vec::new).push(block);
+        }
+    }
+
+    let all: HashSet<Address> = order.iter().cloned().collect();
+    let mut dominators: HashMap<Address, HashSet<Address>> = order.iter().map(|x| (*x, all.clone())).collect();
+    dominators.insert(0, [0].iter().cloned().collect());
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in order.iter().skip(1) {
+            let mut new: Option<HashSet<Address>> = None;
+            for predecessor in &predecessors[block] {
+                let dominating = &dominators[predecessor];
+                new = Some(match new {
+                    None => dominating.clone(),
+                    Some(x) => x.intersection(dominating).cloned().collect(),
+                });
+            }
+            let mut new = new.unwrap_or_default();
+            new.insert(*block);
+            if new != dominators[block] {
+                dominators.insert(*block, new);
+                changed = true;
+            }
+        }
+    }
+    dominators
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shifts_are_shared() {
+        use Operation::*;
+        let code = vec![
+            (1, Arg(0)),
+            (2, Sync(1)),
+            (3, StoreVec2([-1.0, 1.0])),
+            (4, StoreVec2([-1.0, 1.0])),
+            (5, Shift(2, 3, None)),
+            (6, Shift(2, 4, None)),
+            (7, Add(5, 6)),
+            (8, Exit(7, 0)),
+        ];
+        let result = eliminate_common_subexpressions(PipelineIR::new(code));
+        let result: Vec<_> = result.operations().cloned().collect();
+        assert_eq!(
+            result,
+            vec![
+                (1, Arg(0)),
+                (2, Sync(1)),
+                (3, StoreVec2([-1.0, 1.0])),
+                (5, Shift(2, 3, None)),
+                (7, Add(5, 5)),
+                (8, Exit(7, 0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn branches_do_not_share() {
+        use Operation::*;
+        let code = vec![
+            (1, Arg(0)),
+            (2, StoreBool(true)),
+            (3, JumpIfElse(2, 4, 7)),
+            (4, Label),
+            (5, Neg(1)),
+            (6, Jump(7)),
+            (7, Label),
+            (8, Neg(1)),
+            (9, Exit(8, 7)),
+        ];
+        let result = eliminate_common_subexpressions(PipelineIR::new(code.clone()));
+        let result: Vec<_> = result.operations().cloned().collect();
+        assert_eq!(result, code);
+    }
+}
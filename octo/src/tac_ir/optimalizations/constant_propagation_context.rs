@@ -2,6 +2,7 @@ use std::collections::HashMap;
 
 use super::ir::{Address, ConstantValue, Operation, PhiRecord, PipelineIR};
 
+#[derive(Clone)]
 pub struct ConstantPropagationContext
 {
     constants: HashMap<Address, ConstantValue>,
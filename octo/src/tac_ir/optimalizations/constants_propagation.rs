@@ -3,6 +3,7 @@ use std::collections::HashMap;
 use super::ir::{Address, ConstantValue, Operation, PhiRecord, PipelineIR};
 
 use super::code::Code;
+use super::interpreter;
 use super::ConstantPropagationContext;
 
 
@@ -343,11 +344,9 @@ pub fn propagate_constant_operation(
         Neg(addr) => {
             match ctx.get_const(&addr) {
                 Some(v) => {
-                    let val = match v {
-                        ConstantValue::Bool(val) => ConstantValue::Bool(!val),
-                        ConstantValue::Float(val) => ConstantValue::Float(-val),
-                        _ => unreachable!(),
-                    };
+                    // folded invocations give constants of every type
+                    let values: HashMap<Address, ConstantValue> = vec![(addr, v)].into_iter().collect();
+                    let val = interpreter::evaluate(x, &values);
                     Some(ctx.copy_const(result_address, val))
                 },
                 _ => Some(x),
@@ -387,7 +386,26 @@ pub fn propagate_constant_operation(
             };
             Some(Exit(a,lab))
         },
-        Invoke(..) => Some(x), //ignore now for simplicity
+        Output(..) => Some(x),
+        Invoke(function) => {
+            let arguments: Option<HashMap<Address, ConstantValue>> = function
+                .deps()
+                .into_iter()
+                .map(|addr| ctx.get_const(&addr).map(|val| (addr, val)))
+                .collect();
+            match arguments {
+                Some(values) => {
+                    let val = interpreter::evaluate_std(function, &values);
+                    // NaN and infinity have no literals in shader sources, so these stay runtime math
+                    if is_finite(val) {
+                        Some(ctx.copy_const(result_address, val))
+                    } else {
+                        Some(x)
+                    }
+                }
+                None => Some(x),
+            }
+        }
         Sync(addr) => {
             // syncing const value seems useless
             match ctx.get_const(&addr) {
@@ -397,6 +415,15 @@ pub fn propagate_constant_operation(
                 None => Some(x)
             }
         }
+        Scale(addr, _) => {
+            // scaling const value is as useless as syncing it
+            match ctx.get_const(&addr) {
+                Some(y) => {
+                    Some(ctx.copy_const(result_address, y))
+                },
+                None => Some(x)
+            }
+        }
         Shift(..) => Some(x),
         Phi(rec) => {
             let mut modified_rec = rec;
@@ -435,6 +462,16 @@ pub fn propagate_constant_operation(
     return result_operation;
 }
 
+fn is_finite(value: ConstantValue) -> bool {
+    match value {
+        ConstantValue::Float(x) => x.is_finite(),
+        ConstantValue::Vec2(x) => x.iter().all(|v| v.is_finite()),
+        ConstantValue::Vec3(x) => x.iter().all(|v| v.is_finite()),
+        ConstantValue::Vec4(x) => x.iter().all(|v| v.is_finite()),
+        ConstantValue::Int(_) | ConstantValue::Bool(_) => true,
+    }
+}
+
 fn propagate_jump_if_else(
     ctx: &mut ConstantPropagationContext,
     cond: Address,
@@ -546,3 +583,57 @@ fn propagate_jump_if_else(
     }
     return true;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tac_ir::ir::StdFunction;
+
+    #[test]
+    fn invocations_are_folded() {
+        let code = vec![
+            (1, Operation::StoreFloat(-2.0)),
+            (2, Operation::Invoke(StdFunction::Abs(1))),
+            (3, Operation::Invoke(StdFunction::Exp(2))),
+            (4, Operation::StoreVec2([3.0, 4.0])),
+            (5, Operation::Invoke(StdFunction::Length(4))),
+            (6, Operation::Invoke(StdFunction::Max(3, 5))),
+            (7, Operation::Exit(6, 0)),
+        ];
+        let result = propagate_constants(PipelineIR::new(code));
+        let ops: Vec<_> = result.operations().map(|x| x.1).collect();
+        assert_eq!(ops[1], Operation::StoreFloat(2.0));
+        assert_eq!(ops[2], Operation::StoreFloat(2.0f64.exp()));
+        assert_eq!(ops[4], Operation::StoreFloat(5.0));
+        assert_eq!(ops[5], Operation::StoreFloat(2.0f64.exp()));
+    }
+
+    #[test]
+    fn invalid_results_are_kept() {
+        let code = vec![
+            (1, Operation::StoreFloat(-1.0)),
+            (2, Operation::Invoke(StdFunction::Sqrt(1))),
+            (3, Operation::Exit(2, 0)),
+        ];
+        let result = propagate_constants(PipelineIR::new(code));
+        let ops: Vec<_> = result.operations().map(|x| x.1).collect();
+        assert_eq!(ops[1], Operation::Invoke(StdFunction::Sqrt(1)));
+    }
+
+    #[test]
+    fn negations_of_folded_invocations_are_folded() {
+        let code = vec![
+            (1, Operation::StoreVec2([3.0, 4.0])),
+            (2, Operation::Invoke(StdFunction::Normalize(1))),
+            (3, Operation::Neg(2)),
+            (4, Operation::StoreInt(-2)),
+            (5, Operation::Invoke(StdFunction::Abs(4))),
+            (6, Operation::Neg(5)),
+            (7, Operation::Exit(3, 0)),
+        ];
+        let result = propagate_constants(PipelineIR::new(code));
+        let ops: Vec<_> = result.operations().map(|x| x.1).collect();
+        assert_eq!(ops[2], Operation::StoreVec2([-0.6, -0.8]));
+        assert_eq!(ops[5], Operation::StoreInt(-2));
+    }
+}
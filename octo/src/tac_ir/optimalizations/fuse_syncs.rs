@@ -0,0 +1,235 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ir::{replace, Address, EdgeMode, Op, Operation, PipelineIR};
+use super::utils::resolve_replacement;
+
+// budget of operations recomputed in place of single sync, samples included
+const MAX_RECOMPUTED_OPERATIONS: usize = 16;
+
+/// Removes syncs which don't need separate pass, so producer and consumer end up in one shader.
+/// Sync is dropped if it is only sampled at zero offset, then synced value is used directly.
+/// Otherwise it is dropped if synced value is affine in input textures: every shift of it is
+/// replaced by the same computation over shifted inputs, which gives the same result as
+/// sampling intermediate texture, filtering included.
+pub fn fuse_syncs(code: PipelineIR) -> PipelineIR {
+    let (code, inputs, outputs, uniforms) = code.take();
+
+    let operations: HashMap<Address, Operation> = code.iter().cloned().collect();
+    let mut max_id = code.iter().map(|x| x.0).max().unwrap_or(0);
+
+    let mut removed = HashSet::new();
+    let mut replacements: HashMap<Address, Address> = HashMap::new();
+    // recomputed values are placed just before the shift they replace
+    let mut insertions: HashMap<Address, Vec<Op>> = HashMap::new();
+
+    for (sync, op) in &code {
+        let synced_value = match op {
+            Operation::Sync(x) => *x,
+            _ => continue,
+        };
+        let consumers: Vec<Op> = code.iter().filter(|x| x.1.deps().contains(sync)).cloned().collect();
+        // texture of sync is read differently by these
+        if consumers.iter().any(|x| x.1.synced_value() == Some(*sync)) {
+            continue;
+        }
+        let shifts: Vec<(Address, Address, Option<EdgeMode>)> = consumers
+            .iter()
+            .filter_map(|x| match x.1 {
+                Operation::Shift(what, by, mode) if what == *sync => Some((x.0, by, mode)),
+                _ => None,
+            })
+            .collect();
+
+        if shifts.iter().all(|(_, by, _)| is_zero(&operations, *by)) {
+            log::debug!("sync {} is only sampled at zero offset", sync);
+            for (shift, ..) in shifts {
+                removed.insert(shift);
+                replacements.insert(shift, synced_value);
+            }
+        } else {
+            let cost = match affine_cost(&operations, synced_value) {
+                Some(x) if x * shifts.len() <= MAX_RECOMPUTED_OPERATIONS => x,
+                _ => continue,
+            };
+            // outside of texture synced value is zero, while affine function of zero inputs doesn't have to be
+            let zero_border = shifts.iter().any(|x| x.2 == Some(EdgeMode::Zero));
+            if zero_border && cost > 1 {
+                continue;
+            }
+            log::debug!("sync {} is recomputed at {} shifts, {} operations each", sync, shifts.len(), cost);
+            for (shift, by, mode) in shifts {
+                let mut recomputed = Vec::new();
+                let value = recompute(&operations, synced_value, by, mode, &mut max_id, &mut recomputed);
+                insertions.insert(shift, recomputed);
+                removed.insert(shift);
+                replacements.insert(shift, value);
+            }
+        }
+        removed.insert(*sync);
+        replacements.insert(*sync, synced_value);
+    }
+
+    let mut result = Vec::with_capacity(code.len());
+    for op in code {
+        if let Some(recomputed) = insertions.remove(&op.0) {
+            result.extend(recomputed);
+        }
+        if removed.contains(&op.0) {
+            continue;
+        }
+        result.push(op);
+    }
+    // synced value may be removed shift of another sync
+    for op in result.iter_mut() {
+        for operand in op.1.deps() {
+            replace(op, operand, resolve_replacement(&replacements, operand), false);
+        }
+    }
+
+    PipelineIR::construct(result, inputs, outputs, uniforms)
+}
+
+fn is_zero(operations: &HashMap<Address, Operation>, address: Address) -> bool {
+    match operations.get(&address) {
+        Some(Operation::StoreVec2(x)) => x[0] == 0.0 && x[1] == 0.0,
+        Some(Operation::Store(x)) => is_zero(operations, *x),
+        _ => false,
+    }
+}
+
+// same in every pixel
+fn is_constant(operations: &HashMap<Address, Operation>, address: Address) -> bool {
+    use Operation::*;
+    let op = match operations.get(&address) {
+        Some(x) => x,
+        None => return false,
+    };
+    match op {
+        Uniform(..) | StoreInt(..) | StoreFloat(..) | StoreVec2(..) | StoreVec3(..) | StoreVec4(..) | StoreBool(..) => {
+            true
+        }
+        Store(..) | Add(..) | Sub(..) | Mul(..) | Div(..) | Neg(..) | ExtractComponent(..) | ConstructVec2(..)
+        | ConstructVec3(..) | ConstructVec4(..) | Invoke(..) => op.deps().into_iter().all(|x| is_constant(operations, x)),
+        _ => false,
+    }
+}
+
+/// Number of operations computing value from input textures, if it is affine in them.
+fn affine_cost(operations: &HashMap<Address, Operation>, address: Address) -> Option<usize> {
+    use Operation::*;
+    if is_constant(operations, address) {
+        return Some(0);
+    }
+    let cost = |x: Address| affine_cost(operations, x);
+    let op = operations.get(&address)?;
+    let operands = match *op {
+        Arg(..) => return Some(1),
+        Store(a) | Neg(a) | ExtractComponent(a, _) => cost(a)?,
+        Add(a, b) | Sub(a, b) | ConstructVec2(a, b) => cost(a)? + cost(b)?,
+        ConstructVec3(a, b, c) => cost(a)? + cost(b)? + cost(c)?,
+        ConstructVec4(a, b, c, d) => cost(a)? + cost(b)? + cost(c)? + cost(d)?,
+        Mul(a, b) if is_constant(operations, a) => cost(b)?,
+        Mul(a, b) | Div(a, b) if is_constant(operations, b) => cost(a)?,
+        _ => return None,
+    };
+    Some(operands + 1)
+}
+
+// copies computation of affine value, with inputs sampled at given offset
+fn recompute(
+    operations: &HashMap<Address, Operation>,
+    address: Address,
+    offset: Address,
+    mode: Option<EdgeMode>,
+    max_id: &mut Address,
+    result: &mut Vec<Op>,
+) -> Address {
+    if is_constant(operations, address) {
+        return address;
+    }
+    let op = operations[&address];
+    let op = match op {
+        Operation::Arg(..) => Operation::Shift(address, offset, mode),
+        op => {
+            let mut op = (address, op);
+            for operand in op.1.deps() {
+                let value = recompute(operations, operand, offset, mode, max_id, result);
+                replace(&mut op, operand, value, false);
+            }
+            op.1
+        }
+    };
+    *max_id += 1;
+    result.push((*max_id, op));
+    *max_id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_shift_is_fused() {
+        use Operation::*;
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Neg(2)),
+            (4, Sync(3)),
+            (5, StoreVec2([0.0, 0.0])),
+            (6, Shift(4, 5, None)),
+            (7, Add(6, 4)),
+            (8, Exit(7, 1)),
+        ];
+        let result: Vec<_> = fuse_syncs(PipelineIR::new(code)).operations().cloned().collect();
+        assert_eq!(
+            result,
+            vec![(1, Label), (2, Arg(0)), (3, Neg(2)), (5, StoreVec2([0.0, 0.0])), (7, Add(3, 3)), (8, Exit(7, 1))]
+        );
+    }
+
+    #[test]
+    fn affine_value_is_recomputed() {
+        use Operation::*;
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Uniform(0)),
+            (4, Mul(2, 3)),
+            (5, Sync(4)),
+            (6, StoreVec2([1.0, 0.0])),
+            (7, Shift(5, 6, None)),
+            (8, Exit(7, 1)),
+        ];
+        let result: Vec<_> = fuse_syncs(PipelineIR::new(code)).operations().cloned().collect();
+        assert_eq!(
+            result,
+            vec![
+                (1, Label),
+                (2, Arg(0)),
+                (3, Uniform(0)),
+                (4, Mul(2, 3)),
+                (6, StoreVec2([1.0, 0.0])),
+                (9, Shift(2, 6, None)),
+                (10, Mul(9, 3)),
+                (8, Exit(10, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn products_of_textures_stay_synced() {
+        use Operation::*;
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Mul(2, 2)),
+            (4, Sync(3)),
+            (5, StoreVec2([1.0, 0.0])),
+            (6, Shift(4, 5, None)),
+            (7, Exit(6, 1)),
+        ];
+        let result: Vec<_> = fuse_syncs(PipelineIR::new(code.clone())).operations().cloned().collect();
+        assert_eq!(result, code);
+    }
+}
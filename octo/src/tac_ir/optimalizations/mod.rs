@@ -1,16 +1,24 @@
 use super::code;
+use super::interpreter;
 use super::ir;
+use super::utils;
 
+mod common_subexpressions;
 mod constant_propagation_context;
 mod constants_propagation;
+mod fuse_syncs;
 mod remove_unused;
+mod simplify_cfg;
 mod unroll_loop;
 mod move_sync;
 
 use constant_propagation_context::ConstantPropagationContext;
 
+pub use common_subexpressions::eliminate_common_subexpressions;
 pub use constants_propagation::propagate_constants;
+pub use fuse_syncs::fuse_syncs;
 pub use remove_unused::remove_unused_operations;
-pub use unroll_loop::unroll_synced_loop;
+pub use simplify_cfg::simplify_cfg;
+pub use unroll_loop::{unroll_constant_loops, unroll_synced_loop};
 pub use move_sync::move_sync_operations;
 
@@ -35,8 +35,9 @@ pub fn remove_unused_operations(code: PipelineIR) -> PipelineIR {
             Neq(a,b) => {usage.insert(ret_addr,vec![a,b]);},
             Neg(a) => {usage.insert(ret_addr, vec![a]);},
             Sync(a) => {usage.insert(ret_addr,vec![a]);},
+            Scale(a, b) => {usage.insert(ret_addr,vec![a, b]);},
             Store(a) => {usage.insert(ret_addr, vec![a]);},
-            Shift(a,b) => {usage.insert(ret_addr,vec![a,b]);},
+            Shift(a,b,_) => {usage.insert(ret_addr,vec![a,b]);},
             ExtractComponent(a,..) => {usage.insert(ret_addr,vec![a]);},
             StoreComponent(a, .., b) => {usage.insert(ret_addr,vec![a, b]);},
             ConstructVec2(a,b) => {usage.insert(ret_addr,vec![a,b]);},
@@ -53,6 +54,10 @@ pub fn remove_unused_operations(code: PipelineIR) -> PipelineIR {
             Phi(PhiRecord{new, label, old, old_label}) =>{usage.insert(ret_addr, vec![new, old]);},
 
             Exit(a, ..) => {usage.insert(ret_addr, vec![a]);},
+            Output(_, a) => {
+                usage.insert(ret_addr, vec![a]);
+                to_check.push(ret_addr);
+            },
 
             Label => (),
             Arg(..) => (),
@@ -94,6 +99,7 @@ pub fn remove_unused_operations(code: PipelineIR) -> PipelineIR {
             JumpIfElse(..) => true,
             LoopMerge(..) => true,
             Exit(..) => true,
+            Output(..) => true,
             Label => true,
             _ => {
                 used.contains(&ret_addr)
@@ -0,0 +1,336 @@
+use std::collections::{HashMap, HashSet};
+
+use super::ir::{replace, Address, Op, Operation, PhiRecord, PipelineIR};
+use super::utils::{find_blocks, resolve_replacement, successors};
+
+/// Removes unreachable blocks and trivial phi nodes, merges blocks joined only by a jump.
+/// Loop headers, their condition blocks and merge blocks are kept, emitters rely on them.
+pub fn simplify_cfg(code: PipelineIR) -> PipelineIR {
+    let (code, inputs, outputs, uniforms) = code.take();
+
+    let code = remove_unreachable(code);
+    let code = fold_phis(code);
+    let code = merge_blocks(code);
+
+    PipelineIR::construct(code, inputs, outputs, uniforms)
+}
+
+// labels that structured control flow needs, even if nothing jumps to them
+fn structural_labels(code: &[Op]) -> HashSet<Address> {
+    let mut labels = HashSet::new();
+    for (id, (_, op)) in code.iter().enumerate() {
+        match *op {
+            Operation::LoopMerge(continue_label, exit_label) => {
+                labels.extend(loop_labels(code, id, continue_label, exit_label));
+            }
+            Operation::JumpIfElse(_, first, second) => {
+                labels.insert(first);
+                labels.insert(second);
+            }
+            _ => {}
+        }
+    }
+    labels
+}
+
+// continue, exit and condition block of loop with header at given position
+fn loop_labels(code: &[Op], id: usize, continue_label: Address, exit_label: Address) -> Vec<Address> {
+    let mut labels = vec![continue_label, exit_label];
+    // condition block follows loop header
+    if let Some((_, Operation::Jump(condition_label))) = code.get(id + 1) {
+        labels.push(*condition_label);
+    }
+    labels
+}
+
+fn remove_unreachable(code: Vec<Op>) -> Vec<Op> {
+    if code.is_empty() {
+        return code;
+    }
+    let blocks = find_blocks(&code);
+    let successors = successors(&code, &blocks);
+    // blocks of reachable loop are kept, even if some of them are never entered
+    let mut structure: HashMap<Address, Vec<Address>> = HashMap::new();
+    for (id, (_, op)) in code.iter().enumerate() {
+        if let Operation::LoopMerge(continue_label, exit_label) = *op {
+            structure.insert(blocks[id], loop_labels(&code, id, continue_label, exit_label));
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut to_check = vec![blocks[0]];
+    while let Some(block) = to_check.pop() {
+        if !reachable.insert(block) {
+            continue;
+        }
+        for next in successors.get(&block).into_iter().chain(structure.get(&block)) {
+            to_check.extend(next.iter().cloned());
+        }
+    }
+
+    let mut replacements = HashMap::new();
+    let mut result = Vec::with_capacity(code.len());
+    for (id, op) in code.into_iter().enumerate() {
+        if !reachable.contains(&blocks[id]) {
+            log::debug!("removing unreachable {:?}", op);
+            continue;
+        }
+        // value can come only from reachable block
+        if let Operation::Phi(record) = op.1 {
+            if !reachable.contains(&record.label) {
+                replacements.insert(op.0, record.old);
+                continue;
+            }
+            if !reachable.contains(&record.old_label) {
+                replacements.insert(op.0, record.new);
+                continue;
+            }
+        }
+        result.push(op);
+    }
+    apply_replacements(result, &replacements)
+}
+
+// phi nodes with single distinct input
+fn fold_phis(code: Vec<Op>) -> Vec<Op> {
+    let mut replacements = HashMap::new();
+    let mut result = Vec::with_capacity(code.len());
+    for op in code {
+        if let Operation::Phi(record) = op.1 {
+            // loop phi of value not changed inside loop refers to itself
+            if record.new == record.old || record.new == op.0 {
+                replacements.insert(op.0, record.old);
+                continue;
+            }
+            if record.old == op.0 {
+                replacements.insert(op.0, record.new);
+                continue;
+            }
+        }
+        result.push(op);
+    }
+    apply_replacements(result, &replacements)
+}
+
+fn apply_replacements(code: Vec<Op>, replacements: &HashMap<Address, Address>) -> Vec<Op> {
+    if replacements.is_empty() {
+        return code;
+    }
+    code.into_iter()
+        .map(|op| {
+            let mut op = op;
+            for from in replacements.keys() {
+                replace(&mut op, *from, resolve_replacement(replacements, *from), false);
+            }
+            op
+        })
+        .collect()
+}
+
+// merges every block reached only by jump from the previous one,
+// merging doesn't change number of predecessors, so whole chains are merged in one pass
+fn merge_blocks(code: Vec<Op>) -> Vec<Op> {
+    let blocks = find_blocks(&code);
+    let mut predecessors: HashMap<Address, usize> = HashMap::new();
+    for next in successors(&code, &blocks).values() {
+        for label in next {
+            *predecessors.entry(*label).or_insert(0) += 1;
+        }
+    }
+    let structural = structural_labels(&code);
+
+    // label of merged block and label of block it ends up in
+    let mut merged: HashMap<Address, Address> = HashMap::new();
+    for id in 1..code.len() {
+        let label = code[id].0;
+        let jumps_here = match code[id - 1].1 {
+            Operation::Jump(target) => target == label,
+            _ => false,
+        };
+        if jumps_here
+            && code[id].1 == Operation::Label
+            && predecessors.get(&label) == Some(&1)
+            && !structural.contains(&label)
+        {
+            // previous block may be merged already
+            let merged_into = resolve_replacement(&merged, blocks[id - 1]);
+            log::debug!("merging block {} into {}", label, merged_into);
+            merged.insert(label, merged_into);
+        }
+    }
+    if merged.is_empty() {
+        return code;
+    }
+
+    code.into_iter()
+        .filter(|op| match op.1 {
+            // the only jump to merged block
+            Operation::Jump(target) => !merged.contains_key(&target),
+            Operation::Label => !merged.contains_key(&op.0),
+            _ => true,
+        })
+        .map(|op| relabel(op, &merged))
+        .collect()
+}
+
+fn relabel(op: Op, merged: &HashMap<Address, Address>) -> Op {
+    let label = |x: Address| *merged.get(&x).unwrap_or(&x);
+    let (ret, op) = op;
+    let op = match op {
+        Operation::Jump(target) => Operation::Jump(label(target)),
+        Operation::JumpIfElse(condition, first, second) => Operation::JumpIfElse(condition, label(first), label(second)),
+        Operation::LoopMerge(continue_label, exit_label) => Operation::LoopMerge(label(continue_label), label(exit_label)),
+        Operation::Exit(value, block) => Operation::Exit(value, label(block)),
+        Operation::Phi(record) => Operation::Phi(PhiRecord {
+            label: label(record.label),
+            old_label: label(record.old_label),
+            ..record
+        }),
+        x => x,
+    };
+    (ret, op)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jump_chains_are_merged() {
+        use Operation::*;
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Jump(4)),
+            (4, Label),
+            (5, Jump(6)),
+            (6, Label),
+            (7, Neg(2)),
+            (8, Exit(7, 6)),
+        ];
+        let result: Vec<_> = simplify_cfg(PipelineIR::new(code)).operations().cloned().collect();
+        assert_eq!(result, vec![(1, Label), (2, Arg(0)), (7, Neg(2)), (8, Exit(7, 1))]);
+    }
+
+    #[test]
+    fn trivial_phis_are_folded() {
+        use Operation::*;
+        let record = PhiRecord {
+            new: 2,
+            label: 6,
+            old: 2,
+            old_label: 1,
+        };
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, StoreBool(true)),
+            (4, Arg(1)),
+            (5, JumpIfElse(4, 6, 8)),
+            (6, Label),
+            (7, Jump(8)),
+            (8, Label),
+            (9, Phi(record)),
+            (10, Neg(9)),
+            (11, Exit(10, 8)),
+        ];
+        let result: Vec<_> = simplify_cfg(PipelineIR::new(code)).operations().cloned().collect();
+        assert!(!result.iter().any(|x| x.1.is_phi()));
+        assert!(result.contains(&(10, Neg(2))));
+    }
+
+    fn simplified(code: Vec<Op>) -> Vec<Op> {
+        simplify_cfg(PipelineIR::new(code)).operations().cloned().collect()
+    }
+
+    #[test]
+    fn phi_labels_follow_merged_blocks() {
+        use Operation::*;
+        let record = PhiRecord {
+            new: 8,
+            label: 7,
+            old: 2,
+            old_label: 10,
+        };
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Arg(1)),
+            (4, JumpIfElse(3, 5, 10)),
+            (5, Label),
+            (6, Jump(7)),
+            (7, Label),
+            (8, Neg(2)),
+            (9, Jump(12)),
+            (10, Label),
+            (11, Jump(12)),
+            (12, Label),
+            (13, Phi(record)),
+            (14, Exit(13, 12)),
+        ];
+        let merged_record = PhiRecord { label: 5, ..record };
+        let expected = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Arg(1)),
+            (4, JumpIfElse(3, 5, 10)),
+            (5, Label),
+            (8, Neg(2)),
+            (9, Jump(12)),
+            (10, Label),
+            (11, Jump(12)),
+            (12, Label),
+            (13, Phi(merged_record)),
+            (14, Exit(13, 12)),
+        ];
+        assert_eq!(simplified(code), expected);
+    }
+
+    #[test]
+    fn loops_in_unreachable_code_are_removed() {
+        use Operation::*;
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, StoreBool(true)),
+            // folded branch, loop below is never entered
+            (4, Jump(20)),
+            (5, Label),
+            (6, LoopMerge(10, 12)),
+            (7, Jump(8)),
+            (8, Label),
+            (9, JumpIfElse(3, 10, 12)),
+            (10, Label),
+            (11, Jump(5)),
+            (12, Label),
+            (13, Jump(20)),
+            (20, Label),
+            (21, Neg(2)),
+            (22, Exit(21, 20)),
+        ];
+        let expected = vec![(1, Label), (2, Arg(0)), (3, StoreBool(true)), (21, Neg(2)), (22, Exit(21, 1))];
+        assert_eq!(simplified(code), expected);
+    }
+
+    #[test]
+    fn reachable_loop_keeps_blocks_never_entered() {
+        use Operation::*;
+        // condition is folded, so continue block is never entered
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Jump(4)),
+            (4, Label),
+            (5, LoopMerge(9, 11)),
+            (6, Jump(7)),
+            (7, Label),
+            (8, Jump(11)),
+            (9, Label),
+            (10, Jump(4)),
+            (11, Label),
+            (12, Neg(2)),
+            (13, Exit(12, 11)),
+        ];
+        assert_eq!(simplified(code.clone()), code);
+    }
+}
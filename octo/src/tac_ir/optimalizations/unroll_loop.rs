@@ -1,16 +1,96 @@
+use errors::IrError;
+
 use super::ir::*;
 use super::super::utils::{PeekableCode, find_loop, LoopCode};
 use super::ConstantPropagationContext;
 use super::constants_propagation::propagate_constant_operation;
 
 use std::collections::HashMap;
+use std::convert::Infallible;
+
+/// Unrolls every loop containing sync, pipeline can't be split into passes otherwise.
+/// Fails if trip count of such loop isn't known at compile time.
+pub fn unroll_synced_loop(code: PipelineIR) -> Result<PipelineIR, IrError> {
+    unroll(code, contains_sync, true, |state, loop_data, phi_nodes| {
+        let unrolled = unroll_loop(
+            &mut state.code,
+            loop_data,
+            phi_nodes,
+            &mut state.constants,
+            &mut state.max_id,
+            usize::max_value(),
+        );
+        match unrolled {
+            Result::Ok(var_map) => {
+                state.address_map.extend(var_map);
+                Result::Ok(())
+            }
+            Result::Err(()) => Result::Err(IrError::SyncInLoop),
+        }
+    })
+}
+
+/// Unrolls innermost loops without syncs, if their trip count is constant
+/// and unrolled code takes at most `max_operations` operations. Other loops are kept.
+pub fn unroll_constant_loops(code: PipelineIR, max_operations: usize) -> PipelineIR {
+    let selected = |loop_data: &LoopCode| !contains_sync(loop_data) && is_straight(loop_data);
+    let unrolled = unroll(code, selected, false, |state, loop_data, phi_nodes| {
+        // unrolling may fail halfway, so it works on copies
+        let mut unrolled_code = Vec::new();
+        let mut unrolled_constants = state.constants.clone();
+        let mut unrolled_max_id = state.max_id;
+        let unrolled = unroll_loop(
+            &mut unrolled_code,
+            loop_data,
+            phi_nodes,
+            &mut unrolled_constants,
+            &mut unrolled_max_id,
+            max_operations,
+        );
+        match unrolled {
+            Result::Ok(var_map) => {
+                log::debug!("unrolled loop at {} into {} operations", loop_data.entry_label, unrolled_code.len());
+                state.code.extend(unrolled_code);
+                state.constants = unrolled_constants;
+                state.max_id = unrolled_max_id;
+                state.address_map.extend(var_map);
+            }
+            // loops that can't be unrolled are kept
+            Result::Err(()) => {
+                state.code.push((loop_data.entry_label, Operation::Label));
+                state.code.extend(phi_nodes.iter().rev().map(|(ret, record)| (*ret, Operation::Phi(*record))));
+                let address_map = &state.address_map;
+                state.code.extend(loop_data.emit().map(|op| map_addresses(op, address_map)));
+            }
+        }
+        Result::<(), Infallible>::Ok(())
+    });
+    match unrolled {
+        Result::Ok(x) => x,
+        Result::Err(never) => match never {},
+    }
+}
 
-pub fn unroll_synced_loop(code: PipelineIR) -> PipelineIR {
+// code emitted so far and what is needed to unroll following loops
+struct Unrolling {
+    code: Vec<Op>,
+    constants: ConstantPropagationContext,
+    max_id: Address,
+    address_map: HashMap<Address, Address>,
+}
+
+// selected loops are passed to `unroll_selected` without their entry label and phi nodes,
+// with `move_syncs` syncs right before loop are moved into its body first
+fn unroll<E>(
+    code: PipelineIR,
+    selected: impl Fn(&LoopCode) -> bool,
+    move_syncs: bool,
+    mut unroll_selected: impl FnMut(&mut Unrolling, &LoopCode, &[(Address, PhiRecord)]) -> Result<(), E>,
+) -> Result<PipelineIR, E> {
     let (code, inputs, outputs, uniforms) = code.take();
 
     let mut constants = ConstantPropagationContext::default();
-    let mut max_id = code.iter().map(|x| x.0).max().unwrap();
-    //println!("max id is {}", max_id);
+    let max_id = code.iter().map(|x| x.0).max().unwrap();
 
     for (ret, op) in code.iter() {
         let ret = *ret;
@@ -46,77 +126,106 @@ pub fn unroll_synced_loop(code: PipelineIR) -> PipelineIR {
             _ => (),
         }
     }
-    
-    let mut peekable = PeekableCode::new(code.iter());
 
-    let mut result_code = Vec::new();
-
-    let mut address_map = HashMap::new();
+    let mut peekable = PeekableCode::new(code.iter());
 
+    let mut state = Unrolling {
+        code: Vec::new(),
+        constants,
+        max_id,
+        address_map: HashMap::new(),
+    };
 
     let mut last_label = 0;
 
     while let Some((ret, op_code)) = peekable.next().copied() {
-        let mut orig = (ret, op_code);
-        let original = (ret, op_code);
-        for (from, to) in address_map.iter() {
-            replace(&mut orig, *from, *to, false);
-        }
-        //println!("not unroll: mapped {:?} into {:?}", original, orig);
-        result_code.push(orig);
+        let orig = map_addresses((ret, op_code), &state.address_map);
+        state.code.push(orig);
         match op_code {
             Operation::Label => last_label = ret,
             Operation::LoopMerge(..) => {
                 let mut loop_data = find_loop(ret, op_code, &mut peekable, last_label);
-                result_code.pop();
-                if !contains_sync(&loop_data) {
-                    result_code.extend(loop_data.emit());
+                state.code.pop();
+                if !selected(&loop_data) {
+                    let address_map = &state.address_map;
+                    state.code.extend(loop_data.emit().map(|op| map_addresses(op, address_map)));
                     continue;
                 }
                 // current op: LoopMerge
                 let mut phi_nodes = Vec::new();
                 loop {
-                    while let Some((phi_ret, Operation::Phi(record))) = result_code.last() {
+                    while let Some((phi_ret, Operation::Phi(record))) = state.code.last() {
                         phi_nodes.push((*phi_ret, *record));
-                        result_code.pop();
+                        state.code.pop();
+                    }
+                    if !move_syncs {
+                        break;
                     }
-                    if let Some((sync_ret, Operation::Sync(val))) = result_code.last() {
-                        loop_data.body.insert(1, (*sync_ret, Operation::Sync(*val)));
-                        result_code.pop();
+                    if let Some(&(sync_ret, sync_op)) = state.code.last().filter(|x| x.1.synced_value().is_some()) {
+                        loop_data.body.insert(1, (sync_ret, sync_op));
+                        state.code.pop();
                     } else {
                         break;
                     }
                 }
-                assert!(*result_code.last().unwrap() == (loop_data.entry_label, Operation::Label));
-                result_code.pop();
-
-                let var_map = unroll_loop(&mut result_code, loop_data, phi_nodes, &mut constants, &mut max_id);
-                address_map.extend(var_map);
+                assert!(*state.code.last().unwrap() == (loop_data.entry_label, Operation::Label));
+                state.code.pop();
 
+                unroll_selected(&mut state, &loop_data, &phi_nodes)?;
             }
             _ => (),
+        }
+    }
 
+    Result::Ok(PipelineIR::construct(state.code, inputs, outputs, uniforms))
+}
+
+// replace does not touch labels of phi nodes
+fn map_addresses(op: Op, address_map: &HashMap<Address, Address>) -> Op {
+    let mut op = op;
+    for (from, to) in address_map.iter() {
+        replace(&mut op, *from, *to, false);
+    }
+    if let Operation::Phi(record) = &mut op.1 {
+        if let Some(label) = address_map.get(&record.label) {
+            record.label = *label;
+        }
+        if let Some(label) = address_map.get(&record.old_label) {
+            record.old_label = *label;
         }
     }
-    //println!("final address map: {:?}", address_map);
+    op
+}
 
-    PipelineIR::construct(result_code, inputs, outputs, uniforms)
+// no control flow inside, so nothing else than values is renamed while unrolling
+fn is_straight(loop_code: &LoopCode) -> bool {
+    loop_code
+        .condition
+        .iter()
+        .chain(loop_code.body.iter())
+        .chain(loop_code.continue_code.iter())
+        .all(|x| match x.1 {
+            Operation::Label
+            | Operation::Jump(..)
+            | Operation::JumpIfElse(..)
+            | Operation::LoopMerge(..)
+            | Operation::Phi(..) => false,
+            _ => true,
+        })
 }
 
 fn contains_sync(loop_code: &LoopCode) -> bool {
-    loop_code.body.iter().any(|x| match x.1 {
-        Operation::Sync(_) => true,
-        _ => false
-    })
+    loop_code.body.iter().any(|x| x.1.synced_value().is_some())
 }
 
 fn unroll_loop(
     result_code: &mut Vec<Op>,
-    loop_data: LoopCode,
-    phi_nodes: Vec<(Address, PhiRecord)>,
+    loop_data: &LoopCode,
+    phi_nodes: &[(Address, PhiRecord)],
     constants: &mut ConstantPropagationContext,
     max_id: &mut Address,
-) -> HashMap<Address, Address> {
+    max_operations: usize,
+) -> Result<HashMap<Address, Address>, ()> {
 
     result_code.push((loop_data.entry_label, Operation::Label));
 
@@ -130,7 +239,7 @@ fn unroll_loop(
     let mut iter = 0;
 
     // emit first loop phi nodes
-    for phi in &phi_nodes {
+    for phi in phi_nodes {
         //println!("Inserting phi: {} with value of {}, constant: {}", phi.0, phi.1.old, constants.get_const(&phi.1.old).is_some());
 
         address_map.insert(phi.0, phi.1.old);
@@ -140,9 +249,8 @@ fn unroll_loop(
 
     loop {
 
-        if max_iterations <= iter {
-            // TODO: proper error handling.
-            panic!("Code contains loop with sync that couldn't be statically unrolled.");
+        if max_iterations <= iter || result_code.len() > max_operations {
+            return Result::Err(());
         }
 
         let mut condition_operations : Vec<_> = loop_data.condition.clone().into_iter().rev().collect();
@@ -167,14 +275,8 @@ fn unroll_loop(
         let condition_op = result_code.last().unwrap();
         let condition_met = match condition_op.1 {
             Operation::StoreBool(value) => value,
-            Operation::JumpIfElse(..) => {
-                panic!("For loop with sync couldn't be unrolled");
-            }
-            _=> {
-                //println!("iteration: {}", iter);
-                //println!("result: {:#?}", result_code);
-                panic!(format!("Unexpected operation: {:?}", condition_op.1));
-            },
+            // condition is not constant
+            _ => return Result::Err(()),
         };
 
         if !condition_met {
@@ -205,7 +307,7 @@ fn unroll_loop(
             result_code.push((op.0, new_op));
         }
 
-        for phi in &phi_nodes {
+        for phi in phi_nodes {
             let new_addr = address_map[&phi.1.new];
             address_map.insert(phi.0, new_addr);
         }
@@ -217,7 +319,7 @@ fn unroll_loop(
         // emit phi and map in address_map
         for phi in &phi_nodes {
             let new_add = address_map[&phi.1.new];
-            println!("Phi {} is now {}", phi.0, new_add);
+            log::debug!("Phi {} is now {}", phi.0, new_add);
             address_map.insert(phi.0, new_add);
             
         }
@@ -230,6 +332,39 @@ fn unroll_loop(
     //println!("label on exit: {} into {}", loop_data.exit_label, label);
     address_map.insert(loop_data.exit_label, label);
 
-    return address_map;
+    if result_code.len() > max_operations {
+        return Result::Err(());
+    }
+    Result::Ok(address_map)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tac_ir;
+    use crate::tac_ir::test_utils::compile;
+
+    #[test]
+    fn sync_in_loop_of_unknown_length_is_rejected() {
+        let program = compile("
+            pipeline main(color: vec4) -> (vec4)
+            with {
+                size: vec2,
+            }
+            {
+                let c = color;
+                for (let i = 0.0; i < size.x; i = i + 1.0) {
+                    c = shift(c, vec2(1.0, 0.0) / size);
+                }
+                return c;
+            }
+        ");
+        let program = tac_ir::propagate_constants(program);
+        let program = tac_ir::move_sync_operations(program);
+        match unroll_synced_loop(program) {
+            Result::Err(IrError::SyncInLoop) => {}
+            x => panic!("expected error, got {:?}", x.map(|_| ())),
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use errors::IrError;
 use octo_runtime as or;
 use std::collections::{HashMap, HashSet};
 
-use super::ir::{Address, Op, Operation, PipelineIR, ValueType, PhiRecord};
+use super::ir::{replace, Address, Op, Operation, PipelineIR, ValueType, PhiRecord};
 use super::utils::{find_loop, LoopCode, PeekableCode, find_if_else, IfElseCode};
 
 #[derive(Debug, Clone)]
@@ -28,19 +29,36 @@ impl std::convert::Into<or::InputType> for InputTexture {
 
 #[derive(Debug, Clone)]
 pub enum OutputTexture {
-    Result,
+    Result(Vec<usize>), // pipeline results, n-th one goes to n-th attachment
     Generated(Vec<usize>),
 }
 
 impl std::convert::Into<or::OutputType> for OutputTexture {
     fn into(self) -> or::OutputType {
         match self {
-            OutputTexture::Result => or::OutputType::Result,
+            // single result keeps format readable by runtimes without multiple results
+            OutputTexture::Result(ref x) if x.len() == 1 => or::OutputType::Result,
+            OutputTexture::Result(x) => or::OutputType::Results(x),
             OutputTexture::Generated(x) => or::OutputType::Textures(x),
         }
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureSize {
+    Original,
+    Scaled(f32),
+}
+
+impl std::convert::Into<or::TextureSize> for TextureSize {
+    fn into(self) -> or::TextureSize {
+        match self {
+            TextureSize::Original => or::TextureSize::Original,
+            TextureSize::Scaled(x) => or::TextureSize::Scaled(x),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ShaderPass {
     pub shader_id: usize,
@@ -53,19 +71,26 @@ pub struct ShaderPass {
 pub struct PipelineDef {
     pub shaders: Vec<ShaderDef>,
     pub passes: Vec<ShaderPass>,
-    pub textures: Vec<ValueType>,
+    pub textures: Vec<(ValueType, TextureSize)>,
+    // physical texture of n-th generated texture
+    pub texture_slots: Vec<usize>,
     pub args: Vec<(ValueType, String)>,
     pub uniforms: Vec<(ValueType, String)>,
 }
 
-pub fn split(program: PipelineIR) -> PipelineDef {
+pub fn split(program: PipelineIR) -> Result<PipelineDef, IrError> {
     let (operations, inputs, outputs, uniforms) = program.take();
+    let operations = remove_redundant_syncs(operations);
 
     let mut syncs = find_syncs(&operations);
 
     if syncs.len() == 0 {
-        let inputs_num = inputs.len();
-        let outputs_num = outputs.len();
+        let the_only_pass = ShaderPass {
+            shader_id: 0,
+            input: (0..inputs.len()).map(|x| InputTexture::Arg(x)).collect(),
+            output: OutputTexture::Result((0..outputs.len()).collect()),
+            dependencies: Option::None,
+        };
 
         let the_only_shader = ShaderDef {
             code: operations,
@@ -73,30 +98,18 @@ pub fn split(program: PipelineIR) -> PipelineDef {
             output_type: outputs,
         };
 
-        //println!("outputs: {}", outputs_num);
-        let the_only_pass = ShaderPass {
-            shader_id: 0,
-            input: (0..inputs_num).map(|x| InputTexture::Arg(x)).collect(),
-            output: OutputTexture::Result,
-            dependencies: Option::None,
-        };
-
-        return PipelineDef {
+        return Result::Ok(PipelineDef {
             shaders: vec![the_only_shader],
             passes: vec![the_only_pass],
             textures: vec![],
+            texture_slots: vec![],
             args: inputs,
             uniforms
-        };
+        });
     }
 
     let dependencies = prepare_dependencies(&operations);
 
-    for (op, val, index, block) in &syncs {
-        if dependencies.contains_key(op) {
-        }
-    }
-
     let types = check_types(&operations, &inputs, &uniforms);
 
     let last_op = operations.last().unwrap();
@@ -109,66 +122,94 @@ pub fn split(program: PipelineIR) -> PipelineDef {
 
     syncs.push((last_op.0, exit_value, operations.len()-1, 100000));
 
+    // results other than first one are stored before exit
+    let output_operations: Vec<Address> = operations.iter().filter_map(|x| match x.1 {
+        Operation::Output(..) => Some(x.0),
+        _ => None,
+    }).collect();
+
+
+    // syncs stored in every texture, the last group stands for results
+    let groups = pack_syncs(&syncs, &operations, &types, &dependencies);
+    let scales = value_scales(&operations)?;
+    let sizes = groups
+        .iter()
+        .map(|group| texture_size(&operations, syncs[group[0]].2, &scales))
+        .collect::<Result<Vec<_>, _>>()?;
+    // textures written by every pass
+    let passes = merge_passes(&groups, &syncs, &sizes, &operations, &dependencies);
+    // texture storing every sync and offset of its first channel
+    let mut packing: HashMap<Address, (usize, usize)> = HashMap::new();
+    for (group_id, group) in groups.iter().enumerate() {
+        let mut offset = 0;
+        for sync in group {
+            packing.insert(syncs[*sync].0, (group_id, offset));
+            offset += components(types[&syncs[*sync].1]);
+        }
+    }
+    let mut writer = vec![0; groups.len()];
+    for (pass_id, pass) in passes.iter().enumerate() {
+        for texture in pass {
+            writer[*texture] = pass_id;
+        }
+    }
+    let texture_type = |group: &Vec<usize>| {
+        if group.len() > 1 {
+            ValueType::Vec4
+        } else {
+            types[&syncs[group[0]].1]
+        }
+    };
 
-    let mut programs: Vec<Vec<(Address, Operation)>> = Vec::with_capacity(syncs.len() + 1);
-
-
-    for (sync_operation, synced_value, index, block_label_at_split) in &syncs {
-        //println!("generating program for syncing {}", synced_value);
-        let mut used = HashSet::new();
-        let mut to_check = Vec::new();
-        to_check.push(*synced_value);
+    let mut programs: Vec<Vec<(Address, Operation)>> = Vec::with_capacity(passes.len());
 
-        while let Some(addr) = to_check.pop() {
-            if used.contains(&addr) {
-                continue;
-            }
-            used.insert(addr);
-            //println!("checking {}", addr);
-            match dependencies.get(&addr) {
-                None => (),
-                Some(val) => to_check.extend_from_slice(val),
-            }
+    for (id, pass) in passes.iter().enumerate() {
+        let mut roots: Vec<Address> = pass.iter().flat_map(|x| groups[*x].iter()).map(|x| syncs[*x].1).collect();
+        if id == passes.len() - 1 {
+            roots.extend_from_slice(&output_operations);
         }
+        let used = used_operations(&dependencies, roots);
 
         let new_program = operations.iter().filter(|x| used.contains(&x.0)).cloned().collect();
 
-        //println!("program generating {}: {:?}", synced_value, new_program);
         programs.push(new_program);
     }
-    //syncs.pop();
 
     let mut shaders: Vec<ShaderDef> = Vec::with_capacity(programs.len());
     let mut shader_passes: Vec<ShaderPass> = Vec::with_capacity(programs.len());
 
     for (id, program) in programs.iter().enumerate() {
         let mut deps = Vec::new();
+        let mut read = Vec::new();
         let program_inputs: Vec<_> = program.iter().filter_map(|x| {
             match x.1 {
                 Operation::Arg(y) => {
-                    println!("looking for type for: {}", y);
+                    log::debug!("looking for type for: {}", y);
                     let t = types[&x.0];
                     Some((InputTexture::Arg(y), t))
                 },
-                Operation::Sync(val) => {
-                    let sc = syncs.iter().enumerate().find(|(id, s)| s.1 == val);
-                    match sc {
+                Operation::Sync(..) | Operation::Scale(..) => {
+                    let group = match packing.get(&x.0) {
                         None => panic!("Internal compiler error"),
-                        Some(t) => {
-                            let typ = types[&(t.1).1];
-                            deps.push(t.0);
-                            Some((InputTexture::Generated(t.0), typ))
-                        }
+                        Some(t) => t.0,
+                    };
+                    // values packed together are read from single texture
+                    if read.contains(&group) {
+                        return None;
                     }
+                    read.push(group);
+                    if !deps.contains(&writer[group]) {
+                        deps.push(writer[group]);
+                    }
+                    Some((InputTexture::Generated(group), texture_type(&groups[group])))
                 }
                 _=> None
             }
         }).collect();
-        let (t, ret) = if id ==syncs.len()-1 {
-            (*outputs.first().unwrap(), OutputTexture::Result)
+        let (output_types, ret) = if id == passes.len() - 1 {
+            (outputs.clone(), OutputTexture::Result((0..outputs.len()).collect()))
         } else {
-            let sc = syncs[id];
-            (types[&sc.1], OutputTexture::Generated(vec![id]))
+            (passes[id].iter().map(|x| texture_type(&groups[*x])).collect(), OutputTexture::Generated(passes[id].clone()))
         };
 
         let shader_inputs: Vec<_> = program_inputs.iter().map(|x| x.1).collect();
@@ -185,7 +226,7 @@ pub fn split(program: PipelineIR) -> PipelineDef {
             }
         });
 
-        let mut shader_code: Vec<_> = std::iter::once((1, Operation::Label)).chain(program.iter().map(|x| {
+        let shader_code: Vec<_> = std::iter::once((1, Operation::Label)).chain(program.iter().map(|x| {
             let op = match x.1 {
                 Operation::Label => {
                     label_stack.push(x.0);
@@ -202,13 +243,10 @@ pub fn split(program: PipelineIR) -> PipelineDef {
                     Operation::Arg(id.0)
 
                 },
-                Operation::Sync(addr) =>{
-                    let sc = syncs.iter().enumerate().find(|(id, s)| s.1 == addr);
-                    let sc_id = match sc {
+                Operation::Sync(..) | Operation::Scale(..) =>{
+                    let sc_id = match packing.get(&x.0) {
                         None => panic!("Internal compiler error"),
-                        Some(t) => {
-                            t.0
-                        }
+                        Some(t) => t.0,
                     };
                     let id = program_in.iter().enumerate().find(|(id, value)| {
                         match value {
@@ -230,13 +268,39 @@ pub fn split(program: PipelineIR) -> PipelineDef {
             };
             (x.0, op)
         })).collect();
-        shader_code.push((shader_code.last().unwrap().0 + 1, Operation::Exit(syncs[id].1, last_label)));
+        let mut max_id = operations.iter().map(|x| x.0).max().unwrap();
+        let packed_inputs: HashMap<Address, (usize, ValueType)> = program.iter().filter_map(|x| {
+            match packing.get(&x.0) {
+                Some((group, offset)) if x.1.synced_value().is_some() && groups[*group].len() > 1 => {
+                    Some((x.0, (*offset, types[&x.0])))
+                }
+                _ => None,
+            }
+        }).collect();
+        let mut shader_code = unpack_inputs(shader_code, &packed_inputs, &mut max_id);
+        let mut written = Vec::with_capacity(passes[id].len());
+        for texture in &passes[id] {
+            let group = &groups[*texture];
+            let value = if group.len() > 1 {
+                let values: Vec<_> = group.iter().map(|x| (syncs[*x].1, types[&syncs[*x].1])).collect();
+                pack_values(&mut shader_code, &values, &mut max_id)
+            } else {
+                syncs[group[0]].1
+            };
+            written.push(value);
+        }
+        // n-th texture goes to n-th attachment, the first one is written by exit
+        for (attachment, value) in written.iter().enumerate().skip(1) {
+            max_id += 1;
+            shader_code.push((max_id, Operation::Output(attachment, *value)));
+        }
+        shader_code.push((max_id + 1, Operation::Exit(written[0], last_label)));
 
-        println!("Generated shader: {:?}", shader_code);
+        log::debug!("Generated shader: {:?}", shader_code);
 
         let mut pp_ir = PipelineIR::new(shader_code);
         pp_ir.inputs = shader_inputs.iter().map(|x| (*x, "".to_owned())).collect();
-        pp_ir.outputs = vec![t];
+        pp_ir.outputs = output_types.clone();
         pp_ir.uniforms = uniforms.clone();
 
         let pp_ir = super::optimalizations::remove_unused_operations(pp_ir);
@@ -247,7 +311,7 @@ pub fn split(program: PipelineIR) -> PipelineDef {
         shaders.push(ShaderDef{
             code: shader_code,
             input_type: shader_inputs,
-            output_type: vec![t],
+            output_type: output_types,
         });
 
         shader_passes.push(ShaderPass{
@@ -258,20 +322,331 @@ pub fn split(program: PipelineIR) -> PipelineDef {
         });
 
     }
-    syncs.pop();
-
-    let textures = syncs.iter().map(|x| types[&x.1]).collect();
-    let outputs_num = outputs.len();
-
-    //println!("outputs: {}", outputs_num);
-
-    return PipelineDef {
+    let textures = groups[..groups.len() - 1]
+        .iter()
+        .zip(sizes.iter())
+        .map(|(group, size)| (texture_type(group), *size))
+        .collect();
+    let (textures, texture_slots) = share_textures(&mut shader_passes, textures);
+
+    return Result::Ok(PipelineDef {
         shaders: shaders,
         passes: shader_passes,
         textures: textures,
+        texture_slots,
         args: inputs,
         uniforms
+    });
+}
+
+/// Assigns synced values to physical textures, values whose lifetimes don't overlap share one
+/// if their type and size match. Passes are rewritten to use physical textures.
+fn share_textures(
+    passes: &mut Vec<ShaderPass>,
+    textures: Vec<(ValueType, TextureSize)>,
+) -> (Vec<(ValueType, TextureSize)>, Vec<usize>) {
+    // passes finished before given one starts, dependencies of dependencies included
+    let mut ancestors: Vec<HashSet<usize>> = Vec::with_capacity(passes.len());
+    for pass in passes.iter() {
+        let mut before = HashSet::new();
+        for dependency in pass.dependencies.iter().flatten() {
+            before.insert(*dependency);
+            before.extend(ancestors[*dependency].iter().cloned());
+        }
+        ancestors.push(before);
+    }
+
+    // passes writing or reading texture
+    let mut users: Vec<Vec<usize>> = vec![Vec::new(); textures.len()];
+    for (id, pass) in passes.iter().enumerate() {
+        for input in &pass.input {
+            if let InputTexture::Generated(texture) = input {
+                users[*texture].push(id);
+            }
+        }
+        if let OutputTexture::Generated(outputs) = &pass.output {
+            for texture in outputs {
+                users[*texture].push(id);
+            }
+        }
+    }
+
+    let mut physical: Vec<(ValueType, TextureSize)> = Vec::new();
+    // last value stored in physical texture, users of previous ones finished before it was written
+    let mut occupants: Vec<usize> = Vec::new();
+    let mut slots = vec![0; textures.len()];
+    for (id, pass) in passes.iter().enumerate() {
+        let outputs = match &pass.output {
+            OutputTexture::Generated(outputs) => outputs,
+            OutputTexture::Result(..) => continue,
+        };
+        for texture in outputs {
+            let free = (0..physical.len()).find(|slot| {
+                physical[*slot] == textures[*texture]
+                    && users[occupants[*slot]].iter().all(|user| ancestors[id].contains(user))
+            });
+            let slot = match free {
+                Some(slot) => {
+                    log::debug!("texture {} reuses slot of texture {}", texture, occupants[slot]);
+                    occupants[slot] = *texture;
+                    slot
+                }
+                None => {
+                    physical.push(textures[*texture]);
+                    occupants.push(*texture);
+                    physical.len() - 1
+                }
+            };
+            slots[*texture] = slot;
+        }
+    }
+
+    for pass in passes.iter_mut() {
+        for input in pass.input.iter_mut() {
+            if let InputTexture::Generated(texture) = input {
+                *texture = slots[*texture];
+            }
+        }
+        if let OutputTexture::Generated(outputs) = &mut pass.output {
+            for texture in outputs.iter_mut() {
+                *texture = slots[*texture];
+            }
+        }
+    }
+    (physical, slots)
+}
+
+// Sync of value that is already stored in texture (synced or scaled, possibly through copies) can be dropped.
+fn remove_redundant_syncs(program: Vec<Op>) -> Vec<Op> {
+    let mut program = program;
+    let resolve = |program: &Vec<Op>, addr: Address| {
+        let mut addr = addr;
+        loop {
+            match program.iter().find(|x| x.0 == addr) {
+                Some((_, Operation::Store(x))) => addr = *x,
+                Some((_, op)) if op.synced_value().is_some() => return Some(addr),
+                _ => return None,
+            }
+        }
     };
+
+    let redundant: Vec<_> = program.iter().filter_map(|x| match x.1 {
+        Operation::Sync(val) => resolve(&program, val).map(|texture| (x.0, texture)),
+        _ => None,
+    }).collect();
+
+    for (sync_address, texture_address) in redundant {
+        program.retain(|x| x.0 != sync_address);
+        for op in program.iter_mut() {
+            replace(op, sync_address, texture_address, false);
+        }
+    }
+    program
+}
+
+// Number of texture channels taken by value.
+fn components(typ: ValueType) -> usize {
+    match typ {
+        ValueType::Vec2 => 2,
+        ValueType::Vec3 => 3,
+        ValueType::Vec4 => 4,
+        _ => 1,
+    }
+}
+
+// Operations needed to compute given values, syncs are computed by previous passes.
+fn used_operations(dependencies: &HashMap<Address, Vec<Address>>, roots: Vec<Address>) -> HashSet<Address> {
+    let mut used = HashSet::new();
+    let mut to_check = roots;
+    while let Some(addr) = to_check.pop() {
+        if !used.insert(addr) {
+            continue;
+        }
+        if let Some(val) = dependencies.get(&addr) {
+            to_check.extend_from_slice(val);
+        }
+    }
+    used
+}
+
+/// Groups syncs computed by the same pass, the last group is exit. Consecutive syncs of one block
+/// which don't depend on each other are packed into channels of single vec4 texture.
+fn pack_syncs(
+    syncs: &[(Address, Address, usize, Address)],
+    operations: &[Op],
+    types: &HashMap<Address, ValueType>,
+    dependencies: &HashMap<Address, Vec<Address>>,
+) -> Vec<Vec<usize>> {
+    // scaled textures have different size, integers and booleans are not stored in floats
+    let packable = |sync: &(Address, Address, usize, Address)| match (operations[sync.2].1, types[&sync.1]) {
+        (Operation::Sync(..), ValueType::Float)
+        | (Operation::Sync(..), ValueType::Vec2)
+        | (Operation::Sync(..), ValueType::Vec3) => true,
+        _ => false,
+    };
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut channels = 0;
+    for (id, sync) in syncs.iter().enumerate().take(syncs.len() - 1) {
+        let size = components(types[&sync.1]);
+        let fits = match groups.last() {
+            Some(group) if packable(sync) && packable(&syncs[group[0]]) => {
+                let used = used_operations(dependencies, vec![sync.1]);
+                syncs[group[0]].3 == sync.3
+                    && channels + size <= 4
+                    && group.iter().all(|x| !used.contains(&syncs[*x].0))
+            }
+            _ => false,
+        };
+        if fits {
+            log::debug!("packing sync {} with {}", sync.0, syncs[groups.last().unwrap()[0]].0);
+            groups.last_mut().unwrap().push(id);
+            channels += size;
+        } else {
+            groups.push(vec![id]);
+            channels = size;
+        }
+    }
+    groups.push(vec![syncs.len() - 1]);
+    groups
+}
+
+// Most color attachments written by single pass.
+const MAX_ATTACHMENTS: usize = 4;
+
+/// Groups textures written by the same pass, the last group computes results. Consecutive textures
+/// of one block and size which don't depend on each other but share some work are written together.
+fn merge_passes(
+    groups: &[Vec<usize>],
+    syncs: &[(Address, Address, usize, Address)],
+    sizes: &[TextureSize],
+    operations: &[Op],
+    dependencies: &HashMap<Address, Vec<Address>>,
+) -> Vec<Vec<usize>> {
+    let kinds: HashMap<Address, Operation> = operations.iter().cloned().collect();
+    // computations and samples, not constants or control flow
+    let is_work = |address: &Address| match kinds.get(address) {
+        Some(Operation::Label)
+        | Some(Operation::Jump(..))
+        | Some(Operation::LoopMerge(..))
+        | Some(Operation::Uniform(..))
+        | Some(Operation::StoreInt(..))
+        | Some(Operation::StoreFloat(..))
+        | Some(Operation::StoreVec2(..))
+        | Some(Operation::StoreVec3(..))
+        | Some(Operation::StoreVec4(..))
+        | Some(Operation::StoreBool(..))
+        | None => false,
+        _ => true,
+    };
+    let block = |texture: usize| syncs[groups[texture][0]].3;
+
+    let mut passes: Vec<Vec<usize>> = Vec::new();
+    let mut used: HashSet<Address> = HashSet::new();
+    for texture in 0..groups.len() - 1 {
+        let texture_used = used_operations(dependencies, groups[texture].iter().map(|x| syncs[*x].1).collect());
+        let fits = match passes.last() {
+            Some(pass) => {
+                pass.len() < MAX_ATTACHMENTS
+                    && block(pass[0]) == block(texture)
+                    && sizes[pass[0]] == sizes[texture]
+                    && pass.iter().flat_map(|x| groups[*x].iter()).all(|x| !texture_used.contains(&syncs[*x].0))
+                    && texture_used.iter().any(|x| used.contains(x) && is_work(x))
+            }
+            None => false,
+        };
+        if fits {
+            log::debug!("texture {} is written by the same pass as texture {}", texture, passes.last().unwrap()[0]);
+            passes.last_mut().unwrap().push(texture);
+            used.extend(texture_used);
+        } else {
+            passes.push(vec![texture]);
+            used = texture_used;
+        }
+    }
+    passes.push(vec![groups.len() - 1]);
+    passes
+}
+
+// Stores values in channels of single vec4, unused channels are zero.
+fn pack_values(code: &mut Vec<Op>, values: &[(Address, ValueType)], max_id: &mut Address) -> Address {
+    let mut channels = Vec::with_capacity(4);
+    for (value, typ) in values {
+        match typ {
+            ValueType::Float => channels.push(*value),
+            _ => {
+                for id in 0..components(*typ) {
+                    *max_id += 1;
+                    code.push((*max_id, Operation::ExtractComponent(*value, id)));
+                    channels.push(*max_id);
+                }
+            }
+        }
+    }
+    if channels.len() < 4 {
+        *max_id += 1;
+        code.push((*max_id, Operation::StoreFloat(0.0)));
+        channels.resize(4, *max_id);
+    }
+    *max_id += 1;
+    code.push((*max_id, Operation::ConstructVec4(channels[0], channels[1], channels[2], channels[3])));
+    *max_id
+}
+
+// Value stored in channels of vec4 starting at given offset.
+fn unpack(packed: Address, offset: usize, typ: ValueType, max_id: &mut Address) -> Vec<Op> {
+    let mut code: Vec<Op> = (0..components(typ))
+        .map(|id| {
+            *max_id += 1;
+            (*max_id, Operation::ExtractComponent(packed, offset + id))
+        })
+        .collect();
+    let value = match code.as_slice() {
+        [(x, _), (y, _)] => Some(Operation::ConstructVec2(*x, *y)),
+        [(x, _), (y, _), (z, _)] => Some(Operation::ConstructVec3(*x, *y, *z)),
+        _ => None,
+    };
+    if let Some(value) = value {
+        *max_id += 1;
+        code.push((*max_id, value));
+    }
+    code
+}
+
+/// Replaces values read from packed textures, directly or by shift, with their components.
+fn unpack_inputs(code: Vec<Op>, packed: &HashMap<Address, (usize, ValueType)>, max_id: &mut Address) -> Vec<Op> {
+    let mut unpacked: HashMap<Address, Vec<Op>> = HashMap::new();
+    for (address, op) in &code {
+        let texture = match op {
+            Operation::Shift(what, ..) => *what,
+            _ => *address,
+        };
+        if let Some((offset, typ)) = packed.get(&texture) {
+            unpacked.insert(*address, unpack(*address, *offset, *typ, max_id));
+        }
+    }
+    let renamed: HashMap<Address, Address> = unpacked.iter().map(|(x, ops)| (*x, ops.last().unwrap().0)).collect();
+
+    let mut result = Vec::with_capacity(code.len());
+    for op in code {
+        let mut op = op;
+        for operand in op.1.deps() {
+            // shift still samples the whole texture
+            let sampled = match op.1 {
+                Operation::Shift(what, ..) => what == operand,
+                _ => false,
+            };
+            match renamed.get(&operand) {
+                Some(new) if !sampled => replace(&mut op, operand, *new, false),
+                _ => (),
+            }
+        }
+        result.push(op);
+        if let Some(ops) = unpacked.remove(&op.0) {
+            result.extend(ops);
+        }
+    }
+    result
 }
 
 // Address of operation, address of synced value and index in operations vector, label at which split happened
@@ -280,12 +655,53 @@ fn find_syncs(program: &Vec<(Address, Operation)>) -> Vec<(Address, Address, usi
     program.iter().enumerate().filter_map(|(id, elem)| {
         match elem.1 {
             Operation::Label => {current_label = elem.0; None},
-            Operation::Sync(x) => Some((elem.0, x, id, current_label)),
-            _ => None
+            op => op.synced_value().map(|x| (elem.0, x, id, current_label)),
         }
     }).collect()
 }
 
+// Size of texture written by sync at given index, scaled sizes are computed by value_scales.
+fn texture_size(program: &[Op], sync_index: usize, scales: &HashMap<Address, f64>) -> Result<TextureSize, IrError> {
+    let (address, op) = program[sync_index];
+    match op {
+        Operation::Scale(..) => {}
+        _ => return Result::Ok(TextureSize::Original),
+    }
+    let factor = scales[&address];
+    if factor <= 0.0 || factor > 1.0 {
+        return Result::Err(IrError::ScaleOutOfRange);
+    }
+    Result::Ok(TextureSize::Scaled(factor as f32))
+}
+
+// Size of every value relative to output. Scale factor has to be known at compile time and is relative
+// to size of scaled value. Phi takes value defined later in loop, so code is walked until sizes settle,
+// loops containing scale are already unrolled.
+fn value_scales(program: &[Op]) -> Result<HashMap<Address, f64>, IrError> {
+    let mut scales: HashMap<Address, f64> = HashMap::new();
+    loop {
+        let mut changed = false;
+        for (address, op) in program {
+            let scale = match op {
+                Operation::Scale(what, factor) => {
+                    let factor = match program.iter().find(|x| x.0 == *factor).map(|x| x.1) {
+                        Some(Operation::StoreFloat(f)) => f,
+                        _ => return Result::Err(IrError::NonConstantScale),
+                    };
+                    Some(scales.get(what).cloned().unwrap_or(1.0) * factor)
+                }
+                op => op.operands_scale(|x| scales.get(&x).cloned()),
+            };
+            if let Some(scale) = scale {
+                changed |= scales.insert(*address, scale) != Some(scale);
+            }
+        }
+        if !changed {
+            return Result::Ok(scales);
+        }
+    }
+}
+
 fn prepare_dependencies(program: &Vec<(Address, Operation)>) -> HashMap<Address, Vec<Address>> {
     let mut usage = HashMap::new();
 
@@ -308,8 +724,11 @@ fn prepare_dependencies(program: &Vec<(Address, Operation)>) -> HashMap<Address,
             Sync(a) => {
                 // doing nothing as we have Sync nodes specified and will be building dependency trees starting from synced values
             },
+            Scale(..) => {
+                // same as Sync
+            },
             Store(a) => {usage.insert(ret_addr, vec![a]);},
-            Shift(a,b) => {usage.insert(ret_addr,vec![a,b]);},
+            Shift(a,b,_) => {usage.insert(ret_addr,vec![a,b]);},
             ExtractComponent(a,..) => {usage.insert(ret_addr,vec![a]);},
             StoreComponent(a, .., b) => {usage.insert(ret_addr,vec![a, b]);},
             ConstructVec2(a,b) => {usage.insert(ret_addr,vec![a,b]);},
@@ -325,6 +744,7 @@ fn prepare_dependencies(program: &Vec<(Address, Operation)>) -> HashMap<Address,
             Phi(PhiRecord{new, old, ..}) =>{usage.insert(ret_addr, vec![new, old]);},
 
             Exit(a, ..) => {usage.insert(ret_addr, vec![a]);},
+            Output(_, a) => {usage.insert(ret_addr, vec![a]);},
 
             Label => (),
             Arg(..) => (),
@@ -367,14 +787,14 @@ fn find_loops_dependencies(program: &Vec<(Address, Operation)>, deps: &mut HashM
                         phi_nodes.push((*phi_ret, *record));
                         result_code.pop();
                     }
-                    if let Some((ret, Operation::Sync(val))) = result_code.last() {
-                        loop_data.body.insert(0, (*ret, Operation::Sync(*val)));
+                    if let Some(&(ret, sync_op)) = result_code.last().filter(|x| x.1.synced_value().is_some()) {
+                        loop_data.body.insert(0, (ret, sync_op));
                         result_code.pop();
                     } else {
                         break;
                     }
                 }
-                println!("op before phi: {:?}", result_code.last());
+                log::debug!("op before phi: {:?}", result_code.last());
                 assert!(*result_code.last().unwrap() == (loop_data.entry_label, Operation::Label));
                 result_code.pop();
                 phi_nodes.iter().for_each(|elem| {
@@ -435,7 +855,7 @@ fn check_types(operations: &Vec<(Address, Operation)>, input_types: &Vec<(ValueT
     let mut types: HashMap<Address, ValueType> = HashMap::new();
 
     for (ret_addr, op) in operations {
-        println!("adding {} to type map", *ret_addr);
+        log::debug!("adding {} to type map", *ret_addr);
         use Operation::*;
         let typ = match op {
             Arg(num) => input_types[*num].0,
@@ -478,7 +898,9 @@ fn check_types(operations: &Vec<(Address, Operation)>, input_types: &Vec<(ValueT
             Neg(val) => types[&val],
             Label => continue,
             Exit(val,..) => types[&val],
+            Output(_, val) => types[&val],
             Sync(a) => types[&a],
+            Scale(a, ..) => types[&a],
             Shift(a, ..) => types[&a],
             Phi(record) => types[&record.old],
             Jump(..) => continue,
@@ -486,7 +908,6 @@ fn check_types(operations: &Vec<(Address, Operation)>, input_types: &Vec<(ValueT
             LoopMerge(..) => continue,
             Invoke(func) => {
                 let deps = func.deps();
-                //println!("checking deps: {:?}", deps);
                 types[&deps[0]]
             }
 
@@ -497,3 +918,187 @@ fn check_types(operations: &Vec<(Address, Operation)>, input_types: &Vec<(ValueT
 
     types
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::interpreter::{as_components, Image};
+    use super::super::ir::ConstantValue;
+    use super::super::test_utils::{assert_close, compile, gradient, run, split_keeping_results, BLUR, PACKED, RESULTS};
+
+    // squared color is stored at half resolution
+    const SCALED: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        with {
+            size: vec2,
+        }
+        {
+            let small = scale(color * color, 0.5);
+            return small + color;
+        }
+    ";
+
+    const NESTED_SCALES: &str = "
+        pipeline main(color: vec4) -> (vec4) {
+            let half = scale(color * color, 0.5);
+            let quarter = scale(half + half, 0.5);
+            return quarter + color;
+        }
+    ";
+
+    const KEPT_SCALE: &str = "
+        pipeline main(color: vec4) -> (vec4) {
+            let half = scale(color * color, 0.5);
+            let same = scale(half * 3.0, 1.0);
+            return same + color;
+        }
+    ";
+
+    // every value is read only by the next pass
+    const CHAIN: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        with {
+            size: vec2,
+        }
+        {
+            let a = color * color;
+            let b = shift(a, vec2(-1.0, 0.0) / size) * shift(a, vec2(1.0, 0.0) / size);
+            let c = shift(b, vec2(0.0, -1.0) / size) * shift(b, vec2(0.0, 1.0) / size);
+            return shift(c, vec2(-1.0, 0.0) / size) * shift(c, vec2(0.0, 1.0) / size);
+        }
+    ";
+
+    // both synced values are computed from normalized color
+    const SHARED: &str = "
+        pipeline main(color: vec4) -> (vec4)
+        with {
+            size: vec2,
+        }
+        {
+            let n = normalize(color.xyz);
+            let a = vec4(n.x * n.y, n.y * n.z, n.z, 1.0);
+            let b = vec4(n.x, n.y * n.y, n.z * n.x, 0.0);
+            let dx = vec2(1.0, 0.0) / size;
+            return shift(a, dx) + shift(b, -dx);
+        }
+    ";
+
+    #[test]
+    fn results_go_to_their_attachments() {
+        let pipeline = split_keeping_results(RESULTS);
+        assert_eq!(pipeline.passes.len(), 2);
+        match &pipeline.passes[1].output {
+            OutputTexture::Result(results) => assert_eq!(results, &vec![0, 1, 2]),
+            OutputTexture::Generated(_) => panic!("last pass doesn't write results"),
+        }
+
+        let input = gradient(5, 3);
+        let mut lengths = Image::new(5, 3, ConstantValue::Float(0.0));
+        let mut doubled = Image::new(5, 3, ConstantValue::Vec2([0.0; 2]));
+        for y in 0..3 {
+            for x in 0..5 {
+                let color = as_components(input.get(x, y));
+                let length = color.iter().map(|v| v * v).sum::<f64>().sqrt();
+                lengths.set(x, y, ConstantValue::Float(length));
+                doubled.set(x, y, ConstantValue::Vec2([color[0] * 2.0, color[1] * 2.0]));
+            }
+        }
+        let results = run(&compile(RESULTS));
+        assert_eq!(results.len(), 3);
+        assert_close(&results[1], &lengths);
+        assert_close(&results[2], &doubled);
+    }
+
+    #[test]
+    fn fused_syncs_need_no_pass() {
+        // shifted swizzle of input is sampled from input directly
+        let pipeline = split_keeping_results(BLUR);
+        assert_eq!(pipeline.shaders.len(), 1);
+    }
+
+    #[test]
+    fn textures_are_shared() {
+        let pipeline = split_keeping_results(CHAIN);
+        assert_eq!(pipeline.passes.len(), 4);
+        assert_eq!(pipeline.textures.len(), 2);
+        assert_eq!(pipeline.texture_slots, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn syncs_are_packed() {
+        let pipeline = split_keeping_results(PACKED);
+        assert_eq!(pipeline.passes.len(), 2);
+        assert_eq!(pipeline.textures.len(), 1);
+        assert_eq!(pipeline.textures[0].0, ValueType::Vec4);
+    }
+
+    #[test]
+    fn shared_work_is_done_once() {
+        let pipeline = split_keeping_results(SHARED);
+        assert_eq!(pipeline.passes.len(), 2);
+        match &pipeline.passes[0].output {
+            OutputTexture::Generated(textures) => assert_eq!(textures, &vec![0, 1]),
+            OutputTexture::Result(_) => panic!("first pass writes results"),
+        }
+    }
+
+    #[test]
+    fn scaled_texture_has_reduced_size() {
+        let pipeline = split_keeping_results(SCALED);
+        assert_eq!(pipeline.passes.len(), 2);
+        assert_eq!(pipeline.textures, vec![(ValueType::Vec4, TextureSize::Scaled(0.5))]);
+    }
+
+    #[test]
+    fn scale_by_uniform_is_rejected() {
+        use Operation::*;
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, Uniform(0)),
+            (4, Scale(2, 3)),
+            (5, Exit(4, 1)),
+        ];
+        let mut program = PipelineIR::new(code);
+        program.inputs = vec![(ValueType::Vec4, "color".to_owned())];
+        program.outputs = vec![ValueType::Vec4];
+        program.uniforms = vec![(ValueType::Float, "factor".to_owned())];
+        match split(program) {
+            Result::Err(IrError::NonConstantScale) => {}
+            x => panic!("expected error, got {:?}", x.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn nested_scales_are_composed() {
+        let pipeline = split_keeping_results(NESTED_SCALES);
+        let sizes: Vec<_> = pipeline.textures.iter().map(|x| x.1).collect();
+        assert!(sizes.contains(&TextureSize::Scaled(0.5)));
+        assert!(sizes.contains(&TextureSize::Scaled(0.25)));
+    }
+
+    #[test]
+    fn unit_scale_keeps_size_of_value() {
+        let pipeline = split_keeping_results(KEPT_SCALE);
+        assert!(pipeline.textures.iter().all(|x| x.1 == TextureSize::Scaled(0.5)));
+    }
+
+    #[test]
+    fn scale_out_of_range_is_rejected() {
+        use Operation::*;
+        let code = vec![
+            (1, Label),
+            (2, Arg(0)),
+            (3, StoreFloat(1.5)),
+            (4, Scale(2, 3)),
+            (5, Exit(4, 1)),
+        ];
+        let mut program = PipelineIR::new(code);
+        program.inputs = vec![(ValueType::Vec4, "color".to_owned())];
+        program.outputs = vec![ValueType::Vec4];
+        match split(program) {
+            Result::Err(IrError::ScaleOutOfRange) => {}
+            x => panic!("expected error, got {:?}", x.map(|_| ())),
+        }
+    }
+}
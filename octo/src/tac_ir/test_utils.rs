@@ -0,0 +1,246 @@
+use super::interpreter::{as_components, interpret, interpret_passes, Image};
+use super::ir::{ConstantValue, Operation, PhiRecord, PipelineIR, StdFunction, ValueType};
+use super::split_passes::{split, PipelineDef, ShaderDef};
+use crate::static_analysis::analyze;
+use crate::tac_ir;
+
+pub fn compile(src: &str) -> PipelineIR {
+    let program = match parser::parse(src, false) {
+        Result::Ok(x) => x,
+        Result::Err(_) => panic!("parsing failed"),
+    };
+    match analyze(program).0 {
+        Some(x) => tac_ir::emit_ir(x),
+        None => panic!("static analysis failed"),
+    }
+}
+
+// passes run by compiler before splitting
+pub fn optimize(program: PipelineIR) -> PipelineIR {
+    let program = tac_ir::propagate_constants(program);
+    let program = tac_ir::eliminate_common_subexpressions(program);
+    let program = tac_ir::remove_unused_operations(program);
+    let program = tac_ir::move_sync_operations(program);
+    let program = match tac_ir::unroll_synced_loop(program) {
+        Result::Ok(x) => x,
+        Result::Err(err) => panic!("unrolling failed: {}", err),
+    };
+    let program = tac_ir::fuse_syncs(program);
+    let program = tac_ir::remove_unused_operations(program);
+    tac_ir::simplify_cfg(program)
+}
+
+pub fn gradient(width: usize, height: usize) -> Image {
+    let mut image = Image::new(width, height, ConstantValue::Vec4([0.0; 4]));
+    for y in 0..height {
+        for x in 0..width {
+            image.set(x, y, ConstantValue::Vec4([x as f64, y as f64, 0.5, 1.0]));
+        }
+    }
+    image
+}
+
+pub fn assert_close(a: &Image, b: &Image) {
+    assert_eq!(a.pixels.len(), b.pixels.len());
+    for (x, y) in a.pixels.iter().zip(b.pixels.iter()) {
+        let close = as_components(*x)
+            .iter()
+            .zip(as_components(*y).iter())
+            .all(|(x, y)| (x - y).abs() < 1e-9);
+        assert!(close, "{:?} != {:?}", x, y);
+    }
+}
+
+// pipelines are run on gradient of 5x3 pixels, with its size as the only uniform
+const WIDTH: usize = 5;
+const HEIGHT: usize = 3;
+
+/// Blurs color horizontally and sums length of the result in loop.
+pub const BLUR: &str = "
+    pipeline main(color: vec4) -> (vec4)
+    with {
+        size: vec2,
+    }
+    {
+        let c = color.xyz;
+        let blurred = (shift(c, vec2(-1.0, 0.0) / size) + c + shift(c, vec2(1.0, 0.0) / size)) / 3.0;
+        let total = 0.0;
+        for (let i = 0; i < 3; i = i + 1) {
+            total = total + length(blurred);
+        }
+        return vec4(blurred.x, blurred.y, total, 1.0);
+    }
+";
+
+/// Shifts input by two pixels, `MODE` is replaced by name of edge mode.
+pub const EDGE: &str = "
+    pipeline main(color: vec4) -> (vec4)
+    with {
+        size: vec2,
+    }
+    {
+        return shift(color, vec2(2.0, 0.0) / size, MODE);
+    }
+";
+
+/// Three independent values fit into channels of one texture.
+pub const PACKED: &str = "
+    pipeline main(color: vec4) -> (vec4)
+    with {
+        size: vec2,
+    }
+    {
+        let l = length(color);
+        let m = color.x * color.y;
+        let uv = color.xy * color.zw;
+        let dx = vec2(1.0, 0.0) / size;
+        let s = shift(uv, dx);
+        return vec4(shift(l, dx), shift(m, -dx), s.x, s.y);
+    }
+";
+
+/// Results of different types, only the first one is synced.
+pub const RESULTS: &str = "
+    pipeline main(color: vec4) -> (vec4, float, vec2)
+    with {
+        size: vec2,
+    }
+    {
+        let blurred = shift(color * color, vec2(1.0, 0.0) / size) + color;
+        return (blurred, length(color), color.xy * 2.0);
+    }
+";
+
+/// Value assigned in both branches of if-else.
+pub const BRANCHES: &str = "
+    pipeline main(color: vec4) -> (vec4)
+    with {
+        size: vec2,
+    }
+    {
+        let c = color;
+        if (color.x < 2.0) {
+            c = color * 2.0;
+        } else {
+            c = color * 0.5;
+        }
+        return c;
+    }
+";
+
+/// Constants shifted with and without edge mode, zero mode masks them outside of texture.
+pub const SHIFTED_CONSTANTS: &str = "
+    pipeline main(color: vec4) -> (vec4)
+    with {
+        size: vec2,
+    }
+    {
+        let dx = vec2(1.0, 0.0) / size;
+        return color * shift(0.5, dx, zero) + color * shift(0.25, dx);
+    }
+";
+
+/// Matrix uniform scaled by float and applied to vector, interpreter doesn't support matrices.
+/// Matrix takes three registers of 16 bytes and vectors following it don't share them.
+pub const MATRICES: &str = "
+    pipeline main(color: vec4) -> (vec4)
+    with {
+        gain: float,
+        transform: mat3,
+        offset: vec3,
+        tint: vec3,
+    }
+    {
+        let scaled = transform * gain;
+        let c = scaled * color.xyz + offset * tint;
+        return vec4(c.x, c.y, c.z, 1.0);
+    }
+";
+
+/// Results of pipeline run on gradient.
+pub fn run(program: &PipelineIR) -> Vec<Image> {
+    let uniforms = [ConstantValue::Vec2([WIDTH as f64, HEIGHT as f64])];
+    interpret(program, &[gradient(WIDTH, HEIGHT)], &uniforms, WIDTH, HEIGHT)
+}
+
+/// Results of split passes run on gradient.
+pub fn run_passes(pipeline: &PipelineDef) -> Vec<Image> {
+    let uniforms = [ConstantValue::Vec2([WIDTH as f64, HEIGHT as f64])];
+    interpret_passes(pipeline, &[gradient(WIDTH, HEIGHT)], &uniforms, WIDTH, HEIGHT)
+}
+
+pub fn assert_same_results(expected: &[Image], results: &[Image]) {
+    assert_eq!(expected.len(), results.len());
+    for (expected, result) in expected.iter().zip(results.iter()) {
+        assert_close(expected, result);
+    }
+}
+
+/// Splits optimized pipeline into passes and checks that they give the same results as
+/// not optimized pipeline.
+pub fn split_keeping_results(src: &str) -> PipelineDef {
+    let expected = run(&compile(src));
+    let pipeline = passes(src);
+    assert_same_results(&expected, &run_passes(&pipeline));
+    pipeline
+}
+
+/// Passes of optimized pipeline, for pipelines which can't be run by interpreter.
+pub fn passes(src: &str) -> PipelineDef {
+    match split(optimize(compile(src))) {
+        Result::Ok(x) => x,
+        Result::Err(err) => panic!("splitting failed: {}", err),
+    }
+}
+
+/// Pass summing length of its input three times in loop, every time multiplied by uniform.
+/// Returns shader together with uniforms of pipeline.
+pub fn loop_shader() -> (ShaderDef, Vec<(ValueType, String)>) {
+    use Operation::*;
+    let i = PhiRecord {
+        new: 23,
+        label: 22,
+        old: 4,
+        old_label: 1,
+    };
+    let total = PhiRecord {
+        new: 20,
+        label: 22,
+        old: 7,
+        old_label: 1,
+    };
+    let code = vec![
+        (1, Label),
+        (2, Arg(0)),
+        (3, Uniform(0)),
+        (4, StoreInt(0)),
+        (5, StoreInt(3)),
+        (6, StoreInt(1)),
+        (7, StoreFloat(0.0)),
+        (8, Jump(9)),
+        (9, Label),
+        (10, Phi(i)),
+        (11, Phi(total)),
+        (12, LoopMerge(22, 25)),
+        (13, Jump(14)),
+        (14, Label),
+        (15, Less(10, 5)),
+        (16, JumpIfElse(15, 17, 25)),
+        (17, Label),
+        (18, Invoke(StdFunction::Length(2))),
+        (19, Mul(18, 3)),
+        (20, Add(11, 19)),
+        (21, Jump(22)),
+        (22, Label),
+        (23, Add(10, 6)),
+        (24, Jump(9)),
+        (25, Label),
+        (26, Exit(11, 25)),
+    ];
+    let shader = ShaderDef {
+        input_type: vec![ValueType::Vec4],
+        output_type: vec![ValueType::Float],
+        code,
+    };
+    (shader, vec![(ValueType::Float, "gain".to_owned())])
+}
@@ -0,0 +1,47 @@
+use std::collections::HashMap;
+
+use super::super::ir::{Address, Op, Operation};
+
+/// Label of block containing every operation, code before first label belongs to block 0.
+pub fn find_blocks(code: &[Op]) -> Vec<Address> {
+    let mut current = 0;
+    code.iter()
+        .map(|(address, op)| {
+            if let Operation::Label = op {
+                current = *address;
+            }
+            current
+        })
+        .collect()
+}
+
+/// Blocks reached from end of every block, by jump or by falling through to next label.
+pub fn successors(code: &[Op], blocks: &[Address]) -> HashMap<Address, Vec<Address>> {
+    let mut successors: HashMap<Address, Vec<Address>> = HashMap::new();
+    for (id, (address, op)) in code.iter().enumerate() {
+        match *op {
+            Operation::Jump(target) => successors.entry(blocks[id]).or_insert_with(Vec::new).push(target),
+            Operation::JumpIfElse(_, first, second) => {
+                let entry = successors.entry(blocks[id]).or_insert_with(Vec::new);
+                entry.push(first);
+                entry.push(second);
+            }
+            // block without jump at its end falls through
+            Operation::Label if id > 0 => match code[id - 1].1 {
+                Operation::Jump(..) | Operation::JumpIfElse(..) | Operation::Exit(..) => {}
+                _ => successors.entry(blocks[id - 1]).or_insert_with(Vec::new).push(*address),
+            },
+            _ => {}
+        }
+    }
+    successors
+}
+
+/// Final value of replaced address, replacement may be replaced too.
+pub fn resolve_replacement(replacements: &HashMap<Address, Address>, address: Address) -> Address {
+    let mut address = address;
+    while let Some(next) = replacements.get(&address) {
+        address = *next;
+    }
+    address
+}
@@ -55,13 +55,13 @@ pub fn find_if_else<'b, I: std::iter::Iterator<Item = &'b Op>>(
     let end_label = match true_code[true_code.len() - 1].1 {
         Operation::Jump(lab) => lab,
         x => {
-            println!("error at {:#?}", x);
+            log::debug!("error at {:#?}", x);
             panic!();
         }
     };
     let if_jump_end_label = true_code[true_code.len()-1].0;
     true_code.pop();
-    println!(
+    log::debug!(
         "labels: start: {}, else: {}, end: {}",
         if_label, else_label, end_label
     );
@@ -0,0 +1,33 @@
+//! Temporary files written by tests.
+use std::path::PathBuf;
+
+/// Directory of files, removed when dropped.
+pub struct TempFiles {
+    pub dir: PathBuf,
+}
+
+impl TempFiles {
+    /// Name has to be unique among tests, they are run in parallel.
+    pub fn new(name: &str, files: &[(&str, &str)]) -> TempFiles {
+        let dir = std::env::temp_dir().join(format!("octo-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (path, content) in files {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        TempFiles {
+            dir: dir.canonicalize().unwrap(),
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl Drop for TempFiles {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
@@ -28,8 +28,12 @@ pub enum ValueType {
 
 #[derive(Serialize, Deserialize, PartialEq)]
 pub enum OutputType {
+    // the only pipeline result
     Result,
     Textures(Vec<TextureId>),
+    // ids of pipeline results, n-th result is written to n-th color attachment
+    // new variants go last, so modules written before them keep deserializing
+    Results(Vec<usize>),
 }
 
 #[derive(Serialize, Deserialize, PartialEq)]
@@ -54,18 +58,39 @@ pub struct ShaderPass {
     pub dependencies: Option<Vec<PassId>>,
 }
 
+/// Resources used by WGSL shader, as (bind group, binding).
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct WgslBindings {
+    pub sampler: Option<(u32, u32)>,
+    // n-th input texture of pass
+    pub textures: Vec<(u32, u32)>,
+    pub uniforms: Option<(u32, u32)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WgslShader {
+    pub source: String,
+    pub bindings: WgslBindings,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct OctoModule {
     pub name: String,
     pub version: u32,
     pub basic_vertex_spirv: Vec<u32>,
     pub fragment_shaders: HashMap<ShaderId, Vec<u32>>,
+    // empty unless module was compiled with WGSL backend
+    #[serde(default)]
+    pub wgsl_shaders: HashMap<ShaderId, WgslShader>,
     pub passes: Vec<ShaderPass>,
 
     pub uniform_block: Vec<(String, ValueType)>,
     pub uniform_block_size: usize,
     pub required_input: Vec<(String, ValueType)>,
     pub textures: Vec<(TextureId, TextureType, TextureSize)>,
+    // texture holding n-th intermediate value, values with disjoint lifetimes share textures
+    #[serde(default)]
+    pub texture_slots: Vec<TextureId>,
 }
 
 impl OctoModule {
@@ -74,12 +99,14 @@ impl OctoModule {
             name: "test_module".to_owned(),
             version: 0u32,
             fragment_shaders: HashMap::new(),
+            wgsl_shaders: HashMap::new(),
             basic_vertex_spirv: vec![],
             passes: vec![],
             uniform_block: vec![],
             uniform_block_size: 0,
             required_input: vec![],
             textures: vec![],
+            texture_slots: vec![],
         }
     }
 }
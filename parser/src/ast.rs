@@ -96,11 +96,54 @@ impl std::fmt::Display for Type {
     }
 }
 
-//#[derive(Debug)]
-//pub enum ProgramItem {
-//    Function(Box<Function>),
-//    GpuFunction(Box<GpuFunction>),
-//}
+#[derive(Debug)]
+pub enum ProgramItem {
+    Import(Spanned<String>),
+    Constant(Constant),
+    GpuFunction(GpuFunction),
+}
+
+#[derive(Debug)]
+pub struct Program {
+    pub imports: Vec<Spanned<String>>,
+    pub constants: Vec<Constant>,
+    pub functions: Vec<GpuFunction>,
+    // files that are only imported don't need pipeline
+    pub pipeline: Option<Pipeline>,
+}
+
+impl Program {
+    pub fn new(items: Vec<ProgramItem>, pipeline: Option<Pipeline>) -> Program {
+        let mut program = Program {
+            imports: vec![],
+            constants: vec![],
+            functions: vec![],
+            pipeline,
+        };
+        for item in items {
+            match item {
+                ProgramItem::Import(path) => program.imports.push(path),
+                ProgramItem::Constant(constant) => program.constants.push(constant),
+                ProgramItem::GpuFunction(function) => program.functions.push(function),
+            }
+        }
+        program
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Constant {
+    pub name: Spanned<String>,
+    pub value: Box<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GpuFunction {
+    pub name: Spanned<String>,
+    pub arguments: Vec<Variable>,
+    pub result: Spanned<Type>,
+    pub block: Block,
+}
 
 #[derive(Debug)]
 pub struct Pipeline {
@@ -116,18 +159,20 @@ pub struct UniformBlock {
     pub entries: Vec<Variable>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Block {
     pub statements: Vec<Statement>,
+    // from opening to closing brace
+    pub span: AstSpan,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ValueStorage {
     Existing(Vec<Spanned<String>>),
     Creation(Spanned<String>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     Expression(Box<Expression>),
     Assignment(ValueStorage, Box<Expression>),
@@ -156,11 +201,14 @@ pub fn extract_access_chain(exp: Box<Expression>)-> Result<Vec<Spanned<String>>,
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Expression {
     Variable(Variable),
     Literal(Literal),
+    // unary minus
     Negation(Box<Expression>),
+    // logical not, written with "!"
+    Not(Box<Expression>),
     Mul(Box<Expression>, Box<Expression>),
     Div(Box<Expression>, Box<Expression>),
     Add(Box<Expression>, Box<Expression>),
@@ -173,10 +221,13 @@ pub enum Expression {
     NotEquals(Box<Expression>, Box<Expression>),
     And(Box<Expression>, Box<Expression>),
     Or(Box<Expression>, Box<Expression>),
-    Shift(Box<Expression>, Box<Expression>),
+    // shifted value, offset, name of edge mode
+    Shift(Box<Expression>, Box<Expression>, Option<Spanned<String>>),
     Scale(Box<Expression>, Box<Expression>),
     Invocation(Spanned<String>, Vec<Box<Expression>>),
     Access(Box<Expression>, Spanned<String>),
+    // only valid as returned value of pipeline with many results
+    Tuple(Vec<Box<Expression>>),
 }
 
 fn concat_spans(span1: Span<ByteIndex>, span2: Span<ByteIndex>) -> Span<ByteIndex> {
@@ -189,7 +240,7 @@ impl Expression {
         match self {
             Expression::Variable(var) => var.identifier.span,
             Expression::Literal(lit) => lit.span(),
-            Negation(left) => left.span(),
+            Negation(left) | Not(left) => left.span(),
             Mul(left, right) => concat_spans(left.span(), right.span()),
             Div(left, right) => concat_spans(left.span(), right.span()),
             Add(left, right) => concat_spans(left.span(), right.span()),
@@ -203,14 +254,41 @@ impl Expression {
             And(left, right) => concat_spans(left.span(), right.span()),
             Or(left, right) => concat_spans(left.span(), right.span()),
             // TODO: fix shift and scale. These are not including parentheses and Shift/Scale keyword
-            Shift(left, right) => concat_spans(left.span(), right.span()),
+            Shift(left, right, _) => concat_spans(left.span(), right.span()),
             Scale(left, right) => concat_spans(left.span(), right.span()),
-            _ => span(0, 1),
+            Tuple(values) => concat_spans(values[0].span(), values[values.len() - 1].span()),
+            Invocation(name, args) => match args.last() {
+                Some(last) => concat_spans(name.span, last.span()),
+                None => name.span,
+            },
+            Access(value, field) => concat_spans(value.span(), field.span),
         }
     }
 }
 
-#[derive(Debug)]
+/// Value of shift sampling outside of texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeMode {
+    // value at the nearest edge
+    Clamp,
+    Mirror,
+    Repeat,
+    Zero,
+}
+
+impl EdgeMode {
+    pub fn from_name(name: &str) -> Option<EdgeMode> {
+        match name {
+            "clamp" => Some(EdgeMode::Clamp),
+            "mirror" => Some(EdgeMode::Mirror),
+            "repeat" => Some(EdgeMode::Repeat),
+            "zero" => Some(EdgeMode::Zero),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Literal {
     Int(Spanned<i64>),
     Float(Spanned<f64>),
@@ -0,0 +1,469 @@
+use crate::ast::*;
+use crate::lexer::{self, Comment};
+use crate::{parse, FailedParsing};
+
+const INDENT: &str = "    ";
+
+/// Formats source in canonical style, comments are kept.
+pub fn format(src: &str) -> Result<String, FailedParsing> {
+    let program = parse(src, false)?;
+    // lexical errors would already fail parsing
+    let comments = lexer::comments(src).unwrap_or_default();
+    let mut printer = Printer {
+        src,
+        comments,
+        next_comment: 0,
+        lines: vec![],
+        indent: 0,
+    };
+    printer.program(&program);
+    Result::Ok(printer.finish())
+}
+
+enum Item<'a> {
+    Import(&'a Spanned<String>),
+    Constant(&'a Constant),
+    Function(&'a GpuFunction),
+    Pipeline(&'a Pipeline),
+}
+
+impl<'a> Item<'a> {
+    fn start(&self) -> usize {
+        match self {
+            Item::Import(x) => position(x.span.start()),
+            Item::Constant(x) => position(x.name.span.start()),
+            Item::Function(x) => position(x.name.span.start()),
+            Item::Pipeline(x) => position(x.name.span.start()),
+        }
+    }
+
+    // functions and pipeline are always separated with empty line
+    fn is_block(&self) -> bool {
+        match self {
+            Item::Function(_) | Item::Pipeline(_) => true,
+            _ => false,
+        }
+    }
+}
+
+// source is parsed without offset, so indices are shifted by one
+fn position(index: ByteIndex) -> usize {
+    index.0 as usize - 1
+}
+
+struct Printer<'a> {
+    src: &'a str,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    lines: Vec<String>,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn finish(mut self) -> String {
+        self.comments_before(self.src.len());
+        while self.lines.last().map_or(false, |x| x.is_empty()) {
+            self.lines.pop();
+        }
+        let mut result = self.lines.join("\n");
+        result.push('\n');
+        result
+    }
+
+    fn line(&mut self, text: &str) {
+        self.lines.push(format!("{}{}", INDENT.repeat(self.indent), text));
+    }
+
+    // no empty lines at file start, after opening brace or repeated
+    fn blank(&mut self) {
+        match self.lines.last() {
+            None => {}
+            Some(last) if last.is_empty() || last.ends_with('{') => {}
+            Some(_) => self.lines.push(String::new()),
+        }
+    }
+
+    // there is empty line between line of position and previous text
+    fn blank_before(&self, position: usize) -> bool {
+        let line_start = self.src[..position].rfind('\n').map_or(0, |x| x + 1);
+        // another statement starts earlier in this line
+        if self.src[line_start..position].contains(|x| x == ';' || x == '}') {
+            return false;
+        }
+        let before = &self.src[..line_start];
+        let code_end = before.trim_end().len();
+        code_end > 0 && before[code_end..].matches('\n').count() >= 2
+    }
+
+    // there is code before position in the same line
+    fn is_trailing(&self, position: usize) -> bool {
+        let line_start = self.src[..position].rfind('\n').map_or(0, |x| x + 1);
+        !self.src[line_start..position].trim().is_empty() && !self.lines.is_empty()
+    }
+
+    // comments from the end of previously printed line
+    fn trailing_comments(&mut self, position: usize) {
+        while self.next_comment < self.comments.len() {
+            let comment = &self.comments[self.next_comment];
+            if comment.start >= position || !self.is_trailing(comment.start) {
+                break;
+            }
+            let text = comment.text.trim_end().to_owned();
+            if let Some(last) = self.lines.last_mut() {
+                last.push(' ');
+                last.push_str(&text);
+            }
+            self.next_comment += 1;
+        }
+    }
+
+    fn comments_before(&mut self, position: usize) {
+        self.trailing_comments(position);
+        while self.next_comment < self.comments.len() && self.comments[self.next_comment].start < position {
+            if self.is_trailing(self.comments[self.next_comment].start) {
+                self.trailing_comments(position);
+                continue;
+            }
+            let comment = self.comments[self.next_comment].clone();
+            self.next_comment += 1;
+            if self.blank_before(comment.start) {
+                self.blank();
+            }
+            // lines of block comment are kept as they are
+            for (id, text) in comment.text.trim_end().lines().enumerate() {
+                if id == 0 {
+                    self.line(text);
+                } else {
+                    self.lines.push(text.to_owned());
+                }
+            }
+        }
+    }
+
+    fn program(&mut self, program: &Program) {
+        let mut items: Vec<Item> = program.imports.iter().map(Item::Import).collect();
+        items.extend(program.constants.iter().map(Item::Constant));
+        items.extend(program.functions.iter().map(Item::Function));
+        items.extend(program.pipeline.iter().map(Item::Pipeline));
+        // program keeps items grouped by kind, source order is restored
+        items.sort_by_key(|x| x.start());
+
+        let mut previous_block = false;
+        for item in &items {
+            let start = item.start();
+            self.trailing_comments(start);
+            if previous_block || item.is_block() {
+                self.blank();
+            }
+            self.comments_before(start);
+            if self.blank_before(start) {
+                self.blank();
+            }
+            match item {
+                Item::Import(path) => self.line(&format!("import \"{}\";", path.val)),
+                Item::Constant(constant) => {
+                    let value = self.expression(&constant.value, 0);
+                    self.line(&format!("let {} = {};", constant.name.val, value));
+                }
+                Item::Function(function) => self.function(function),
+                Item::Pipeline(pipeline) => self.pipeline(pipeline),
+            }
+            previous_block = item.is_block();
+        }
+    }
+
+    fn function(&mut self, function: &GpuFunction) {
+        let header = format!(
+            "gpu_fun {}({}) -> {} {{",
+            function.name.val,
+            self.definitions(&function.arguments).join(", "),
+            self.type_text(&function.result)
+        );
+        self.line(&header);
+        self.block_body(&function.block);
+        self.line("}");
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline) {
+        let results: Vec<_> = pipeline.results.iter().map(|x| self.type_text(x)).collect();
+        let header = format!(
+            "pipeline {}({}) -> ({})",
+            pipeline.name.val,
+            self.definitions(&pipeline.arguments).join(", "),
+            results.join(", ")
+        );
+        match &pipeline.uniforms {
+            None => self.line(&format!("{} {{", header)),
+            Some(uniforms) => {
+                self.line(&header);
+                self.line("with {");
+                self.indent += 1;
+                for entry in self.definitions(&uniforms.entries) {
+                    self.line(&format!("{},", entry));
+                }
+                self.indent -= 1;
+                self.line("}");
+                self.line("{");
+            }
+        }
+        self.block_body(&pipeline.block);
+        self.line("}");
+    }
+
+    fn block_body(&mut self, block: &Block) {
+        self.indent += 1;
+        for statement in &block.statements {
+            self.statement(statement);
+        }
+        // comments before closing brace
+        self.comments_before(position(block.span.end()) - 1);
+        self.indent -= 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let start = statement_start(statement);
+        self.comments_before(start);
+        if self.blank_before(start) {
+            self.blank();
+        }
+        match statement {
+            Statement::For(init, condition, step, block) => {
+                let header = format!(
+                    "for ({}; {}; {}) {{",
+                    self.simple_statement(init),
+                    self.expression(condition, 0),
+                    self.simple_statement(step)
+                );
+                self.line(&header);
+                self.block_body(block);
+                self.line("}");
+            }
+            Statement::IfElse(condition, block, else_block) => {
+                let header = format!("if ({}) {{", self.expression(condition, 0));
+                self.line(&header);
+                self.block_body(block);
+                if let Some(else_block) = else_block {
+                    self.line("} else {");
+                    self.block_body(else_block);
+                }
+                self.line("}");
+            }
+            x => {
+                let text = format!("{};", self.simple_statement(x));
+                self.line(&text);
+            }
+        }
+    }
+
+    fn simple_statement(&self, statement: &Statement) -> String {
+        match statement {
+            Statement::Assignment(ValueStorage::Creation(name), value) => {
+                format!("let {} = {}", name.val, self.expression(value, 0))
+            }
+            Statement::Assignment(ValueStorage::Existing(path), value) => {
+                let path: Vec<_> = path.iter().map(|x| x.val.as_str()).collect();
+                format!("{} = {}", path.join("."), self.expression(value, 0))
+            }
+            Statement::Expression(value) => self.expression(value, 0),
+            Statement::Return(value) => format!("return {}", self.expression(value, 0)),
+            _ => panic!("internal formatter error: block statement inside for header"),
+        }
+    }
+
+    // type names are taken from source, unknown ones would be lost otherwise
+    fn definitions(&self, variables: &[Variable]) -> Vec<String> {
+        variables
+            .iter()
+            .map(|x| {
+                let rest = &self.src[position(x.identifier.span.end())..];
+                let rest = rest.trim_start().trim_start_matches(':').trim_start();
+                let typ: String = rest.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect();
+                format!("{}: {}", x.identifier.val, typ)
+            })
+            .collect()
+    }
+
+    fn type_text(&self, typ: &Spanned<Type>) -> String {
+        self.src[position(typ.span.start())..position(typ.span.end())].to_owned()
+    }
+
+    /// Expression text, parenthesized if its precedence is lower than `min_level`.
+    fn expression(&self, expression: &Expression, min_level: u8) -> String {
+        use Expression::*;
+        let text = match expression {
+            Or(left, right) => self.binary("or", left, right, 2, 2),
+            And(left, right) => self.binary("and", left, right, 2, 2),
+            Equals(left, right) => self.binary("==", left, right, 3, 3),
+            NotEquals(left, right) => self.binary("!=", left, right, 3, 3),
+            Less(left, right) => self.binary("<", left, right, 4, 4),
+            LessEqual(left, right) => self.binary("<=", left, right, 4, 4),
+            More(left, right) => self.binary(">", left, right, 4, 4),
+            MoreEqual(left, right) => self.binary(">=", left, right, 4, 4),
+            Add(left, right) => self.binary("+", left, right, 4, 5),
+            Sub(left, right) => self.binary("-", left, right, 4, 5),
+            Mul(left, right) => self.binary("*", left, right, 5, 6),
+            Div(left, right) => self.binary("/", left, right, 5, 6),
+            Negation(value) => format!("-{}", self.expression(value, 7)),
+            Not(value) => format!("!{}", self.expression(value, 7)),
+            // literals are copied, so 1.50 stays 1.50
+            Expression::Literal(literal) => {
+                let span = literal.span();
+                self.src[position(span.start())..position(span.end())].to_owned()
+            }
+            Expression::Variable(variable) => variable.identifier.val.clone(),
+            Invocation(name, args) => format!("{}({})", name.val, self.arguments(args)),
+            Shift(left, right, None) => format!("shift({}, {})", self.expression(left, 0), self.expression(right, 0)),
+            Shift(left, right, Some(mode)) => {
+                format!("shift({}, {}, {})", self.expression(left, 0), self.expression(right, 0), mode.val)
+            }
+            Scale(left, right) => format!("scale({}, {})", self.expression(left, 0), self.expression(right, 0)),
+            Access(value, field) => format!("{}.{}", self.expression(value, 7), field.val),
+            Tuple(values) => format!("({})", self.arguments(values)),
+        };
+        if level(expression) < min_level {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn binary(&self, operator: &str, left: &Expression, right: &Expression, left_level: u8, right_level: u8) -> String {
+        format!(
+            "{} {} {}",
+            self.expression(left, left_level),
+            operator,
+            self.expression(right, right_level)
+        )
+    }
+
+    fn arguments(&self, values: &[Box<Expression>]) -> String {
+        let values: Vec<_> = values.iter().map(|x| self.expression(x, 0)).collect();
+        values.join(", ")
+    }
+}
+
+// precedence levels of grammar, from "and"/"or" to primitives
+fn level(expression: &Expression) -> u8 {
+    use Expression::*;
+    match expression {
+        Or(..) | And(..) => 1,
+        Equals(..) | NotEquals(..) => 2,
+        Less(..) | LessEqual(..) | More(..) | MoreEqual(..) => 3,
+        Add(..) | Sub(..) => 4,
+        Mul(..) | Div(..) => 5,
+        Negation(..) | Not(..) => 6,
+        _ => 7,
+    }
+}
+
+fn statement_start(statement: &Statement) -> usize {
+    match statement {
+        Statement::Assignment(ValueStorage::Creation(name), _) => position(name.span.start()),
+        Statement::Assignment(ValueStorage::Existing(path), _) => position(path[0].span.start()),
+        Statement::Expression(value) | Statement::Return(value) => position(value.span().start()),
+        Statement::For(init, ..) => statement_start(init),
+        Statement::IfElse(condition, ..) => position(condition.span().start()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(src: &str) -> String {
+        match format(src) {
+            Result::Ok(x) => x,
+            Result::Err(_) => panic!("parsing failed"),
+        }
+    }
+
+    #[test]
+    fn normalizes_layout() {
+        let src = "import \"common/color.octo\";
+// weights
+let w=vec3(0.2126,0.7152,0.0722);
+gpu_fun lum(c:vec3)->float{return dot(c,w); // trailing
+}
+pipeline main(color:vec4)->(vec4) with{size:vec2}{
+  let x=(color.x+1.0)*2.0;
+
+  if(!(x>1.0) and true_value){x=-x;}else{x=x/2.0;}
+    /* before end */
+  return vec4(x,x,x,1.0);}";
+        let expected = "import \"common/color.octo\";
+// weights
+let w = vec3(0.2126, 0.7152, 0.0722);
+
+gpu_fun lum(c: vec3) -> float {
+    return dot(c, w); // trailing
+}
+
+pipeline main(color: vec4) -> (vec4)
+with {
+    size: vec2,
+}
+{
+    let x = (color.x + 1.0) * 2.0;
+
+    if (!(x > 1.0) and true_value) {
+        x = -x;
+    } else {
+        x = x / 2.0;
+    }
+    /* before end */
+    return vec4(x, x, x, 1.0);
+}
+";
+        assert_eq!(formatted(src), expected);
+        assert_eq!(formatted(expected), expected);
+    }
+
+    // statements are wrapped in pipeline, so expressions are parsed in their usual place
+    fn formatted_statements(statements: &str) -> String {
+        let src = format!("pipeline main(color: vec4) -> (vec4) {{\n{}\n}}\n", statements);
+        let result = formatted(&src);
+        assert_eq!(formatted(&result), result, "formatting is not stable");
+        let start = result.find('{').unwrap() + 2;
+        let end = result.rfind('}').unwrap();
+        result[start..end].lines().map(|x| x.trim()).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn needed_parentheses_are_kept() {
+        assert_eq!(formatted_statements("let x = -(-a);"), "let x = -(-a);");
+        assert_eq!(formatted_statements("let x = a-(b-c);"), "let x = a - (b - c);");
+        assert_eq!(formatted_statements("let x = (a-b)-c;"), "let x = a - b - c;");
+        assert_eq!(formatted_statements("let x = a/(b*c);"), "let x = a / (b * c);");
+        assert_eq!(formatted_statements("let x = (a and b) or c;"), "let x = (a and b) or c;");
+        assert_eq!(formatted_statements("let x = !(a < b) == (c >= d);"), "let x = !(a < b) == c >= d;");
+        assert_eq!(formatted_statements("let x = (-a).x;"), "let x = (-a).x;");
+        assert_eq!(formatted_statements("let x = ((a));"), "let x = a;");
+    }
+
+    #[test]
+    fn shift_modes_and_tuples_are_kept() {
+        assert_eq!(
+            formatted_statements("let x = shift(color,vec2(1.0,0.0),zero)+shift(color,size);"),
+            "let x = shift(color, vec2(1.0, 0.0), zero) + shift(color, size);"
+        );
+        assert_eq!(formatted_statements("let x = scale(color,0.5);"), "let x = scale(color, 0.5);");
+        assert_eq!(formatted_statements("return (a,b+c);"), "return (a, b + c);");
+    }
+
+    #[test]
+    fn comments_inside_expressions_are_kept() {
+        assert_eq!(
+            formatted_statements("let x = a + /* half */ b;\nlet y = vec2(1.0, // first\n2.0);"),
+            "let x = a + b; /* half */\nlet y = vec2(1.0, 2.0); // first"
+        );
+        // comment in own line goes after statement it was inside of
+        assert_eq!(
+            formatted_statements("let x = a +\n    // second operand\n    b;\nreturn x;"),
+            "let x = a + b;\n// second operand\nreturn x;"
+        );
+        assert_eq!(
+            formatted_statements("if (a /* small */ < b) {\nreturn a;\n}"),
+            "if (a < b) { /* small */\nreturn a;\n}"
+        );
+    }
+}
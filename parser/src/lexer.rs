@@ -91,7 +91,7 @@ impl fmt::Display for Token {
             Less => "<".to_owned(),
             LessEqual => "<=".to_owned(),
             Let => "let".to_owned(),
-            GpuFun => "fun".to_owned(),
+            GpuFun => "gpu_fun".to_owned(),
             Pipeline => "pipeline".to_owned(),
             Import => "import".to_owned(),
             Shift => "shift".to_owned(),
@@ -104,26 +104,53 @@ impl fmt::Display for Token {
     }
 }
 
+/// Comment skipped by lexer, kept so formatter can restore it.
+/// Offsets are like token locations, end is exclusive.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Comments of whole source, in order.
+pub fn comments(src: &str) -> Result<Vec<Comment>, LexicalError> {
+    let mut lexer = Lexer::new(src);
+    while let Some(token) = lexer.next() {
+        token?;
+    }
+    Result::Ok(lexer.comments)
+}
+
 pub fn span(from: usize, to: usize) -> Sp {
     Sp::new(((from + 1) as u32).into(), ((to + 1) as u32).into())
 }
 
 pub struct Lexer<'input> {
-    _source: &'input str,
+    source: &'input str,
     chars: CharIndices<'input>,
     lookahead: Option<(usize, char)>,
     current_line: usize,
+    // position of source inside CodeMap, so spans from many files don't overlap
+    offset: usize,
+    pub comments: Vec<Comment>,
 }
 
 impl<'input> Lexer<'input> {
     pub fn new(input: &'input str) -> Self {
+        Self::with_offset(input, 0)
+    }
+
+    pub fn with_offset(input: &'input str, offset: usize) -> Self {
         let mut chars = input.char_indices();
         let first = chars.next();
         Lexer {
-            _source: input,
+            source: input,
             chars,
             lookahead: first,
             current_line: 1, // human friendly line counting (not 0-indexed :)
+            offset,
+            comments: vec![],
         }
     }
 
@@ -136,7 +163,7 @@ impl<'input> Lexer<'input> {
                     // Nothing should happen after you pop newline and before you pop anything else.
                     self.current_line += 1;
                 }
-                Some((a, b))
+                Some((a + self.offset, b))
             }
             None => None,
         }
@@ -236,7 +263,13 @@ impl<'input> Lexer<'input> {
         }
         return Result::Ok(string);
     }
-    fn remove_block_comment(&mut self) -> Result<(), LexicalError> {
+    fn add_comment(&mut self, start: usize, end: usize) {
+        let text = self.source[start - self.offset..end - self.offset].to_owned();
+        self.comments.push(Comment { start, end, text });
+    }
+
+    // returns end of comment
+    fn remove_block_comment(&mut self) -> Result<usize, LexicalError> {
         let mut opened_blocks = 1u32;
 
         let start = match self.pop() {
@@ -253,10 +286,13 @@ impl<'input> Lexer<'input> {
                 }
                 '*' => {
                     if self.test('/') {
-                        self.pop();
+                        let end = match self.pop() {
+                            None => unreachable!(),
+                            Some((x, _)) => x + 1,
+                        };
                         opened_blocks -= 1;
                         if opened_blocks == 0 {
-                            return Result::Ok(());
+                            return Result::Ok(end);
                         }
                     }
                 }
@@ -347,13 +383,23 @@ impl<'input> Iterator for Lexer<'input> {
 
                 Some((i, '/')) => match self.peek() {
                     Some('/') => {
-                        while !self.test('\n') {
-                            self.pop();
+                        let mut end = i + 1;
+                        while let Some(c) = self.peek() {
+                            if c == '\n' {
+                                break;
+                            }
+                            if let Some((x, _)) = self.pop() {
+                                end = x + c.len_utf8();
+                            }
                         }
+                        self.add_comment(i, end);
                         continue;
                     }
                     Some('*') => match self.remove_block_comment() {
-                        Result::Ok(()) => continue,
+                        Result::Ok(end) => {
+                            self.add_comment(i, end);
+                            continue;
+                        }
                         Result::Err(er) => return err!(er),
                     },
                     _ => return ok!(Slash, i), // next character is whatever so we emit normal slash
@@ -10,6 +10,7 @@ use codespan_reporting::Diagnostic;
 use lalrpop_util::ParseError;
 
 pub mod ast;
+pub mod format;
 pub mod lexer;
 
 lalrpop_mod!(pub grammar);
@@ -18,22 +19,44 @@ type ParseErr = ParseError<usize, lexer::Token, errors::LexicalError>;
 
 pub struct FailedParsing {
     pub errors: Vec<ParseErr>,
+    /// Program without statements skipped by error recovery, if parsing reached the end of file.
+    pub program: Option<ast::Program>,
 }
 
-pub fn parse(src: &str, lex: bool) -> Result<ast::Pipeline, FailedParsing> {
+pub fn parse(src: &str, lex: bool) -> Result<ast::Program, FailedParsing> {
+    parse_with_offset(src, 0, lex)
+}
+
+/// Parses source placed at `offset` in CodeMap (file start index - 1).
+pub fn parse_with_offset(src: &str, offset: usize, lex: bool) -> Result<ast::Program, FailedParsing> {
     if lex {
-        for lexeme in lexer::Lexer::new(src) {
+        for lexeme in lexer::Lexer::with_offset(src, offset) {
             print!("{:?}, ", lexeme);
         }
         println!();
     }
-    let lexer = lexer::Lexer::new(src);
-    let result = grammar::PipelineParser::new().parse(lexer);
+    let lexer = lexer::Lexer::with_offset(src, offset);
+    let mut recovered = vec![];
+    let result = grammar::ProgramParser::new().parse(&mut recovered, lexer);
+    let mut errors: Vec<ParseErr> = recovered.into_iter().map(|x| x.error).collect();
     match result {
-        Result::Ok(ast) => Result::Ok(ast),
-        Result::Err(error) => Result::Err(FailedParsing {
-            errors: vec![error],
-        }),
+        Result::Ok(ast) => {
+            if errors.is_empty() {
+                Result::Ok(ast)
+            } else {
+                Result::Err(FailedParsing {
+                    errors,
+                    program: Some(ast),
+                })
+            }
+        }
+        Result::Err(error) => {
+            errors.push(error);
+            Result::Err(FailedParsing {
+                errors,
+                program: None,
+            })
+        }
     }
 }
 
@@ -56,7 +79,13 @@ macro_rules! error {
 
 impl<'a> From<ErrWrap<'a>> for Diagnostic {
     fn from(w: ErrWrap) -> Diagnostic {
-        match &w.err {
+        let code = match &w.err {
+            ParseError::InvalidToken { .. } => "E0101",
+            ParseError::UnrecognizedToken { .. } => "E0102",
+            ParseError::ExtraToken { .. } => "E0103",
+            ParseError::User { error } => error.code(),
+        };
+        let diagnostic = match &w.err {
             ParseError::UnrecognizedToken {
                 token,
                 expected: exp,
@@ -111,7 +140,8 @@ impl<'a> From<ErrWrap<'a>> for Diagnostic {
                     *span
                 ),
             },
-        }
+        };
+        diagnostic.with_code(code)
     }
 }
 
@@ -131,25 +161,37 @@ mod tests {
 
     fn fast_expression(code: &str) -> bool {
         let lexer = lexer::Lexer::new(code);
-        let result = grammar::ExpressionParser::new().parse(lexer);
+        let result = grammar::ExpressionParser::new().parse(&mut vec![], lexer);
         result.is_ok()
     }
 
     fn fast_statement(code: &str) -> bool {
         let lexer = lexer::Lexer::new(code);
-        let result = grammar::StatementParser::new().parse(lexer);
+        let result = grammar::StatementParser::new().parse(&mut vec![], lexer);
         result.is_ok()
     }
 
     fn fast_block(code: &str) -> bool {
         let lexer = lexer::Lexer::new(code);
-        let result = grammar::BlockParser::new().parse(lexer);
+        let result = grammar::BlockParser::new().parse(&mut vec![], lexer);
         result.is_ok()
     }
 
     fn fast_op(code: &str) -> bool {
         let lexer = lexer::Lexer::new(code);
-        let result = grammar::OpParser::new().parse(lexer);
+        let result = grammar::OpParser::new().parse(&mut vec![], lexer);
+        result.is_ok()
+    }
+
+    fn fast_program(code: &str) -> bool {
+        let lexer = lexer::Lexer::new(code);
+        let result = grammar::ProgramParser::new().parse(&mut vec![], lexer);
+        result.is_ok()
+    }
+
+    fn fast_function(code: &str) -> bool {
+        let lexer = lexer::Lexer::new(code);
+        let result = grammar::GpuFunctionParser::new().parse(&mut vec![], lexer);
         result.is_ok()
     }
 
@@ -166,6 +208,7 @@ mod tests {
         assert!(fast_statement("let p = 2+2"));
         assert!(fast_statement("dwa = 2 + 2"));
         assert!(fast_statement("return 3.0"));
+        assert!(fast_statement("return (a, 1.0)"));
         assert!(fast_statement("dwa = 2 + 2"));
 
         // well, it might be valid expression
@@ -176,6 +219,14 @@ mod tests {
         assert!(fast_block("{if(i){}}"));
         assert!(fast_block("{if(i){}else {}}"));
 
+        assert!(fast_function("gpu_fun f(a: vec3, b: float) -> vec3 {return a*b;}"));
+        assert!(fast_function("gpu_fun f() -> float {return 1.0;}"));
+        assert!(!fast_function("gpu_fun f(a: vec3) {return a;}"));
+
+        assert!(fast_program("import \"common/color.octo\"; let c = 2.0; gpu_fun f() -> float {return c;}"));
+        assert!(fast_program("import \"a.octo\"; pipeline main() -> (vec4) {} import \"b.octo\";"));
+        assert!(!fast_program("pipeline a() -> (vec4) {} pipeline b() -> (vec4) {}"));
+
         //
         //        assert!(result.is_ok());
         //        let result = result.unwrap();
@@ -186,4 +237,20 @@ mod tests {
         //            _ => assert!(false),
         //        }
     }
+
+    #[test]
+    fn recovery_reports_every_error() {
+        let code = "gpu_fun f(a: float) -> float { let b = ; let c = a * 2.0; c = ) ; return c; }
+            pipeline main() -> (vec4) { let x = vec4(1.0, 1.0, 1.0, 1.0) return x; }";
+        let failed = match parse(code, false) {
+            Result::Ok(_) => panic!("parsing should fail"),
+            Result::Err(x) => x,
+        };
+        assert_eq!(failed.errors.len(), 3);
+
+        // statements between errors are kept
+        let program = failed.program.unwrap();
+        assert_eq!(program.functions[0].block.statements.len(), 2);
+        assert!(program.pipeline.is_some());
+    }
 }